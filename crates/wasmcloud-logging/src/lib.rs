@@ -24,18 +24,73 @@ use wasmcloud_provider_core::{deserialize, serialize};
 
 use log::Log;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
-use std::fs::{File, OpenOptions};
-use std::sync::{Arc, RwLock};
+use std::fs::OpenOptions;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::time::{Duration, Instant};
 
-use simplelog::{Config, LevelFilter, WriteLogger};
+use simplelog::{Config, ConfigBuilder, LevelFilter, WriteLogger};
+
+mod memory;
+mod otlp;
+
+use memory::LogSink;
+pub use memory::{MemoryLog, RegisteredMemoryLog};
+use otlp::OtlpExporter;
 
 #[cfg(not(feature = "static_plugin"))]
 capability_provider!(LoggingProvider, LoggingProvider::new);
 
 pub const LOG_PATH_KEY: &str = "LOG_PATH";
 
+/// The prefix of keys naming extra log files, e.g. `LOG_PATH_ACCESS=/var/log/access.log`.
+/// Records whose target matches the rest of the key, ignoring case, are written to that file
+/// instead of the one at [`LOG_PATH_KEY`]. The file is created if it doesn't exist.
+pub const LOG_PATH_STREAM_PREFIX: &str = "LOG_PATH_";
+
+/// The name of a [`RegisteredMemoryLog`] to write to instead of the file at [`LOG_PATH_KEY`].
+pub const LOG_MEMORY_KEY: &str = "LOG_MEMORY";
+
+/// When set to "true", consecutive identical records are collapsed into a single
+/// "repeated N times" summary.
+pub const LOG_DEDUP_KEY: &str = "LOG_DEDUP";
+
+/// The window, in milliseconds, after which a pending "repeated N times" summary is written
+/// even if the actor keeps sending the same record.
+pub const LOG_DEDUP_WINDOW_KEY: &str = "LOG_DEDUP_WINDOW_MS";
+
+/// An OTLP/HTTP logs endpoint, e.g. `http://collector:4318/v1/logs`, to export records to in
+/// addition to the log file.
+pub const LOG_OTLP_ENDPOINT_KEY: &str = "LOG_OTLP_ENDPOINT";
+
+/// When set to "true" alongside [`LOG_OTLP_ENDPOINT_KEY`], records are only exported and no log
+/// file is written, so `kubectl logs` shows nothing.
+pub const LOG_OTLP_ONLY_KEY: &str = "LOG_OTLP_ONLY";
+
+/// The `strftime` style format of the timestamp on each line, e.g. `%Y-%m-%dT%H:%M:%S%.3f`.
+/// Defaults to `%H:%M:%S`.
+pub const LOG_TIME_FORMAT_KEY: &str = "LOG_TIME_FORMAT";
+
+/// The timezone timestamps are given in, `utc` (the default) or `local`.
+pub const LOG_TIMEZONE_KEY: &str = "LOG_TIMEZONE";
+
+/// Keeps only one in every N `info`, `debug` and `trace` records, e.g. `10` keeps a tenth of
+/// them. `error` and `warn` records are always kept. Sampling deliberately loses records, so
+/// it is meant for actors too chatty to log in full. Everything is kept when unset or `1`.
+pub const LOG_SAMPLE_RATE_KEY: &str = "LOG_SAMPLE_RATE";
+
+lazy_static::lazy_static! {
+    static ref TIME_FORMATS: Mutex<HashSet<&'static str>> = Mutex::new(HashSet::new());
+}
+
+const DEFAULT_DEDUP_WINDOW: Duration = Duration::from_secs(5);
+
+/// How often pending summaries are checked so they are not delayed indefinitely when an actor
+/// goes quiet.
+const DEDUP_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
 /// Origin of messages coming from wasmcloud host
 const SYSTEM_ACTOR: &str = "system";
 
@@ -48,12 +103,142 @@ const INFO: &str = "info";
 const DEBUG: &str = "debug";
 const TRACE: &str = "trace";
 
+type OutputMap = HashMap<String, ActorLogger>;
+
+/// The last record written for an actor along with how many identical records have been
+/// suppressed since.
+struct LastRecord {
+    level: log::Level,
+    target: String,
+    text: String,
+    repeated: usize,
+    since: Instant,
+}
+
+struct Dedup {
+    window: Duration,
+    last: Option<LastRecord>,
+}
+
+/// The log output of a single actor.
+struct ActorLogger {
+    logger: Option<Box<WriteLogger<LogSink>>>,
+    /// Named log files keyed by the lowercased target they take records for.
+    streams: HashMap<String, Box<WriteLogger<LogSink>>>,
+    otlp: Option<OtlpExporter>,
+    dedup: Option<Mutex<Dedup>>,
+    /// Keep one in this many sampled records, see [`LOG_SAMPLE_RATE_KEY`].
+    sample_rate: u64,
+    /// How many sampled records have been seen.
+    sampled: AtomicU64,
+}
+
+impl ActorLogger {
+    fn write(&self, actor: &str, level: log::Level, target: &str, text: &str) {
+        if self.sample_rate > 1 && level > log::Level::Warn {
+            // Deterministic rather than random, so a steady stream keeps exactly 1 in N
+            let seen = self.sampled.fetch_add(1, Ordering::Relaxed);
+            if seen % self.sample_rate != 0 {
+                return;
+            }
+        }
+        let dedup = match &self.dedup {
+            Some(dedup) => dedup,
+            None => return self.emit(actor, level, target, text),
+        };
+        let mut dedup = dedup.lock().unwrap();
+        let window = dedup.window;
+        if let Some(last) = dedup.last.as_mut() {
+            if last.level == level && last.target == target && last.text == text {
+                last.repeated += 1;
+                if last.since.elapsed() >= window {
+                    self.emit_repeated(actor, last);
+                }
+                return;
+            }
+            if last.repeated > 0 {
+                self.emit_repeated(actor, last);
+            }
+        }
+        self.emit(actor, level, target, text);
+        dedup.last = Some(LastRecord {
+            level,
+            target: target.to_owned(),
+            text: text.to_owned(),
+            repeated: 0,
+            since: Instant::now(),
+        });
+    }
+
+    /// Writes the pending summary if it has been held back for longer than the window.
+    fn flush_expired(&self, actor: &str) {
+        if let Some(dedup) = &self.dedup {
+            let mut dedup = dedup.lock().unwrap();
+            let window = dedup.window;
+            if let Some(last) = dedup.last.as_mut() {
+                if last.repeated > 0 && last.since.elapsed() >= window {
+                    self.emit_repeated(actor, last);
+                }
+            }
+        }
+    }
+
+    /// The logger records with the given target go to.
+    fn logger_for(&self, target: &str) -> Option<&WriteLogger<LogSink>> {
+        self.streams
+            .get(&target.to_lowercase())
+            .or_else(|| self.logger.as_ref())
+            .map(Box::as_ref)
+    }
+
+    fn emit(&self, actor: &str, level: log::Level, target: &str, text: &str) {
+        if let Some(logger) = self.logger_for(target) {
+            logger.log(
+                &log::Record::builder()
+                    .args(format_args!("[{}] {}", actor, text))
+                    .level(level)
+                    .target(target)
+                    .build(),
+            );
+        }
+        if let Some(otlp) = &self.otlp {
+            otlp.export(actor, level, target, text);
+        }
+    }
+
+    fn emit_repeated(&self, actor: &str, last: &mut LastRecord) {
+        if let Some(logger) = self.logger_for(&last.target) {
+            logger.log(
+                &log::Record::builder()
+                    .args(format_args!(
+                        "[{}] last message repeated {} times",
+                        actor, last.repeated
+                    ))
+                    .level(last.level)
+                    .target(&last.target)
+                    .build(),
+            );
+        }
+        if let Some(otlp) = &self.otlp {
+            otlp.export(
+                actor,
+                last.level,
+                &last.target,
+                &format!("last message repeated {} times", last.repeated),
+            );
+        }
+        last.repeated = 0;
+        last.since = Instant::now();
+    }
+}
+
 /// LoggingProvider provides an implementation of the wasmcloud:logging capability
 /// that keeps separate log output for each actor.
 #[derive(Clone)]
 pub struct LoggingProvider {
     dispatcher: Arc<RwLock<Box<dyn Dispatcher>>>,
-    output_map: Arc<RwLock<HashMap<String, Box<WriteLogger<File>>>>>,
+    output_map: Arc<RwLock<OutputMap>>,
+    flusher_started: Arc<AtomicBool>,
 }
 
 impl Default for LoggingProvider {
@@ -61,6 +246,7 @@ impl Default for LoggingProvider {
         LoggingProvider {
             dispatcher: Arc::new(RwLock::new(Box::new(NullDispatcher::new()))),
             output_map: Arc::new(RwLock::new(HashMap::new())),
+            flusher_started: Arc::new(AtomicBool::new(false)),
         }
     }
 }
@@ -74,17 +260,155 @@ impl LoggingProvider {
         &self,
         config: CapabilityConfiguration,
     ) -> Result<Vec<u8>, Box<dyn Error + Sync + Send>> {
-        let path = config
+        let otlp = config
             .values
-            .get(LOG_PATH_KEY)
-            .ok_or("log file path was unspecified")?;
+            .get(LOG_OTLP_ENDPOINT_KEY)
+            .map(|endpoint| OtlpExporter::new(endpoint.clone()));
+        let otlp_only = otlp.is_some()
+            && config.values.get(LOG_OTLP_ONLY_KEY).map(String::as_str) == Some("true");
 
-        let file = OpenOptions::new().write(true).open(path)?;
-        let logger = WriteLogger::new(LevelFilter::Trace, Config::default(), file);
+        let dedup = match config.values.get(LOG_DEDUP_KEY).map(String::as_str) {
+            Some("true") => {
+                let window = match config.values.get(LOG_DEDUP_WINDOW_KEY) {
+                    Some(ms) => Duration::from_millis(ms.parse().map_err(|e| {
+                        format!("invalid {} value {:?}: {}", LOG_DEDUP_WINDOW_KEY, ms, e)
+                    })?),
+                    None => DEFAULT_DEDUP_WINDOW,
+                };
+                self.start_flusher();
+                Some(Mutex::new(Dedup { window, last: None }))
+            }
+            _ => None,
+        };
+
+        let sample_rate = match config.values.get(LOG_SAMPLE_RATE_KEY) {
+            Some(rate) => match rate.parse::<u64>() {
+                Ok(rate) if rate > 0 => rate,
+                _ => {
+                    return Err(format!(
+                        "invalid {} value {:?}: expected a whole number of 1 or more",
+                        LOG_SAMPLE_RATE_KEY, rate
+                    )
+                    .into())
+                }
+            },
+            None => 1,
+        };
+
+        let mut streams = HashMap::new();
+        let logger = if otlp_only {
+            None
+        } else {
+            for (key, path) in config.values.iter() {
+                let name = match key.strip_prefix(LOG_PATH_STREAM_PREFIX) {
+                    Some(name) if !name.is_empty() => name.to_lowercase(),
+                    _ => continue,
+                };
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| format!("unable to open {} file {}: {}", key, path, e))?;
+                streams.insert(
+                    name,
+                    WriteLogger::new(
+                        LevelFilter::Trace,
+                        logger_config(&config.values)?,
+                        LogSink::File(file),
+                    ),
+                );
+            }
+            let sink = match config.values.get(LOG_MEMORY_KEY) {
+                Some(name) => LogSink::Memory(
+                    memory::lookup(name).ok_or(format!("no memory log named {}", name))?,
+                ),
+                None => {
+                    let path = config
+                        .values
+                        .get(LOG_PATH_KEY)
+                        .ok_or("log file path was unspecified")?;
+                    // Appending keeps writes at the end if the kubelet truncates the file to
+                    // free disk space
+                    LogSink::File(OpenOptions::new().append(true).open(path)?)
+                }
+            };
+            Some(WriteLogger::new(
+                LevelFilter::Trace,
+                logger_config(&config.values)?,
+                sink,
+            ))
+        };
         let mut output_map = self.output_map.write().unwrap();
-        output_map.insert(config.module, logger);
+        output_map.insert(
+            config.module,
+            ActorLogger {
+                logger,
+                streams,
+                otlp,
+                dedup,
+                sample_rate,
+                sampled: AtomicU64::new(0),
+            },
+        );
         Ok(vec![])
     }
+
+    /// Starts the background thread that writes out held back "repeated N times" summaries.
+    /// The thread exits once the provider has been dropped.
+    fn start_flusher(&self) {
+        if self.flusher_started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let output_map: Weak<RwLock<OutputMap>> = Arc::downgrade(&self.output_map);
+        std::thread::spawn(move || loop {
+            std::thread::sleep(DEDUP_FLUSH_INTERVAL);
+            let output_map = match output_map.upgrade() {
+                Some(output_map) => output_map,
+                None => break,
+            };
+            let output_map = output_map.read().unwrap();
+            for (actor, logger) in output_map.iter() {
+                logger.flush_expired(actor);
+            }
+        });
+    }
+}
+
+/// Builds the log line format from the link values. Without any, this is the same as
+/// `Config::default()`.
+fn logger_config(values: &HashMap<String, String>) -> Result<Config, Box<dyn Error + Sync + Send>> {
+    let mut builder = ConfigBuilder::new();
+    if let Some(format) = values.get(LOG_TIME_FORMAT_KEY) {
+        builder.set_time_format_str(intern_time_format(format));
+    }
+    match values.get(LOG_TIMEZONE_KEY).map(String::as_str) {
+        None | Some("utc") => {}
+        Some("local") => {
+            builder.set_time_to_local(true);
+        }
+        Some(other) => {
+            return Err(format!(
+                "invalid {} value {:?}: expected \"utc\" or \"local\"",
+                LOG_TIMEZONE_KEY, other
+            )
+            .into())
+        }
+    }
+    Ok(builder.build())
+}
+
+/// `simplelog` only takes a `'static` time format. Each distinct format is leaked once and
+/// reused, so relinking actors doesn't leak memory every time.
+fn intern_time_format(format: &str) -> &'static str {
+    let mut formats = TIME_FORMATS.lock().unwrap_or_else(|e| e.into_inner());
+    match formats.get(format) {
+        Some(interned) => interned,
+        None => {
+            let interned: &'static str = Box::leak(format.to_owned().into_boxed_str());
+            formats.insert(interned);
+            interned
+        }
+    }
 }
 
 impl CapabilityProvider for LoggingProvider {
@@ -134,13 +458,7 @@ impl CapabilityProvider for LoggingProvider {
                 let logger = output_map
                     .get(actor)
                     .ok_or(format!("Unable to find logger for actor {}", actor))?;
-                logger.log(
-                    &log::Record::builder()
-                        .args(format_args!("[{}] {}", actor, log_msg.text))
-                        .level(level)
-                        .target(&log_msg.target)
-                        .build(),
-                );
+                logger.write(actor, level, &log_msg.target, &log_msg.text);
                 Ok(vec![])
             }
             _ => Err(format!("Unknown operation: {}", op).into()),
@@ -150,3 +468,240 @@ impl CapabilityProvider for LoggingProvider {
     // No cleanup needed on stop
     fn stop(&self) {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACTOR: &str = "Mactor";
+
+    /// Configures a provider for [`ACTOR`] writing to a fresh memory log, plus `values`.
+    fn configure(values: &[(&str, &str)]) -> (LoggingProvider, RegisteredMemoryLog) {
+        let memory_log = RegisteredMemoryLog::new(64 * 1024);
+        let mut config_values: HashMap<String, String> = values
+            .iter()
+            .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+            .collect();
+        config_values.insert(LOG_MEMORY_KEY.to_owned(), memory_log.name().to_owned());
+        let provider = LoggingProvider::new();
+        provider
+            .configure(CapabilityConfiguration {
+                module: ACTOR.to_owned(),
+                values: config_values,
+            })
+            .expect("configure failed");
+        (provider, memory_log)
+    }
+
+    fn write(provider: &LoggingProvider, level: log::Level, target: &str, text: &str) {
+        let output_map = provider.output_map.read().unwrap();
+        output_map[ACTOR].write(ACTOR, level, target, text);
+    }
+
+    fn contents(memory_log: &RegisteredMemoryLog) -> String {
+        let mut buf = vec![0; 64 * 1024];
+        let (_, read) = memory_log.log().read_at(0, &mut buf);
+        String::from_utf8_lossy(&buf[..read]).into_owned()
+    }
+
+    #[test]
+    fn dedup_collapses_repeated_records() {
+        let (provider, memory_log) =
+            configure(&[(LOG_DEDUP_KEY, "true"), (LOG_DEDUP_WINDOW_KEY, "60000")]);
+        for _ in 0..3 {
+            write(&provider, log::Level::Info, "", "hello");
+        }
+        write(&provider, log::Level::Info, "", "goodbye");
+
+        let log = contents(&memory_log);
+        assert_eq!(log.matches("hello").count(), 1, "got: {}", log);
+        assert!(
+            log.contains("last message repeated 2 times"),
+            "got: {}",
+            log
+        );
+        let summary = log.find("repeated 2 times").unwrap();
+        assert!(log.find("goodbye").unwrap() > summary, "got: {}", log);
+    }
+
+    #[test]
+    fn dedup_keeps_records_differing_in_level_or_target() {
+        let (provider, memory_log) = configure(&[(LOG_DEDUP_KEY, "true")]);
+        write(&provider, log::Level::Info, "", "hello");
+        write(&provider, log::Level::Warn, "", "hello");
+        write(&provider, log::Level::Warn, "other", "hello");
+
+        let log = contents(&memory_log);
+        assert_eq!(log.matches("hello").count(), 3, "got: {}", log);
+        assert!(!log.contains("repeated"), "got: {}", log);
+    }
+
+    #[test]
+    fn dedup_summary_flushed_after_window() {
+        let (provider, memory_log) =
+            configure(&[(LOG_DEDUP_KEY, "true"), (LOG_DEDUP_WINDOW_KEY, "50")]);
+        write(&provider, log::Level::Info, "", "hello");
+        write(&provider, log::Level::Info, "", "hello");
+        assert!(!contents(&memory_log).contains("repeated"));
+
+        std::thread::sleep(Duration::from_millis(100));
+        provider.output_map.read().unwrap()[ACTOR].flush_expired(ACTOR);
+        let log = contents(&memory_log);
+        assert!(
+            log.contains("last message repeated 1 times"),
+            "got: {}",
+            log
+        );
+
+        // Nothing is pending after the flush, so a second one writes nothing
+        provider.output_map.read().unwrap()[ACTOR].flush_expired(ACTOR);
+        assert_eq!(contents(&memory_log).matches("repeated").count(), 1);
+    }
+
+    #[test]
+    fn dedup_summary_written_when_window_expires_mid_run() {
+        let (provider, memory_log) =
+            configure(&[(LOG_DEDUP_KEY, "true"), (LOG_DEDUP_WINDOW_KEY, "0")]);
+        write(&provider, log::Level::Info, "", "hello");
+        write(&provider, log::Level::Info, "", "hello");
+        let log = contents(&memory_log);
+        assert!(
+            log.contains("last message repeated 1 times"),
+            "got: {}",
+            log
+        );
+    }
+
+    #[test]
+    fn dedup_rejects_invalid_window() {
+        let memory_log = RegisteredMemoryLog::new(1024);
+        let values = vec![
+            (LOG_DEDUP_KEY.to_owned(), "true".to_owned()),
+            (LOG_DEDUP_WINDOW_KEY.to_owned(), "soon".to_owned()),
+            (LOG_MEMORY_KEY.to_owned(), memory_log.name().to_owned()),
+        ];
+        let result = LoggingProvider::new().configure(CapabilityConfiguration {
+            module: ACTOR.to_owned(),
+            values: values.into_iter().collect(),
+        });
+        assert!(result.is_err());
+    }
+
+    /// A collector endpoint nothing listens on; exports to it fail on the export thread only.
+    const UNREACHABLE_OTLP_ENDPOINT: &str = "http://127.0.0.1:9/v1/logs";
+
+    #[test]
+    fn otlp_only_writes_no_log_file() {
+        let provider = LoggingProvider::new();
+        // No LOG_PATH, which would otherwise be required
+        let values = vec![
+            (
+                LOG_OTLP_ENDPOINT_KEY.to_owned(),
+                UNREACHABLE_OTLP_ENDPOINT.to_owned(),
+            ),
+            (LOG_OTLP_ONLY_KEY.to_owned(), "true".to_owned()),
+            (
+                LOG_PATH_STREAM_PREFIX.to_owned() + "ACCESS",
+                "/nonexistent/access.log".to_owned(),
+            ),
+        ];
+        provider
+            .configure(CapabilityConfiguration {
+                module: ACTOR.to_owned(),
+                values: values.into_iter().collect(),
+            })
+            .unwrap();
+        let output_map = provider.output_map.read().unwrap();
+        let logger = &output_map[ACTOR];
+        assert!(logger.logger.is_none());
+        assert!(logger.streams.is_empty());
+        assert!(logger.otlp.is_some());
+        // Writing only exports
+        logger.write(ACTOR, log::Level::Info, "access", "hello");
+    }
+
+    #[test]
+    fn otlp_exports_alongside_the_log() {
+        let (provider, memory_log) = configure(&[
+            (LOG_OTLP_ENDPOINT_KEY, UNREACHABLE_OTLP_ENDPOINT),
+            (LOG_OTLP_ONLY_KEY, "false"),
+        ]);
+        {
+            let output_map = provider.output_map.read().unwrap();
+            assert!(output_map[ACTOR].otlp.is_some());
+        }
+        write(&provider, log::Level::Info, "", "hello");
+        assert!(contents(&memory_log).contains("hello"));
+    }
+
+    #[test]
+    fn otlp_only_needs_an_endpoint() {
+        let (provider, memory_log) = configure(&[(LOG_OTLP_ONLY_KEY, "true")]);
+        {
+            let output_map = provider.output_map.read().unwrap();
+            assert!(output_map[ACTOR].otlp.is_none());
+        }
+        write(&provider, log::Level::Info, "", "hello");
+        assert!(contents(&memory_log).contains("hello"));
+    }
+
+    #[test]
+    fn time_format_sets_the_line_prefix() {
+        // Without any specifiers the format is printed as is
+        let (provider, memory_log) = configure(&[(LOG_TIME_FORMAT_KEY, "stamp")]);
+        write(&provider, log::Level::Info, "", "hello");
+        let log = contents(&memory_log);
+        assert!(log.starts_with("stamp"), "got: {}", log);
+        assert!(log.contains("hello"), "got: {}", log);
+    }
+
+    #[test]
+    fn timezone_must_be_utc_or_local() {
+        for timezone in &["utc", "local"] {
+            let values = vec![(LOG_TIMEZONE_KEY.to_owned(), (*timezone).to_owned())];
+            assert!(logger_config(&values.into_iter().collect()).is_ok());
+        }
+        let values = vec![(LOG_TIMEZONE_KEY.to_owned(), "Europe/Paris".to_owned())];
+        let err = logger_config(&values.into_iter().collect()).unwrap_err();
+        assert!(err.to_string().contains(LOG_TIMEZONE_KEY), "got: {}", err);
+    }
+
+    #[test]
+    fn time_formats_are_interned() {
+        let first = intern_time_format("%H:%M:%S%.6f");
+        let second = intern_time_format(&String::from("%H:%M:%S%.6f"));
+        assert!(std::ptr::eq(first, second));
+        assert!(!std::ptr::eq(first, intern_time_format("%H:%M")));
+    }
+
+    #[test]
+    fn stream_records_go_to_their_own_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let access = dir.path().join("access.log");
+        let access_key = LOG_PATH_STREAM_PREFIX.to_owned() + "ACCESS";
+        let (provider, memory_log) = configure(&[(&access_key, access.to_str().unwrap())]);
+        write(&provider, log::Level::Info, "access", "GET /");
+        // Targets are matched regardless of case
+        write(&provider, log::Level::Info, "Access", "GET /health");
+        write(&provider, log::Level::Info, "app", "started");
+
+        let stream = std::fs::read_to_string(&access).unwrap();
+        assert!(stream.contains("GET /"), "got: {}", stream);
+        assert!(stream.contains("GET /health"), "got: {}", stream);
+        assert!(!stream.contains("started"), "got: {}", stream);
+        let log = contents(&memory_log);
+        assert!(log.contains("started"), "got: {}", log);
+        assert!(!log.contains("GET"), "got: {}", log);
+    }
+
+    #[test]
+    fn stream_without_a_name_is_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let unnamed = dir.path().join("unnamed.log");
+        let (provider, memory_log) =
+            configure(&[(LOG_PATH_STREAM_PREFIX, unnamed.to_str().unwrap())]);
+        write(&provider, log::Level::Info, "", "hello");
+        assert!(!unnamed.exists());
+        assert!(contents(&memory_log).contains("hello"));
+    }
+}
@@ -0,0 +1,155 @@
+//! Bounded in-memory log storage for nodes without a writable disk.
+//!
+//! The host registers a [`MemoryLog`] for an actor and passes its name to the provider in the
+//! [`LOG_MEMORY_KEY`](crate::LOG_MEMORY_KEY) link value. Both live in the same process, so the
+//! registry is what connects them. Anything kept in memory is lost when the process exits.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use lazy_static::lazy_static;
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+lazy_static! {
+    static ref REGISTRY: RwLock<HashMap<String, Arc<MemoryLog>>> = RwLock::new(HashMap::new());
+}
+
+/// A log kept in a ring buffer holding at most `capacity` bytes. When full, the oldest bytes
+/// are dropped.
+///
+/// Positions count every byte ever written, so readers keep a stable cursor while old data
+/// is discarded underneath them.
+pub struct MemoryLog {
+    capacity: usize,
+    ring: Mutex<Ring>,
+}
+
+struct Ring {
+    data: VecDeque<u8>,
+    /// The position of the first byte in `data`.
+    start: u64,
+}
+
+impl MemoryLog {
+    fn new(capacity: usize) -> Self {
+        MemoryLog {
+            capacity: capacity.max(1),
+            ring: Mutex::new(Ring {
+                data: VecDeque::new(),
+                start: 0,
+            }),
+        }
+    }
+
+    /// Appends to the log, dropping the oldest bytes to stay within capacity.
+    pub fn append(&self, bytes: &[u8]) {
+        let mut ring = self.ring.lock().unwrap_or_else(|e| e.into_inner());
+        // Only the tail of an oversized write can be kept anyway
+        let bytes = &bytes[bytes.len().saturating_sub(self.capacity)..];
+        let overflow = (ring.data.len() + bytes.len()).saturating_sub(self.capacity);
+        ring.data.drain(..overflow);
+        ring.start += overflow as u64;
+        ring.data.extend(bytes);
+    }
+
+    /// The position just past the last byte written.
+    pub fn end(&self) -> u64 {
+        let ring = self.ring.lock().unwrap_or_else(|e| e.into_inner());
+        ring.start + ring.data.len() as u64
+    }
+
+    /// Copies bytes from `pos` into `buf`. Returns the position the bytes were read from, which
+    /// is later than `pos` if that data has already been dropped, and how many were read.
+    pub fn read_at(&self, pos: u64, buf: &mut [u8]) -> (u64, usize) {
+        let ring = self.ring.lock().unwrap_or_else(|e| e.into_inner());
+        let pos = pos.max(ring.start);
+        let offset = (pos - ring.start) as usize;
+        if offset >= ring.data.len() {
+            return (pos, 0);
+        }
+        let read = buf.len().min(ring.data.len() - offset);
+        for (slot, byte) in buf[..read]
+            .iter_mut()
+            .zip(ring.data.range(offset..offset + read))
+        {
+            *slot = *byte;
+        }
+        (pos, read)
+    }
+}
+
+/// A [`MemoryLog`] registered under a unique name. It is unregistered when dropped, though
+/// the log itself lives on while anything still holds it.
+pub struct RegisteredMemoryLog {
+    name: String,
+    log: Arc<MemoryLog>,
+}
+
+impl RegisteredMemoryLog {
+    /// Creates and registers a log holding at most `capacity` bytes.
+    pub fn new(capacity: usize) -> Self {
+        let name = format!("memory-log-{}", NEXT_ID.fetch_add(1, Ordering::SeqCst));
+        let log = Arc::new(MemoryLog::new(capacity));
+        REGISTRY
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(name.clone(), log.clone());
+        RegisteredMemoryLog { name, log }
+    }
+
+    /// The name to pass in the [`LOG_MEMORY_KEY`](crate::LOG_MEMORY_KEY) link value.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The registered log.
+    pub fn log(&self) -> &Arc<MemoryLog> {
+        &self.log
+    }
+}
+
+impl Drop for RegisteredMemoryLog {
+    fn drop(&mut self) {
+        REGISTRY
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.name);
+    }
+}
+
+/// Looks up a registered log by name.
+pub(crate) fn lookup(name: &str) -> Option<Arc<MemoryLog>> {
+    REGISTRY
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(name)
+        .cloned()
+}
+
+/// Where an actor's log records are written.
+pub(crate) enum LogSink {
+    File(std::fs::File),
+    Memory(Arc<MemoryLog>),
+}
+
+impl Write for LogSink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            LogSink::File(file) => file.write(buf),
+            LogSink::Memory(log) => {
+                log.append(buf);
+                Ok(buf.len())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            LogSink::File(file) => file.flush(),
+            LogSink::Memory(_) => Ok(()),
+        }
+    }
+}
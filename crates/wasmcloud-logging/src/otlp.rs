@@ -0,0 +1,219 @@
+//! Export of log records to an OpenTelemetry collector using OTLP over HTTP with JSON encoding.
+
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+
+/// The most records sent in one export request.
+const MAX_BATCH_SIZE: usize = 512;
+
+/// How long records are held waiting for a batch to fill up.
+const BATCH_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How many times an export is attempted before its batch is dropped.
+const MAX_ATTEMPTS: u32 = 3;
+
+/// The wait before the first retry, doubled for every one after.
+const INITIAL_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// The instrumentation scope records are reported under.
+const SCOPE_NAME: &str = "wasmcloud:logging";
+
+struct OtlpRecord {
+    time: SystemTime,
+    level: log::Level,
+    actor: String,
+    target: String,
+    text: String,
+}
+
+/// Sends an actor's log records to an OTLP/HTTP logs endpoint, such as
+/// `http://collector:4318/v1/logs`.
+///
+/// Records are batched on a background thread so the actor is never held up by the collector.
+/// A batch that still fails after [`MAX_ATTEMPTS`] is dropped. The thread exits once the
+/// exporter is dropped, after sending what it holds.
+pub(crate) struct OtlpExporter {
+    sender: Sender<OtlpRecord>,
+}
+
+impl OtlpExporter {
+    pub(crate) fn new(endpoint: String) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || export_loop(&endpoint, receiver));
+        OtlpExporter { sender }
+    }
+
+    pub(crate) fn export(&self, actor: &str, level: log::Level, target: &str, text: &str) {
+        // The send only fails if the export thread is gone, in which case there is nowhere to
+        // report it to anyway
+        let _ = self.sender.send(OtlpRecord {
+            time: SystemTime::now(),
+            level,
+            actor: actor.to_owned(),
+            target: target.to_owned(),
+            text: text.to_owned(),
+        });
+    }
+}
+
+fn export_loop(endpoint: &str, receiver: Receiver<OtlpRecord>) {
+    let mut batch = Vec::new();
+    let mut deadline = Instant::now() + BATCH_TIMEOUT;
+    loop {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let disconnected = match receiver.recv_timeout(timeout) {
+            Ok(record) => {
+                batch.push(record);
+                if batch.len() < MAX_BATCH_SIZE {
+                    continue;
+                }
+                false
+            }
+            Err(RecvTimeoutError::Timeout) => false,
+            Err(RecvTimeoutError::Disconnected) => true,
+        };
+        if !batch.is_empty() {
+            send_batch(endpoint, &batch);
+            batch.clear();
+        }
+        if disconnected {
+            break;
+        }
+        deadline = Instant::now() + BATCH_TIMEOUT;
+    }
+}
+
+fn send_batch(endpoint: &str, batch: &[OtlpRecord]) {
+    let body = export_request(batch);
+    let mut delay = INITIAL_RETRY_DELAY;
+    for attempt in 1..=MAX_ATTEMPTS {
+        match ureq::post(endpoint).send_json(body.clone()) {
+            Ok(_) => return,
+            Err(e) if attempt == MAX_ATTEMPTS => {
+                log::error!(
+                    "Dropping {} log records after failing to export them to {}: {}",
+                    batch.len(),
+                    endpoint,
+                    e
+                );
+            }
+            Err(_) => {
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+        }
+    }
+}
+
+/// Builds an `ExportLogsServiceRequest` in the OTLP JSON encoding.
+fn export_request(batch: &[OtlpRecord]) -> Value {
+    let records: Vec<Value> = batch.iter().map(log_record).collect();
+    json!({
+        "resourceLogs": [{
+            "resource": {
+                "attributes": [string_attribute("service.name", "wasmcloud-actor")],
+            },
+            "scopeLogs": [{
+                "scope": { "name": SCOPE_NAME },
+                "logRecords": records,
+            }],
+        }],
+    })
+}
+
+fn log_record(record: &OtlpRecord) -> Value {
+    let time = record
+        .time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    json!({
+        // 64 bit integers are encoded as strings in OTLP JSON
+        "timeUnixNano": time.to_string(),
+        "severityNumber": severity_number(record.level),
+        "severityText": record.level.to_string(),
+        "body": { "stringValue": record.text },
+        "attributes": [
+            string_attribute("actor", &record.actor),
+            string_attribute("target", &record.target),
+        ],
+    })
+}
+
+fn string_attribute(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
+
+/// Maps a level to the base OTLP severity number of its range.
+fn severity_number(level: log::Level) -> u8 {
+    match level {
+        log::Level::Trace => 1,
+        log::Level::Debug => 5,
+        log::Level::Info => 9,
+        log::Level::Warn => 13,
+        log::Level::Error => 17,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(level: log::Level, text: &str) -> OtlpRecord {
+        OtlpRecord {
+            time: UNIX_EPOCH + Duration::from_millis(1500),
+            level,
+            actor: "Mactor".to_owned(),
+            target: "access".to_owned(),
+            text: text.to_owned(),
+        }
+    }
+
+    #[test]
+    fn export_request_shape() {
+        let request = export_request(&[
+            record(log::Level::Info, "hello"),
+            record(log::Level::Error, "oops"),
+        ]);
+        let resource_logs = &request["resourceLogs"][0];
+        assert_eq!(
+            resource_logs["resource"]["attributes"],
+            json!([{ "key": "service.name", "value": { "stringValue": "wasmcloud-actor" } }])
+        );
+        assert_eq!(resource_logs["scopeLogs"][0]["scope"]["name"], SCOPE_NAME);
+        let records = resource_logs["scopeLogs"][0]["logRecords"]
+            .as_array()
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            records[0],
+            json!({
+                "timeUnixNano": "1500000000",
+                "severityNumber": 9,
+                "severityText": "INFO",
+                "body": { "stringValue": "hello" },
+                "attributes": [
+                    { "key": "actor", "value": { "stringValue": "Mactor" } },
+                    { "key": "target", "value": { "stringValue": "access" } },
+                ],
+            })
+        );
+        assert_eq!(records[1]["severityNumber"], 17);
+        assert_eq!(records[1]["severityText"], "ERROR");
+    }
+
+    #[test]
+    fn severity_numbers_increase_with_level() {
+        let levels = [
+            log::Level::Trace,
+            log::Level::Debug,
+            log::Level::Info,
+            log::Level::Warn,
+            log::Level::Error,
+        ];
+        let numbers: Vec<u8> = levels.iter().map(|l| severity_number(*l)).collect();
+        assert_eq!(numbers, vec![1, 5, 9, 13, 17]);
+    }
+}
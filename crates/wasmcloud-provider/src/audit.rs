@@ -0,0 +1,247 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use kubelet::pod::PodKey;
+use log::error;
+use serde_derive::Serialize;
+
+use crate::redact::Redactor;
+
+/// The audit log target that writes records to stdout.
+const STDOUT_TARGET: &str = "stdout";
+
+/// An action the provider took against the wasmCloud host.
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum AuditAction {
+    Start,
+    Stop,
+    Link,
+    Unlink,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Outcome {
+    Success,
+    Failure,
+}
+
+#[derive(Debug, Serialize)]
+struct AuditRecord<'a> {
+    timestamp: DateTime<Utc>,
+    action: AuditAction,
+    /// The id of the wasmCloud host the action was taken in, which tells pods in a dedicated
+    /// host apart from those sharing the provider's.
+    host: &'a str,
+    namespace: String,
+    pod: String,
+    actor: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    capability: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    binding: Option<&'a str>,
+    outcome: Outcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// An append only trail of the actions the provider takes on actors, written as one JSON
+/// record per line. This is separate from the actors' own logs.
+pub(crate) struct AuditLog {
+    sink: Option<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl AuditLog {
+    /// Creates an audit log writing to `target`, which is either `stdout` or a file path that is
+    /// appended to. No target disables auditing.
+    pub(crate) fn new(target: Option<&str>) -> anyhow::Result<Self> {
+        let sink: Option<Box<dyn Write + Send>> = match target {
+            None => None,
+            Some(STDOUT_TARGET) => Some(Box::new(std::io::stdout())),
+            Some(path) => Some(Box::new(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| anyhow::anyhow!("Unable to open audit log {}: {}", path, e))?,
+            )),
+        };
+        Ok(AuditLog {
+            sink: sink.map(Mutex::new),
+        })
+    }
+
+    /// Records the outcome of an action taken in the given host, masking the actor's sensitive values in any error.
+    /// Failing to write the record is logged but otherwise ignored so that auditing never fails
+    /// a pod.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn record<T>(
+        &self,
+        action: AuditAction,
+        host: &str,
+        pod_key: &PodKey,
+        actor: &str,
+        capability: Option<(&str, Option<&str>)>,
+        result: &anyhow::Result<T>,
+        redactor: &Redactor,
+    ) {
+        let sink = match &self.sink {
+            Some(sink) => sink,
+            None => return,
+        };
+        let record = AuditRecord {
+            timestamp: Utc::now(),
+            action,
+            host,
+            namespace: pod_key.namespace(),
+            pod: pod_key.name(),
+            actor,
+            capability: capability.map(|(name, _)| name),
+            binding: capability.and_then(|(_, binding)| binding),
+            outcome: match result {
+                Ok(_) => Outcome::Success,
+                Err(_) => Outcome::Failure,
+            },
+            error: result
+                .as_ref()
+                .err()
+                .map(|e| redactor.redact(&e.to_string())),
+        };
+        let write = serde_json::to_vec(&record)
+            .map_err(anyhow::Error::from)
+            .and_then(|mut line| {
+                line.push(b'\n');
+                // A panic mid-write leaves at worst a partial line, so keep auditing after one
+                let mut sink = sink.lock().unwrap_or_else(|e| e.into_inner());
+                sink.write_all(&line)?;
+                sink.flush()?;
+                Ok(())
+            });
+        if let Err(e) = write {
+            error!("Unable to write audit record {:?}: {}", record, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    use crate::test_support::{container, pod_key};
+    use crate::{EnvVars, HTTP_CAPABILITY};
+
+    fn records(path: &std::path::Path) -> Vec<serde_json::Value> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    fn redactor() -> Redactor {
+        let container = container(json!({
+            "name": "greet",
+            "image": "greet:1",
+            "env": [{ "name": "API_TOKEN", "value": "hunter2" }]
+        }));
+        let env: EnvVars = vec![("API_TOKEN".to_owned(), "hunter2".to_owned())]
+            .into_iter()
+            .collect();
+        Redactor::new(&container, &env, &["*_TOKEN".to_owned()])
+    }
+
+    #[test]
+    fn records_start_link_and_failed_stop() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let audit = AuditLog::new(file.path().to_str()).unwrap();
+        let pod_key = pod_key("default", "greet");
+        let redactor = redactor();
+
+        audit.record(
+            AuditAction::Start,
+            "NHOST",
+            &pod_key,
+            "MACTOR",
+            None,
+            &Ok::<_, anyhow::Error>(()),
+            &redactor,
+        );
+        audit.record(
+            AuditAction::Link,
+            "NHOST",
+            &pod_key,
+            "MACTOR",
+            Some((HTTP_CAPABILITY, None)),
+            &Ok::<_, anyhow::Error>(()),
+            &redactor,
+        );
+        audit.record(
+            AuditAction::Stop,
+            "NHOST",
+            &pod_key,
+            "MACTOR",
+            None,
+            &Err::<(), _>(anyhow::anyhow!("host rejected token hunter2")),
+            &redactor,
+        );
+
+        let records = records(file.path());
+        assert_eq!(records.len(), 3);
+        for record in &records {
+            assert_eq!(record["namespace"], "default");
+            assert_eq!(record["pod"], "greet");
+            assert_eq!(record["actor"], "MACTOR");
+            assert_eq!(record["host"], "NHOST");
+            assert!(record["timestamp"].is_string());
+        }
+
+        assert_eq!(records[0]["action"], "start");
+        assert_eq!(records[0]["outcome"], "success");
+        assert!(records[0].get("capability").is_none());
+        assert!(records[0].get("error").is_none());
+
+        assert_eq!(records[1]["action"], "link");
+        assert_eq!(records[1]["capability"], HTTP_CAPABILITY);
+        assert!(records[1].get("binding").is_none());
+
+        assert_eq!(records[2]["action"], "stop");
+        assert_eq!(records[2]["outcome"], "failure");
+        assert_eq!(records[2]["error"], "host rejected token [REDACTED]");
+    }
+
+    #[test]
+    fn appends_to_an_existing_trail() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let pod_key = pod_key("default", "greet");
+        for action in [AuditAction::Start, AuditAction::Stop].iter() {
+            // Each restart of the provider opens the trail again
+            AuditLog::new(file.path().to_str()).unwrap().record(
+                *action,
+                "NHOST",
+                &pod_key,
+                "MACTOR",
+                Some((HTTP_CAPABILITY, Some("default"))),
+                &Ok::<_, anyhow::Error>(()),
+                &Redactor::default(),
+            );
+        }
+
+        let records = records(file.path());
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["action"], "start");
+        assert_eq!(records[1]["action"], "stop");
+        assert_eq!(records[1]["binding"], "default");
+    }
+
+    #[test]
+    fn unopenable_trail_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing").join("audit.log");
+        assert!(AuditLog::new(path.to_str()).is_err());
+    }
+}
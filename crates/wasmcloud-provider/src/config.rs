@@ -0,0 +1,558 @@
+//! Provider specific configuration that isn't covered by the kubelet's own
+//! [`kubelet::config::Config`].
+//!
+//! Values are read from `KRUSTLET_WASMCLOUD_*` environment variables in the same way the kubelet
+//! reads its own `KRUSTLET_*` settings. Anything left unset falls back to its default.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use oci_distribution::Reference;
+
+const KUBE_API_QPS_VAR: &str = "KRUSTLET_WASMCLOUD_KUBE_API_QPS";
+const KUBE_API_BURST_VAR: &str = "KRUSTLET_WASMCLOUD_KUBE_API_BURST";
+const AUDIT_LOG_VAR: &str = "KRUSTLET_WASMCLOUD_AUDIT_LOG";
+const KEEP_FAILED_VAR: &str = "KRUSTLET_WASMCLOUD_KEEP_FAILED_SECS";
+const NO_CAPABILITIES_VAR: &str = "KRUSTLET_WASMCLOUD_NO_CAPABILITIES";
+const SCHEDULER_NAMES_VAR: &str = "KRUSTLET_WASMCLOUD_SCHEDULER_NAMES";
+const IMAGE_REWRITES_VAR: &str = "KRUSTLET_WASMCLOUD_IMAGE_REWRITES";
+const LOG_MEMORY_BYTES_VAR: &str = "KRUSTLET_WASMCLOUD_LOG_MEMORY_BYTES";
+const MAX_LOG_STREAMS_VAR: &str = "KRUSTLET_WASMCLOUD_MAX_LOG_STREAMS";
+const MAX_NODE_LOG_STREAMS_VAR: &str = "KRUSTLET_WASMCLOUD_MAX_NODE_LOG_STREAMS";
+const START_DEADLINE_VAR: &str = "KRUSTLET_WASMCLOUD_START_DEADLINE_SECS";
+const SELF_TEST_VAR: &str = "KRUSTLET_WASMCLOUD_SELF_TEST";
+const CAPABILITY_DEFAULTS_VAR: &str = "KRUSTLET_WASMCLOUD_CAPABILITY_DEFAULTS";
+const QUARANTINE_FAILURES_VAR: &str = "KRUSTLET_WASMCLOUD_QUARANTINE_FAILURES";
+const QUARANTINE_WINDOW_VAR: &str = "KRUSTLET_WASMCLOUD_QUARANTINE_WINDOW_SECS";
+const COSIGN_PUBLIC_KEY_VAR: &str = "KRUSTLET_WASMCLOUD_COSIGN_PUBLIC_KEY";
+const STATE_DIR_VAR: &str = "KRUSTLET_WASMCLOUD_STATE_DIR";
+const ALLOWED_RESOURCES_VAR: &str = "KRUSTLET_WASMCLOUD_ALLOWED_RESOURCES";
+const ALLOWED_REGISTRIES_VAR: &str = "KRUSTLET_WASMCLOUD_ALLOWED_REGISTRIES";
+const DISABLE_FS_VAR: &str = "KRUSTLET_WASMCLOUD_DISABLE_FS";
+const LOG_MIN_FREE_BYTES_VAR: &str = "KRUSTLET_WASMCLOUD_LOG_MIN_FREE_BYTES";
+const SENSITIVE_ENV_VAR: &str = "KRUSTLET_WASMCLOUD_SENSITIVE_ENV";
+const LINK_RETRIES_VAR: &str = "KRUSTLET_WASMCLOUD_LINK_RETRIES";
+const IMAGE_FETCH_TIMEOUT_VAR: &str = "KRUSTLET_WASMCLOUD_IMAGE_FETCH_TIMEOUT_SECS";
+const RUNTIME_CLASS_NAMES_VAR: &str = "KRUSTLET_WASMCLOUD_RUNTIME_CLASS_NAMES";
+const DEV_DIR_VAR: &str = "KRUSTLET_WASMCLOUD_DEV_DIR";
+const IMAGE_INSTANCE_LIMITS_VAR: &str = "KRUSTLET_WASMCLOUD_IMAGE_INSTANCE_LIMITS";
+const TOLERATE_DIRECT_ASSIGNMENT_VAR: &str = "KRUSTLET_WASMCLOUD_TOLERATE_DIRECT_ASSIGNMENT";
+
+/// Link configuration values keyed by capability contract ID.
+pub type CapabilityDefaults = HashMap<String, HashMap<String, String>>;
+
+/// What to do with an actor that declares no capabilities. Such an actor runs but can't do
+/// anything, which usually means it wasn't built with the wasmCloud actor SDK or was signed
+/// without any capability claims.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NoCapabilities {
+    /// Start the actor, logging a warning and writing it to the container's log.
+    Warn,
+    /// Refuse to start the actor.
+    Fail,
+}
+
+impl FromStr for NoCapabilities {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(NoCapabilities::Warn),
+            "fail" => Ok(NoCapabilities::Fail),
+            _ => Err(anyhow::anyhow!("expected \"warn\" or \"fail\"")),
+        }
+    }
+}
+
+/// Whether to check the built-in capabilities at startup, see
+/// [`WasmCloudProvider::self_test`](crate::WasmCloudProvider::self_test).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SelfTest {
+    /// Don't check them.
+    Off,
+    /// Log a warning for each capability that fails its check.
+    Warn,
+    /// Refuse to start if any capability fails its check.
+    Fail,
+}
+
+impl FromStr for SelfTest {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(SelfTest::Off),
+            "warn" => Ok(SelfTest::Warn),
+            "fail" => Ok(SelfTest::Fail),
+            _ => Err(anyhow::anyhow!("expected \"off\", \"warn\" or \"fail\"")),
+        }
+    }
+}
+
+/// Configuration for the [`WasmCloudProvider`](crate::WasmCloudProvider).
+#[derive(Clone, Debug)]
+pub struct WasmCloudConfig {
+    /// The sustained rate, in queries per second, at which starting pods may call the API server
+    /// (for example to resolve ConfigMap and Secret references). A value of 0 disables limiting.
+    ///
+    /// The status patches of pods the provider is starting or running, and of their containers,
+    /// are metered too. Those of the earlier and failed pod states (registration, image pulls,
+    /// volume mounts, errors and backoff) are made by the kubelet itself, through a client that
+    /// offers no way to meter its requests, and are not.
+    pub kube_api_qps: f64,
+    /// The number of API calls allowed in a burst above `kube_api_qps`.
+    pub kube_api_burst: u32,
+    /// Where to write the audit trail of actor starts, stops, links and unlinks: `stdout` or a
+    /// file path. Auditing is off when unset.
+    pub audit_log: Option<String>,
+    /// A debugging aid: how long to keep the log file of an actor that failed to start so it
+    /// can be inspected. Set through the environment in seconds. Failed actors are cleaned up
+    /// immediately when unset (or 0).
+    pub keep_failed: Option<Duration>,
+    /// How to treat actors that declare no capabilities, `warn` or `fail`.
+    pub no_capabilities: NoCapabilities,
+    /// The schedulers whose pods this node runs, matched against `spec.schedulerName`. Set
+    /// through the environment as a comma separated list. Pods from any scheduler are run when
+    /// empty.
+    pub scheduler_names: Vec<String>,
+    /// Image reference prefixes to rewrite before pulling, as `(from, to)` pairs, for example
+    /// to pull through a mirror registry. Set through the environment as a comma separated list
+    /// of `from=to`, e.g. `docker.io/=mirror.internal/`. The first matching prefix wins.
+    pub image_rewrites: Vec<(String, String)>,
+    /// Keeps actor logs in memory, at most this many bytes per actor with the oldest dropped
+    /// first, instead of in files under the data directory. For nodes without a writable disk.
+    /// Logs kept in memory are lost when the kubelet restarts. Files are used when unset.
+    pub log_memory_bytes: Option<usize>,
+    /// The most log streams, such as `kubectl logs -f`, that may be open against a pod at once.
+    /// Requests beyond it are rejected until a stream is closed. Unlimited when unset (or 0).
+    pub max_log_streams: Option<usize>,
+    /// The most log streams that may be open across every pod on the node at once, so that
+    /// many `kubectl logs -f` during an incident can't saturate the disk the logs are read
+    /// from. Requests beyond it are rejected until a stream is closed. Unlimited when unset
+    /// (or 0).
+    pub max_node_log_streams: Option<usize>,
+    /// How long a pod may take from being registered, through pulling its images, to all of
+    /// its actors being started and linked. A pod still starting at the deadline fails with a
+    /// `StartTimeout` and whatever was started is rolled back. Set through the environment in
+    /// seconds. There is no deadline when unset (or 0).
+    pub start_deadline: Option<Duration>,
+    /// Whether to check the built-in capabilities at startup, `off`, `warn` or `fail`.
+    pub self_test: SelfTest,
+    /// Node wide link configuration for each capability, used for any value a pod's container
+    /// env doesn't set. Values the provider sets per actor, such as the HTTP port, the FS root
+    /// and where logs go, always win. Set through the environment as the path of a TOML file
+    /// with a table per capability contract ID:
+    ///
+    /// ```toml
+    /// ["wasmcloud:logging"]
+    /// LOG_DEDUP = "true"
+    /// ```
+    pub capability_defaults: CapabilityDefaults,
+    /// How many times a pod's actors may exit while it is running within `quarantine_window`
+    /// before it is quarantined: stopped with phase `Failed` and never restarted until it is
+    /// deleted. Other failures, such as image pulls, don't count. Pods are restarted with
+    /// backoff indefinitely when unset (or 0).
+    pub quarantine_failures: Option<u32>,
+    /// The window `quarantine_failures` is counted over. Set through the environment in seconds.
+    pub quarantine_window: Duration,
+    /// A PEM encoded ECDSA P-256 public key, as made by `cosign generate-key-pair`. When set,
+    /// every image must carry a cosign signature made with it, and pods whose images don't fail
+    /// to pull with a `SignatureVerificationFailed` error. Set through the environment as the
+    /// path of the key file. Keyless (Fulcio certificate) signatures aren't supported. Images
+    /// aren't verified when unset.
+    pub cosign_public_key: Option<String>,
+    /// Where capability providers keep state that must outlive a pod, such as a key-value
+    /// store's data. Each container gets `<state_dir>/<namespace>/<pod>/<container>`, passed to
+    /// its capabilities as `STATE_DIR`. The directory is keyed by the pod's name rather than its
+    /// UID, so a pod recreated with the same name, as a StatefulSet does when it reschedules
+    /// one, gets its old state back. Capabilities get no state directory when unset.
+    pub state_dir: Option<PathBuf>,
+    /// Resources, besides `cpu` and `memory`, that containers may request or set limits for,
+    /// e.g. `example.com/widget`. Pods naming any other resource, such as GPUs or hugepages,
+    /// are refused rather than run without it. Set through the environment as a comma
+    /// separated list.
+    pub allowed_resources: Vec<String>,
+    /// The registry hosts images may be pulled from, e.g. `ghcr.io` or
+    /// `registry.internal:5000`, checked after any `image_rewrites`. Pods with images from any
+    /// other registry fail to pull with a `RegistryNotAllowed` error. Set through the
+    /// environment as a comma separated list. Every registry is allowed when empty.
+    pub allowed_registries: Vec<String>,
+    /// Forbids the FS (`wasmcloud:blobstore`) capability, for nodes that must never give
+    /// actors access to local files. Pods mounting volumes, and actors declaring the
+    /// capability, are refused. Set through the environment as `true` or `false`.
+    pub disable_fs: bool,
+    /// The free space, in bytes, to keep on the disk holding actor logs. When it drops below
+    /// this, the logs of running actors are emptied, largest first, until it is back above it,
+    /// rather than letting the disk fill up. Each trimmed log is reported as a
+    /// [`ProviderEvent::LogTrimmed`](crate::ProviderEvent::LogTrimmed). Logs are never trimmed
+    /// when unset (or 0), or when they are kept in memory.
+    pub log_min_free_bytes: Option<u64>,
+    /// Env keys whose values are masked wherever the provider reports on a container, such as
+    /// its status message and the audit log, in addition to values taken from Secrets, which
+    /// always are. Patterns ignore case and `*` matches anything, e.g. `*_TOKEN` or `PASSWORD`.
+    /// Set through the environment as a comma separated list.
+    pub sensitive_env: Vec<String>,
+    /// How many times to retry linking an actor to a capability after a transient failure,
+    /// such as a provider that is still starting timing out, with the wait doubling from 200ms
+    /// between attempts. Other failures, such as invalid link configuration, fail the start
+    /// straight away, as does running out of retries, rolling back whatever was started. The
+    /// host is held while retrying, so keep this low. Links aren't retried when 0.
+    pub link_retries: u32,
+    /// How long fetching a single image may take, including checking its signature, before the
+    /// pull fails with an `ImagePullTimeout` error and is retried with backoff. Unlike
+    /// `start_deadline` it is per fetch, so a registry that has stopped answering is given up
+    /// on quickly. Set through the environment in seconds. Fetches aren't timed out when unset
+    /// (or 0).
+    pub image_fetch_timeout: Option<Duration>,
+    /// The RuntimeClasses that mean this runtime, matched against `spec.runtimeClassName`.
+    /// Pods naming any other runtime class are refused rather than run under the wrong
+    /// assumptions. Pods that don't name one are always run. Set through the environment as a
+    /// comma separated list. Defaults to `wasmcloud`.
+    pub runtime_class_names: Vec<String>,
+    /// For single node development clusters only: a directory watched for actor modules. Each
+    /// `<name>.wasm` file dropped into it is run as a pod named after it in the `default`
+    /// namespace, bound to this node, and the pod is deleted when the file is. The modules are
+    /// read straight from the directory, so they skip `allowed_registries` and signature
+    /// checks. Nothing is watched when unset.
+    pub dev_dir: Option<PathBuf>,
+    /// The most actors of an image that may run on the node at once, keyed by image reference
+    /// as written in the pod spec, before any `image_rewrites`. A pod that would take an image
+    /// over its limit fails to start with an `ImageInstanceLimitExceeded` error. Set through the
+    /// environment as a comma separated list of `image=max`, e.g.
+    /// `registry.internal/greet:v1=3`. Images that aren't listed are unlimited.
+    pub image_instance_limits: HashMap<String, usize>,
+    /// Runs pods assigned straight to this node with `spec.nodeName` even when they don't
+    /// tolerate its `kubernetes.io/arch` taints, adding the missing tolerations to the pod so
+    /// the taint manager doesn't evict it, and warns about each one in the log and as an event
+    /// on the pod. The kubelet must be allowed to patch pods, which the `NodeRestriction`
+    /// admission plugin forbids. This only relaxes direct assignment: pods placed by the
+    /// scheduler still need the tolerations to be scheduled here at all.
+    pub tolerate_direct_assignment: bool,
+}
+
+impl Default for WasmCloudConfig {
+    fn default() -> Self {
+        // These match the kubelet's own `--kube-api-qps` and `--kube-api-burst` defaults
+        WasmCloudConfig {
+            kube_api_qps: 5.0,
+            kube_api_burst: 10,
+            audit_log: None,
+            keep_failed: None,
+            no_capabilities: NoCapabilities::Warn,
+            scheduler_names: vec![],
+            image_rewrites: vec![],
+            log_memory_bytes: None,
+            max_log_streams: None,
+            max_node_log_streams: None,
+            start_deadline: None,
+            self_test: SelfTest::Off,
+            capability_defaults: HashMap::new(),
+            quarantine_failures: None,
+            quarantine_window: Duration::from_secs(600),
+            cosign_public_key: None,
+            state_dir: None,
+            allowed_resources: vec![],
+            allowed_registries: vec![],
+            disable_fs: false,
+            log_min_free_bytes: None,
+            sensitive_env: vec![],
+            link_retries: 0,
+            image_fetch_timeout: None,
+            runtime_class_names: vec!["wasmcloud".to_owned()],
+            dev_dir: None,
+            image_instance_limits: HashMap::new(),
+            tolerate_direct_assignment: false,
+        }
+    }
+}
+
+impl WasmCloudConfig {
+    /// Reads the configuration from the environment, using the defaults for unset variables.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let defaults = WasmCloudConfig::default();
+        Ok(WasmCloudConfig {
+            kube_api_qps: env_or(KUBE_API_QPS_VAR, defaults.kube_api_qps)?,
+            kube_api_burst: env_or(KUBE_API_BURST_VAR, defaults.kube_api_burst)?,
+            audit_log: std::env::var(AUDIT_LOG_VAR).ok(),
+            keep_failed: match env_or(KEEP_FAILED_VAR, 0u64)? {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            no_capabilities: env_or(NO_CAPABILITIES_VAR, defaults.no_capabilities)?,
+            scheduler_names: env_list(SCHEDULER_NAMES_VAR).unwrap_or(defaults.scheduler_names),
+            image_rewrites: match std::env::var(IMAGE_REWRITES_VAR) {
+                Ok(rewrites) => parse_image_rewrites(&rewrites)?,
+                Err(_) => defaults.image_rewrites,
+            },
+            log_memory_bytes: match std::env::var(LOG_MEMORY_BYTES_VAR) {
+                Ok(_) => Some(env_or(LOG_MEMORY_BYTES_VAR, 0)?),
+                Err(_) => defaults.log_memory_bytes,
+            },
+            max_log_streams: match env_or(MAX_LOG_STREAMS_VAR, 0usize)? {
+                0 => None,
+                max => Some(max),
+            },
+            max_node_log_streams: match env_or(MAX_NODE_LOG_STREAMS_VAR, 0usize)? {
+                0 => None,
+                max => Some(max),
+            },
+            start_deadline: match env_or(START_DEADLINE_VAR, 0u64)? {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            self_test: env_or(SELF_TEST_VAR, defaults.self_test)?,
+            capability_defaults: match std::env::var(CAPABILITY_DEFAULTS_VAR) {
+                Ok(path) => read_capability_defaults(&path)?,
+                Err(_) => defaults.capability_defaults,
+            },
+            quarantine_failures: match env_or(QUARANTINE_FAILURES_VAR, 0u32)? {
+                0 => None,
+                failures => Some(failures),
+            },
+            quarantine_window: Duration::from_secs(env_or(
+                QUARANTINE_WINDOW_VAR,
+                defaults.quarantine_window.as_secs(),
+            )?),
+            cosign_public_key: match std::env::var(COSIGN_PUBLIC_KEY_VAR) {
+                Ok(path) => Some(std::fs::read_to_string(&path).map_err(|e| {
+                    anyhow::anyhow!(
+                        "Unable to read cosign public key {} from {}: {}",
+                        path,
+                        COSIGN_PUBLIC_KEY_VAR,
+                        e
+                    )
+                })?),
+                Err(_) => defaults.cosign_public_key,
+            },
+            state_dir: std::env::var(STATE_DIR_VAR)
+                .ok()
+                .map(PathBuf::from)
+                .or(defaults.state_dir),
+            allowed_resources: env_list(ALLOWED_RESOURCES_VAR)
+                .unwrap_or(defaults.allowed_resources),
+            allowed_registries: env_list(ALLOWED_REGISTRIES_VAR)
+                .unwrap_or(defaults.allowed_registries),
+            disable_fs: env_or(DISABLE_FS_VAR, defaults.disable_fs)?,
+            log_min_free_bytes: match env_or(LOG_MIN_FREE_BYTES_VAR, 0u64)? {
+                0 => None,
+                bytes => Some(bytes),
+            },
+            sensitive_env: env_list(SENSITIVE_ENV_VAR).unwrap_or(defaults.sensitive_env),
+            link_retries: env_or(LINK_RETRIES_VAR, defaults.link_retries)?,
+            image_fetch_timeout: match env_or(IMAGE_FETCH_TIMEOUT_VAR, 0u64)? {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            runtime_class_names: env_list(RUNTIME_CLASS_NAMES_VAR)
+                .unwrap_or(defaults.runtime_class_names),
+            dev_dir: std::env::var(DEV_DIR_VAR)
+                .ok()
+                .map(PathBuf::from)
+                .or(defaults.dev_dir),
+            image_instance_limits: match std::env::var(IMAGE_INSTANCE_LIMITS_VAR) {
+                Ok(limits) => parse_image_instance_limits(&limits)?,
+                Err(_) => defaults.image_instance_limits,
+            },
+            tolerate_direct_assignment: env_or(
+                TOLERATE_DIRECT_ASSIGNMENT_VAR,
+                defaults.tolerate_direct_assignment,
+            )?,
+        })
+    }
+}
+
+/// Reads a comma separated list, ignoring empty entries.
+fn env_list(name: &str) -> Option<Vec<String>> {
+    std::env::var(name).ok().map(|list| {
+        list.split(',')
+            .map(str::trim)
+            .filter(|n| !n.is_empty())
+            .map(str::to_owned)
+            .collect()
+    })
+}
+
+/// Reads and validates the capability defaults file.
+fn read_capability_defaults(path: &str) -> anyhow::Result<CapabilityDefaults> {
+    let contents = std::fs::read_to_string(path).map_err(|e| {
+        anyhow::anyhow!(
+            "Unable to read capability defaults file {} from {}: {}",
+            path,
+            CAPABILITY_DEFAULTS_VAR,
+            e
+        )
+    })?;
+    let defaults: CapabilityDefaults = toml::from_str(&contents).map_err(|e| {
+        anyhow::anyhow!(
+            "Invalid capability defaults file {}: {}. Expected a table of string values per capability",
+            path,
+            e
+        )
+    })?;
+    let known = [
+        crate::FS_CAPABILITY,
+        crate::HTTP_CAPABILITY,
+        crate::LOG_CAPABILITY,
+    ];
+    if let Some(unknown) = defaults.keys().find(|c| !known.contains(&c.as_str())) {
+        return Err(anyhow::anyhow!(
+            "Invalid capability defaults file {}: unknown capability {:?}, expected one of {}",
+            path,
+            unknown,
+            known.join(", ")
+        ));
+    }
+    Ok(defaults)
+}
+
+/// Parses a comma separated list of `from=to` image reference prefix rewrites.
+fn parse_image_rewrites(rewrites: &str) -> anyhow::Result<Vec<(String, String)>> {
+    rewrites
+        .split(',')
+        .map(str::trim)
+        .filter(|r| !r.is_empty())
+        .map(|rewrite| {
+            let mut parts = rewrite.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(from), Some(to)) if !from.is_empty() && !to.is_empty() => {
+                    Ok((from.to_owned(), to.to_owned()))
+                }
+                _ => Err(anyhow::anyhow!(
+                    "Invalid image rewrite {:?} in {}: expected from=to",
+                    rewrite,
+                    IMAGE_REWRITES_VAR
+                )),
+            }
+        })
+        .collect()
+}
+
+/// Parses a comma separated list of `image=max` instance limits. Each image is stored as its
+/// whole reference, so `greet:v1` and its fully qualified form are the same image.
+fn parse_image_instance_limits(limits: &str) -> anyhow::Result<HashMap<String, usize>> {
+    limits
+        .split(',')
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(|limit| {
+            let invalid = |reason: String| {
+                anyhow::anyhow!(
+                    "Invalid image instance limit {:?} in {}: {}",
+                    limit,
+                    IMAGE_INSTANCE_LIMITS_VAR,
+                    reason
+                )
+            };
+            let mut parts = limit.rsplitn(2, '=');
+            let (max, image) = match (parts.next(), parts.next()) {
+                (Some(max), Some(image)) if !image.is_empty() => (max, image),
+                _ => return Err(invalid("expected image=max".to_owned())),
+            };
+            let max = max.parse().map_err(|e| invalid(format!("{}", e)))?;
+            let image =
+                Reference::try_from(image.to_owned()).map_err(|e| invalid(format!("{}", e)))?;
+            Ok((image.whole(), max))
+        })
+        .collect()
+}
+
+/// Parses the named environment variable, returning `default` if it isn't set.
+fn env_or<T>(name: &str, default: T) -> anyhow::Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map_err(|e| anyhow::anyhow!("Invalid value {:?} for {}: {}", value, name, e)),
+        Err(_) => Ok(default),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_rewrites_parse_in_order() {
+        let rewrites =
+            parse_image_rewrites(" docker.io/=mirror.internal/ ,, ghcr.io/=mirror.internal/ghcr/")
+                .unwrap();
+        assert_eq!(
+            rewrites,
+            vec![
+                ("docker.io/".to_owned(), "mirror.internal/".to_owned()),
+                ("ghcr.io/".to_owned(), "mirror.internal/ghcr/".to_owned()),
+            ]
+        );
+        assert!(parse_image_rewrites("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn image_rewrites_need_both_sides() {
+        for invalid in &["docker.io/", "=mirror.internal/", "docker.io/="] {
+            let err = parse_image_rewrites(invalid).unwrap_err();
+            assert!(err.to_string().contains("expected from=to"), "{}", err);
+        }
+    }
+
+    fn defaults_file(contents: &str) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, contents.as_bytes()).unwrap();
+        file
+    }
+
+    fn read(file: &tempfile::NamedTempFile) -> anyhow::Result<CapabilityDefaults> {
+        read_capability_defaults(file.path().to_str().unwrap())
+    }
+
+    #[test]
+    fn capability_defaults_are_read_per_capability() {
+        let file = defaults_file(
+            r#"
+            ["wasmcloud:httpserver"]
+            PORT = "8080"
+            BIND_ADDR = "127.0.0.1"
+
+            ["wasmcloud:logging"]
+            LOG_DEDUP = "true"
+            "#,
+        );
+        let defaults = read(&file).unwrap();
+        assert_eq!(defaults.len(), 2);
+        assert_eq!(defaults[crate::HTTP_CAPABILITY]["PORT"], "8080");
+        assert_eq!(defaults[crate::HTTP_CAPABILITY]["BIND_ADDR"], "127.0.0.1");
+        assert_eq!(defaults[crate::LOG_CAPABILITY]["LOG_DEDUP"], "true");
+    }
+
+    #[test]
+    fn capability_defaults_must_be_strings() {
+        let file = defaults_file("[\"wasmcloud:httpserver\"]\nPORT = 8080\n");
+        let err = read(&file).unwrap_err().to_string();
+        assert!(err.contains("Expected a table of string values"), "{}", err);
+    }
+
+    #[test]
+    fn capability_defaults_for_unknown_capabilities_are_refused() {
+        let file = defaults_file("[\"wasmcloud:keyvalue\"]\nURL = \"redis://\"\n");
+        let err = read(&file).unwrap_err().to_string();
+        assert!(
+            err.contains("unknown capability \"wasmcloud:keyvalue\""),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn missing_capability_defaults_file_names_the_variable() {
+        let err = read_capability_defaults("/nonexistent/defaults.toml")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains(CAPABILITY_DEFAULTS_VAR), "{}", err);
+    }
+}
@@ -0,0 +1,366 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::api::Api;
+use kubelet::container::Container;
+use kubelet::pod::Pod;
+use kubelet::provider::Provider;
+use log::{debug, error, info};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use wasmcloud_host::Host;
+
+use crate::rate_limit::RateLimiter;
+use crate::volume_swap;
+use crate::{Capability, CapabilityDefaults, EnvVars, WasmCloudProvider, PORT_KEY};
+
+/// How often the watched ConfigMaps are looked up to spot changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a changed ConfigMap must stay unchanged before the change is applied, so a burst of
+/// edits is applied once.
+const DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// A ConfigMap volume the container mounts, to be rewritten when the ConfigMap changes.
+pub(crate) struct ConfigMapVolume {
+    pub(crate) config_map: String,
+    /// The volume's directory on the node.
+    pub(crate) host_path: PathBuf,
+}
+
+/// The ConfigMaps an actor's config comes from and where to look them up.
+pub(crate) struct ConfigMapWatch {
+    pub(crate) client: kube::Client,
+    pub(crate) api_rate_limiter: Arc<RateLimiter>,
+    pub(crate) pod: Pod,
+    pub(crate) container: Container,
+    /// The env the actor was linked with.
+    pub(crate) env: EnvVars,
+    /// The link values a key falls back to once the env no longer sets it.
+    pub(crate) capability_defaults: Arc<CapabilityDefaults>,
+    pub(crate) volumes: Vec<ConfigMapVolume>,
+}
+
+impl ConfigMapWatch {
+    /// The names of every ConfigMap the container's env or volumes refer to.
+    fn config_maps(&self) -> BTreeSet<String> {
+        let mut names: BTreeSet<String> = self
+            .container
+            .env()
+            .as_ref()
+            .map(|env| {
+                env.iter()
+                    .filter_map(|e| e.value_from.as_ref())
+                    .filter_map(|from| from.config_map_key_ref.as_ref())
+                    .filter_map(|key_ref| key_ref.name.clone())
+                    .collect()
+            })
+            .unwrap_or_default();
+        names.extend(self.volumes.iter().map(|v| v.config_map.clone()));
+        names
+    }
+
+    /// Whether the container uses any ConfigMap, and so whether it is worth watching.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.config_maps().is_empty()
+    }
+}
+
+/// Returns the ConfigMap volumes among the pod's volumes with the given names.
+pub(crate) fn config_map_volumes(pod: &Pod, mounted: &[(String, PathBuf)]) -> Vec<ConfigMapVolume> {
+    let volumes = pod
+        .as_kube_pod()
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.volumes.as_ref());
+    mounted
+        .iter()
+        .filter_map(|(name, host_path)| {
+            let config_map = volumes?
+                .iter()
+                .find(|v| &v.name == name)?
+                .config_map
+                .as_ref()?
+                .name
+                .clone()?;
+            Some(ConfigMapVolume {
+                config_map,
+                host_path: host_path.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Looks up the resource version of each ConfigMap. A ConfigMap that doesn't exist has none.
+async fn versions(
+    api: &Api<ConfigMap>,
+    api_rate_limiter: &RateLimiter,
+    names: &BTreeSet<String>,
+) -> anyhow::Result<BTreeMap<String, Option<String>>> {
+    let mut versions = BTreeMap::new();
+    for name in names {
+        api_rate_limiter.acquire(1).await;
+        let version = match api.get(name).await {
+            Ok(config_map) => config_map.metadata.resource_version,
+            Err(kube::Error::Api(e)) if e.code == 404 => None,
+            Err(e) => {
+                return Err(anyhow::anyhow!("Unable to get ConfigMap {}: {}", name, e));
+            }
+        };
+        versions.insert(name.clone(), version);
+    }
+    Ok(versions)
+}
+
+/// Replaces the volume's files with the ConfigMap's current data, one file per key, all at
+/// once.
+async fn rematerialize(
+    api: &Api<ConfigMap>,
+    api_rate_limiter: &RateLimiter,
+    volume: &ConfigMapVolume,
+) -> anyhow::Result<()> {
+    api_rate_limiter.acquire(1).await;
+    let config_map = api.get(&volume.config_map).await?;
+    let mut files: BTreeMap<String, Vec<u8>> = config_map
+        .data
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(key, value)| (key, value.into_bytes()))
+        .collect();
+    files.extend(
+        config_map
+            .binary_data
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(key, value)| (key, value.0)),
+    );
+    volume_swap::swap_contents(&volume.host_path, &files).await
+}
+
+/// Keeps an actor's config up to date with the ConfigMaps it uses, without restarting it.
+///
+/// The ConfigMaps are looked up every [`POLL_INTERVAL`]. Once a change has held for
+/// [`DEBOUNCE`], ConfigMap volumes are swapped to the new data and every link whose values
+/// changed with the container's env is set again, see [`relink_env`]. The HTTP port can't be
+/// moved while the actor runs, so a changed `PORT` is ignored. Failures are logged and retried
+/// on the next change.
+pub(crate) fn spawn_reload(
+    host: Arc<Mutex<Host>>,
+    actor: String,
+    capabilities: Arc<Mutex<Vec<Capability>>>,
+    watch: ConfigMapWatch,
+) -> JoinHandle<()> {
+    let names = watch.config_maps();
+    let ConfigMapWatch {
+        client,
+        api_rate_limiter,
+        pod,
+        container,
+        mut env,
+        capability_defaults,
+        volumes,
+    } = watch;
+    let api: Api<ConfigMap> = Api::namespaced(client.clone(), pod.namespace());
+    tokio::spawn(async move {
+        // Swapping needs every file to be a link into the current version, so move the files
+        // the volume was mounted with over before anything changes
+        for volume in volumes.iter() {
+            if let Err(e) = volume_swap::adopt(&volume.host_path).await {
+                error!(
+                    "Unable to prepare ConfigMap {} volume for reloads for actor {}: {}",
+                    volume.config_map, actor, e
+                );
+            }
+        }
+        let mut current = match versions(&api, &api_rate_limiter, &names).await {
+            Ok(current) => current,
+            Err(e) => {
+                error!("Unable to watch ConfigMaps for actor {}: {}", actor, e);
+                BTreeMap::new()
+            }
+        };
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            let mut latest = match versions(&api, &api_rate_limiter, &names).await {
+                Ok(latest) => latest,
+                Err(e) => {
+                    error!("Unable to refresh ConfigMaps for actor {}: {}", actor, e);
+                    continue;
+                }
+            };
+            if latest == current {
+                continue;
+            }
+            // Wait for the edits to settle
+            loop {
+                tokio::time::sleep(DEBOUNCE).await;
+                match versions(&api, &api_rate_limiter, &names).await {
+                    Ok(settled) if settled == latest => break,
+                    Ok(settled) => latest = settled,
+                    Err(e) => {
+                        error!("Unable to refresh ConfigMaps for actor {}: {}", actor, e);
+                        break;
+                    }
+                }
+            }
+            let changed: BTreeSet<&String> = latest
+                .iter()
+                .filter(|(name, version)| current.get(*name) != Some(*version))
+                .map(|(name, _)| name)
+                .collect();
+            info!(
+                "ConfigMaps {:?} changed for actor {}, reloading its config",
+                changed, actor
+            );
+
+            for volume in volumes.iter().filter(|v| changed.contains(&v.config_map)) {
+                debug!(
+                    "Rewriting ConfigMap {} volume {} for actor {}",
+                    volume.config_map,
+                    volume.host_path.display(),
+                    actor
+                );
+                if let Err(e) = rematerialize(&api, &api_rate_limiter, volume).await {
+                    error!(
+                        "Unable to rewrite ConfigMap {} volume for actor {}: {}",
+                        volume.config_map, actor, e
+                    );
+                }
+            }
+
+            let api_calls = container
+                .env()
+                .as_ref()
+                .map(|env| env.iter().filter(|e| e.value_from.is_some()).count())
+                .unwrap_or(0);
+            api_rate_limiter.acquire(api_calls).await;
+            let new_env =
+                <WasmCloudProvider as Provider>::env_vars(&container, &pod, &client).await;
+            let mut capabilities = capabilities.lock().await;
+            let lock = host.lock().await;
+            for cap in capabilities.iter_mut() {
+                let relinked =
+                    relink_env(&cap.env, &env, &new_env, capability_defaults.get(cap.name));
+                if relinked == cap.env {
+                    continue;
+                }
+                cap.env = relinked;
+                debug!("Updating {} link for actor {}", cap.name, actor);
+                if let Err(e) = lock
+                    .set_link(
+                        &actor,
+                        cap.name,
+                        cap.binding.clone(),
+                        cap.capability_provider_id.to_owned(),
+                        cap.env.clone(),
+                    )
+                    .await
+                {
+                    error!(
+                        "Unable to update {} link for actor {}: {}",
+                        cap.name, actor, e
+                    );
+                }
+            }
+            env = new_env;
+            current = latest;
+        }
+    })
+}
+
+/// Rebuilds a link's values for the container's new env.
+///
+/// Values the link took from the old env are dropped, falling back to the capability's
+/// default if it has one, and the new env is laid over what is left. Values the provider set
+/// itself, such as the log path, are kept, and so is `PORT`, which can't change while the
+/// actor runs.
+fn relink_env(
+    link_env: &EnvVars,
+    old_env: &EnvVars,
+    new_env: &EnvVars,
+    defaults: Option<&EnvVars>,
+) -> EnvVars {
+    let default = |key: &String| defaults.and_then(|d| d.get(key));
+    let mut relinked = link_env.clone();
+    for (key, value) in old_env.iter().filter(|(key, _)| *key != PORT_KEY) {
+        if relinked.get(key) != Some(value) {
+            continue;
+        }
+        match default(key) {
+            Some(default) => relinked.insert(key.clone(), default.clone()),
+            None => relinked.remove(key),
+        };
+    }
+    for (key, value) in new_env.iter().filter(|(key, _)| *key != PORT_KEY) {
+        let set_by_provider = match link_env.get(key) {
+            Some(linked) => old_env.get(key) != Some(linked) && default(key) != Some(linked),
+            None => false,
+        };
+        if !set_by_provider {
+            relinked.insert(key.clone(), value.clone());
+        }
+    }
+    relinked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> EnvVars {
+        vars.iter()
+            .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn changed_and_added_keys_are_relinked() {
+        let old = vars(&[("GREETING", "hello")]);
+        let link = vars(&[("GREETING", "hello"), ("LOG_PATH", "/var/log/greet")]);
+        let new = vars(&[("GREETING", "howdy"), ("NAME", "world")]);
+
+        let relinked = relink_env(&link, &old, &new, None);
+        assert_eq!(
+            relinked,
+            vars(&[
+                ("GREETING", "howdy"),
+                ("NAME", "world"),
+                ("LOG_PATH", "/var/log/greet"),
+            ])
+        );
+    }
+
+    #[test]
+    fn removed_keys_are_unlinked() {
+        let old = vars(&[("GREETING", "hello"), ("NAME", "world")]);
+        let link = vars(&[("GREETING", "hello"), ("NAME", "world")]);
+        let new = vars(&[("NAME", "world")]);
+
+        assert_eq!(relink_env(&link, &old, &new, None), new);
+    }
+
+    #[test]
+    fn removed_keys_fall_back_to_the_default() {
+        let defaults = vars(&[("BIND_ADDR", "127.0.0.1")]);
+        let old = vars(&[("BIND_ADDR", "0.0.0.0")]);
+        let link = vars(&[("BIND_ADDR", "0.0.0.0")]);
+
+        let relinked = relink_env(&link, &old, &EnvVars::new(), Some(&defaults));
+        assert_eq!(relinked, defaults);
+        // And the env wins over the default again once it sets the key
+        let relinked = relink_env(&relinked, &EnvVars::new(), &old, Some(&defaults));
+        assert_eq!(relinked, old);
+    }
+
+    #[test]
+    fn provider_values_and_port_are_kept() {
+        let old = vars(&[("PORT", "8080"), ("LOG_PATH", "/tmp/mine")]);
+        let link = vars(&[("PORT", "8080"), ("LOG_PATH", "/var/log/greet")]);
+        let new = vars(&[("PORT", "9090"), ("LOG_PATH", "/tmp/other")]);
+
+        assert_eq!(relink_env(&link, &old, &new, None), link);
+    }
+}
@@ -0,0 +1,279 @@
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+use kubelet::store::{PullPolicy, Store};
+use log::{debug, info};
+use oci_distribution::secrets::RegistryAuth;
+use oci_distribution::{Client, Reference};
+use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_ASN1};
+use serde_derive::Deserialize;
+use tokio::sync::Mutex;
+
+/// The annotation on a cosign signature layer holding the base64 encoded signature of the
+/// layer's payload.
+const SIGNATURE_ANNOTATION: &str = "dev.cosignproject.cosign/signature";
+
+/// The DER prefix of a P-256 `SubjectPublicKeyInfo`, which the 65 byte uncompressed public key
+/// point follows.
+const P256_SPKI_PREFIX_LEN: usize = 26;
+const P256_SPKI_LEN: usize = P256_SPKI_PREFIX_LEN + 65;
+
+/// The reason image pulls fail with when an image isn't properly signed.
+const FAILURE_REASON: &str = "SignatureVerificationFailed";
+
+/// The part of cosign's simple signing payload that is checked.
+#[derive(Deserialize)]
+struct Payload {
+    critical: Critical,
+}
+
+#[derive(Deserialize)]
+struct Critical {
+    image: SignedImage,
+}
+
+#[derive(Deserialize)]
+struct SignedImage {
+    #[serde(rename = "docker-manifest-digest")]
+    docker_manifest_digest: String,
+}
+
+/// A [`Store`] that only hands out images with a valid cosign signature made with the
+/// configured key.
+///
+/// The signature is looked up under cosign's `sha256-<digest>.sig` tag next to the image. Once
+/// verified, the image is fetched by digest so the module loaded is the one that was verified,
+/// even if the tag moves in the meantime.
+pub(crate) struct VerifyingStore {
+    inner: Arc<dyn Store + Sync + Send>,
+    client: Mutex<Client>,
+    /// The uncompressed P-256 public key point.
+    public_key: Vec<u8>,
+}
+
+impl VerifyingStore {
+    pub(crate) fn new(
+        inner: Arc<dyn Store + Sync + Send>,
+        client: Client,
+        public_key_pem: &str,
+    ) -> anyhow::Result<Self> {
+        Ok(VerifyingStore {
+            inner,
+            client: Mutex::new(client),
+            public_key: parse_public_key(public_key_pem)?,
+        })
+    }
+
+    /// Verifies the image's signature, returning a reference to the verified digest.
+    async fn verify(
+        &self,
+        image_ref: &Reference,
+        auth: &RegistryAuth,
+    ) -> anyhow::Result<Reference> {
+        let mut client = self.client.lock().await;
+        let (_, digest) = client.pull_manifest(image_ref, auth).await?;
+        let signature_ref = Reference::try_from(format!(
+            "{}/{}:{}.sig",
+            image_ref.registry(),
+            image_ref.repository(),
+            digest.replace(':', "-")
+        ))?;
+        let (signature_manifest, _) = client
+            .pull_manifest(&signature_ref, auth)
+            .await
+            .map_err(|e| anyhow::anyhow!("no signature found at {}: {}", signature_ref, e))?;
+
+        for layer in &signature_manifest.layers {
+            let signature = match layer
+                .annotations
+                .as_ref()
+                .and_then(|a| a.get(SIGNATURE_ANNOTATION))
+            {
+                Some(signature) => base64::decode(signature)?,
+                None => continue,
+            };
+            let mut payload = Vec::new();
+            client
+                .pull_layer(&signature_ref, &layer.digest, &mut payload)
+                .await?;
+            if let Err(e) = check_signature(&self.public_key, &payload, &signature, &digest) {
+                debug!(
+                    "Signature layer {} of {}: {}",
+                    layer.digest, signature_ref, e
+                );
+                continue;
+            }
+            info!("Verified signature of {} ({})", image_ref, digest);
+            return Ok(Reference::try_from(format!(
+                "{}/{}@{}",
+                image_ref.registry(),
+                image_ref.repository(),
+                digest
+            ))?);
+        }
+        Err(anyhow::anyhow!(
+            "none of the signatures at {} were made with the configured key for digest {}",
+            signature_ref,
+            digest
+        ))
+    }
+}
+
+#[async_trait::async_trait]
+impl Store for VerifyingStore {
+    async fn get(
+        &self,
+        image_ref: &Reference,
+        pull_policy: PullPolicy,
+        auth: &RegistryAuth,
+    ) -> anyhow::Result<Vec<u8>> {
+        let verified = self
+            .verify(image_ref, auth)
+            .await
+            .map_err(|e| anyhow::anyhow!("{}: image {}: {}", FAILURE_REASON, image_ref, e))?;
+        self.inner.get(&verified, pull_policy, auth).await
+    }
+}
+
+/// Checks a signature layer: that `signature` over its payload was made with the public key,
+/// and that the payload signs the image's manifest `digest`.
+fn check_signature(
+    public_key: &[u8],
+    payload: &[u8],
+    signature: &[u8],
+    digest: &str,
+) -> anyhow::Result<()> {
+    UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, public_key)
+        .verify(payload, signature)
+        .map_err(|_| anyhow::anyhow!("signature does not verify"))?;
+    let payload: Payload = serde_json::from_slice(payload)?;
+    if payload.critical.image.docker_manifest_digest != digest {
+        return Err(anyhow::anyhow!(
+            "signs {} rather than {}",
+            payload.critical.image.docker_manifest_digest,
+            digest
+        ));
+    }
+    Ok(())
+}
+
+/// Reads a PEM encoded ECDSA P-256 public key, as written by `cosign generate-key-pair`.
+fn parse_public_key(pem: &str) -> anyhow::Result<Vec<u8>> {
+    let pem = pem::parse(pem).map_err(|e| anyhow::anyhow!("invalid PEM: {}", e))?;
+    if pem.tag != "PUBLIC KEY" {
+        return Err(anyhow::anyhow!(
+            "expected a PUBLIC KEY but found a {}",
+            pem.tag
+        ));
+    }
+    // Only P-256 keys, which is what cosign generates, have this length
+    if pem.contents.len() != P256_SPKI_LEN {
+        return Err(anyhow::anyhow!(
+            "only ECDSA P-256 public keys are supported"
+        ));
+    }
+    Ok(pem.contents[P256_SPKI_PREFIX_LEN..].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+
+    const DIGEST: &str = "sha256:0123456789abcdef";
+
+    /// The DER prefix of a P-256 `SubjectPublicKeyInfo`.
+    const P256_SPKI_PREFIX: [u8; P256_SPKI_PREFIX_LEN] = [
+        0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08,
+        0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+    ];
+
+    fn key_pair() -> EcdsaKeyPair {
+        let pkcs8 =
+            EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &SystemRandom::new())
+                .unwrap();
+        EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref()).unwrap()
+    }
+
+    fn public_key_pem(key_pair: &EcdsaKeyPair) -> String {
+        let mut contents = P256_SPKI_PREFIX.to_vec();
+        contents.extend_from_slice(key_pair.public_key().as_ref());
+        pem::encode(&pem::Pem {
+            tag: "PUBLIC KEY".to_owned(),
+            contents,
+        })
+    }
+
+    /// A cosign simple signing payload for `digest`.
+    fn payload(digest: &str) -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({
+            "critical": {
+                "identity": { "docker-reference": "registry.example/greet" },
+                "image": { "docker-manifest-digest": digest },
+                "type": "cosign container image signature",
+            },
+            "optional": null,
+        }))
+        .unwrap()
+    }
+
+    fn sign(key_pair: &EcdsaKeyPair, payload: &[u8]) -> Vec<u8> {
+        key_pair
+            .sign(&SystemRandom::new(), payload)
+            .unwrap()
+            .as_ref()
+            .to_vec()
+    }
+
+    #[test]
+    fn signature_made_with_the_key_verifies() {
+        let key_pair = key_pair();
+        let public_key = parse_public_key(&public_key_pem(&key_pair)).unwrap();
+        let payload = payload(DIGEST);
+        let signature = sign(&key_pair, &payload);
+        check_signature(&public_key, &payload, &signature, DIGEST).unwrap();
+    }
+
+    #[test]
+    fn signature_made_with_another_key_is_rejected() {
+        let public_key = parse_public_key(&public_key_pem(&key_pair())).unwrap();
+        let payload = payload(DIGEST);
+        let signature = sign(&key_pair(), &payload);
+        let err = check_signature(&public_key, &payload, &signature, DIGEST).unwrap_err();
+        assert!(err.to_string().contains("does not verify"), "got: {}", err);
+    }
+
+    #[test]
+    fn unsigned_payload_is_rejected() {
+        let public_key = parse_public_key(&public_key_pem(&key_pair())).unwrap();
+        let payload = payload(DIGEST);
+        assert!(check_signature(&public_key, &payload, &[], DIGEST).is_err());
+    }
+
+    #[test]
+    fn signature_of_another_image_is_rejected() {
+        let key_pair = key_pair();
+        let public_key = parse_public_key(&public_key_pem(&key_pair)).unwrap();
+        let payload = payload("sha256:fedcba9876543210");
+        let signature = sign(&key_pair, &payload);
+        let err = check_signature(&public_key, &payload, &signature, DIGEST).unwrap_err();
+        assert!(err.to_string().contains("rather than"), "got: {}", err);
+    }
+
+    #[test]
+    fn only_p256_public_keys_are_accepted() {
+        let private = pem::encode(&pem::Pem {
+            tag: "PRIVATE KEY".to_owned(),
+            contents: vec![0; P256_SPKI_LEN],
+        });
+        assert!(parse_public_key(&private).is_err());
+        let short = pem::encode(&pem::Pem {
+            tag: "PUBLIC KEY".to_owned(),
+            contents: vec![0; 44],
+        });
+        assert!(parse_public_key(&short).is_err());
+        assert!(parse_public_key("not a key").is_err());
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use k8s_openapi::api::core::v1::Pod;
+use kube::api::{Api, DeleteParams, ListParams, PostParams};
+use kubelet::store::{PullPolicy, Store};
+use log::{error, info, warn};
+use oci_distribution::secrets::RegistryAuth;
+use oci_distribution::Reference;
+use serde_json::json;
+
+use crate::TARGET_WASM32_WASMCLOUD;
+
+/// The registry of the images of pods run from the dev directory. It is never contacted; the
+/// module is read from `<dev_dir>/<repository>.wasm`.
+const DEV_REGISTRY: &str = "dev.wasmcloud.local";
+
+/// Marks the pods the dev directory watcher manages, so it never touches any other pod.
+const DEV_DIR_LABEL: &str = "wasmcloud.dev/dev-dir";
+
+/// The namespace pods run from the dev directory are created in.
+const DEV_NAMESPACE: &str = "default";
+
+/// How often the dev directory is checked for added and removed modules.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The longest a pod name, and so a module name, may be.
+const MAX_NAME_LEN: usize = 63;
+
+/// A [`Store`] that serves the images of pods run from the dev directory from the files in it,
+/// passing every other image to the inner store.
+///
+/// It wraps every other store, so local modules aren't checked against the registry allowlist
+/// or for signatures.
+pub(crate) struct DevDirStore {
+    inner: Arc<dyn Store + Sync + Send>,
+    dir: PathBuf,
+}
+
+impl DevDirStore {
+    pub(crate) fn new(inner: Arc<dyn Store + Sync + Send>, dir: PathBuf) -> Self {
+        DevDirStore { inner, dir }
+    }
+}
+
+#[async_trait::async_trait]
+impl Store for DevDirStore {
+    async fn get(
+        &self,
+        image_ref: &Reference,
+        pull_policy: PullPolicy,
+        auth: &RegistryAuth,
+    ) -> anyhow::Result<Vec<u8>> {
+        if image_ref.registry() != DEV_REGISTRY {
+            return self.inner.get(image_ref, pull_policy, auth).await;
+        }
+        let path = self.dir.join(format!("{}.wasm", image_ref.repository()));
+        tokio::fs::read(&path).await.map_err(|e| {
+            anyhow::anyhow!(
+                "Unable to read module {} for image {}: {}",
+                path.display(),
+                image_ref,
+                e
+            )
+        })
+    }
+}
+
+/// Watches `dir` and keeps one pod per `.wasm` file in it running on `node_name`, for single
+/// node development clusters.
+///
+/// A pod is created when a file appears and deleted when it goes. The pods go through the
+/// same path as any other: they are bound to the node, pulled through the provider's store and
+/// started by its state machine. The task runs for as long as the process does.
+pub(crate) fn spawn_watcher(dir: PathBuf, node_name: String, client: kube::Client) {
+    let pods: Api<Pod> = Api::namespaced(client, DEV_NAMESPACE);
+    tokio::spawn(async move {
+        // Start from the pods left by a previous run, so files removed while the kubelet was
+        // down still have their pods deleted
+        let mut running = loop {
+            match dev_pods(&pods, &node_name).await {
+                Ok(running) => break running,
+                Err(e) => {
+                    error!("Unable to list pods run from dev directory: {}", e);
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                }
+            }
+        };
+        // Modules whose name is taken by a pod the watcher doesn't manage
+        let mut taken = BTreeSet::new();
+        loop {
+            let modules = match modules(&dir).await {
+                Ok(modules) => modules,
+                Err(e) => {
+                    error!("Unable to read dev directory {}: {}", dir.display(), e);
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                    continue;
+                }
+            };
+            taken.retain(|name| modules.contains(name));
+            let added: Vec<String> = modules
+                .difference(&running)
+                .filter(|name| !taken.contains(*name))
+                .cloned()
+                .collect();
+            for name in added {
+                info!("Running module {}.wasm from dev directory", name);
+                let result = match dev_pod(&name, &node_name) {
+                    Ok(pod) => pods.create(&PostParams::default(), &pod).await.map(|_| ()),
+                    Err(e) => {
+                        error!("Unable to build pod for module {}.wasm: {}", name, e);
+                        continue;
+                    }
+                };
+                match result {
+                    Ok(()) => {}
+                    // Left by a previous run, or someone else's pod of the same name
+                    Err(kube::Error::Api(e)) if e.code == 409 => match pods.get(&name).await {
+                        Ok(existing) if is_dev_pod(&existing) => {}
+                        Ok(_) => {
+                            warn!(
+                                "Not running module {}.wasm from dev directory: pod {} in namespace {} already exists and was not created from the dev directory",
+                                name, name, DEV_NAMESPACE
+                            );
+                            taken.insert(name);
+                            continue;
+                        }
+                        Err(e) => {
+                            error!("Unable to look up pod for module {}.wasm: {}", name, e);
+                            continue;
+                        }
+                    },
+                    Err(e) => {
+                        error!("Unable to create pod for module {}.wasm: {}", name, e);
+                        continue;
+                    }
+                }
+                running.insert(name);
+            }
+            let removed: Vec<String> = running.difference(&modules).cloned().collect();
+            for name in removed {
+                info!("Module {}.wasm was removed from dev directory", name);
+                match pods.delete(&name, &DeleteParams::default()).await {
+                    Ok(_) => {}
+                    Err(kube::Error::Api(e)) if e.code == 404 => {}
+                    Err(e) => {
+                        error!("Unable to delete pod for module {}.wasm: {}", name, e);
+                        continue;
+                    }
+                }
+                running.remove(&name);
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    });
+}
+
+/// The names of the dev directory pods already on the node.
+async fn dev_pods(pods: &Api<Pod>, node_name: &str) -> anyhow::Result<BTreeSet<String>> {
+    let params = ListParams::default()
+        .labels(&format!("{}=true", DEV_DIR_LABEL))
+        .fields(&format!("spec.nodeName={}", node_name));
+    Ok(pods
+        .list(&params)
+        .await?
+        .into_iter()
+        .filter_map(|pod| pod.metadata.name)
+        .collect())
+}
+
+/// Whether the pod was created by the dev directory watcher.
+fn is_dev_pod(pod: &Pod) -> bool {
+    pod.metadata
+        .labels
+        .as_ref()
+        .and_then(|labels| labels.get(DEV_DIR_LABEL))
+        .map(String::as_str)
+        == Some("true")
+}
+
+/// The names of the modules in the dev directory. Files whose names can't be used as a pod
+/// name are skipped with a warning.
+async fn modules(dir: &Path) -> anyhow::Result<BTreeSet<String>> {
+    let mut modules = BTreeSet::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("wasm") {
+            continue;
+        }
+        match path.file_stem().and_then(|s| s.to_str()) {
+            Some(name) if is_valid_name(name) => {
+                modules.insert(name.to_owned());
+            }
+            _ => warn!(
+                "Skipping {}: module names in the dev directory must be lowercase letters, digits and '-'",
+                path.display()
+            ),
+        }
+    }
+    Ok(modules)
+}
+
+/// Whether the name is a valid pod name that is also a valid image repository.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && !name.starts_with('-')
+        && !name.ends_with('-')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+/// The pod that runs a module from the dev directory.
+fn dev_pod(name: &str, node_name: &str) -> anyhow::Result<Pod> {
+    Ok(serde_json::from_value(json!({
+        "apiVersion": "v1",
+        "kind": "Pod",
+        "metadata": {
+            "name": name,
+            "namespace": DEV_NAMESPACE,
+            "labels": { DEV_DIR_LABEL: "true" }
+        },
+        "spec": {
+            "nodeName": node_name,
+            "containers": [
+                {
+                    "name": name,
+                    "image": format!("{}/{}:latest", DEV_REGISTRY, name),
+                    // Pick up a rebuilt module whenever the pod is recreated
+                    "imagePullPolicy": "Always"
+                }
+            ],
+            "tolerations": [
+                {
+                    "effect": "NoExecute",
+                    "key": "kubernetes.io/arch",
+                    "operator": "Equal",
+                    "value": TARGET_WASM32_WASMCLOUD
+                },
+                {
+                    "effect": "NoSchedule",
+                    "key": "kubernetes.io/arch",
+                    "operator": "Equal",
+                    "value": TARGET_WASM32_WASMCLOUD
+                }
+            ]
+        }
+    }))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_labelled_pods_are_dev_pods() {
+        assert!(is_dev_pod(&dev_pod("greet", "krustlet").unwrap()));
+        let pod: Pod = serde_json::from_value(json!({
+            "metadata": { "name": "greet", "labels": { "app": "greet" } }
+        }))
+        .unwrap();
+        assert!(!is_dev_pod(&pod));
+        let pod: Pod = serde_json::from_value(json!({
+            "metadata": { "name": "greet", "labels": { DEV_DIR_LABEL: "false" } }
+        }))
+        .unwrap();
+        assert!(!is_dev_pod(&pod));
+    }
+
+    #[test]
+    fn module_names_must_be_pod_names() {
+        assert!(is_valid_name("greet-v2"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("Greet"));
+        assert!(!is_valid_name("-greet"));
+        assert!(!is_valid_name("greet-"));
+        assert!(!is_valid_name("greet.v2"));
+        assert!(!is_valid_name(&"a".repeat(MAX_NAME_LEN + 1)));
+    }
+}
@@ -0,0 +1,614 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use kubelet::pod::PodKey;
+use serde_derive::Serialize;
+use serde_json::json;
+use wascap::jwt::{Actor, Claims};
+use wasmcloud_logging::MemoryLog;
+
+use crate::status::RecentError;
+use crate::{
+    capability_contract_version, ActorInfo, RunningSignal, WasmCloudProvider, FS_CAPABILITY,
+    HTTP_CAPABILITY, LOG_CAPABILITY, WASMCLOUD_HOST_VERSION,
+};
+
+/// The number of bytes from the end of each actor's log included in a state dump.
+const LOG_TAIL_BYTES: u64 = 4096;
+
+/// How many times a second the profiler samples every thread's stack.
+#[cfg(feature = "profiling")]
+const PROFILE_FREQUENCY: i32 = 99;
+
+/// The longest CPU profile that may be asked for.
+#[cfg(feature = "profiling")]
+const MAX_PROFILE_DURATION: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// The claims an actor module was signed with.
+#[derive(Clone, Debug, Serialize)]
+pub struct ActorClaims {
+    /// The public key of the account that signed the module.
+    pub issuer: String,
+    /// The actor's public key.
+    pub subject: String,
+    /// The actor's name, if it was given one.
+    pub name: Option<String>,
+    /// The capability contract IDs the actor may use.
+    pub capabilities: Vec<String>,
+    /// The tags the actor was signed with.
+    pub tags: Vec<String>,
+    /// The actor's version, if it was given one.
+    pub version: Option<String>,
+    /// The actor's revision, if it was given one.
+    pub revision: Option<i32>,
+    /// When the claims were issued, in seconds since the Unix epoch.
+    pub issued_at: u64,
+    /// When the claims expire, in seconds since the Unix epoch, if ever.
+    pub expires: Option<u64>,
+}
+
+impl From<&Claims<Actor>> for ActorClaims {
+    fn from(claims: &Claims<Actor>) -> Self {
+        let metadata = claims.metadata.as_ref();
+        ActorClaims {
+            issuer: claims.issuer.clone(),
+            subject: claims.subject.clone(),
+            name: metadata.and_then(|m| m.name.clone()),
+            capabilities: metadata.and_then(|m| m.caps.clone()).unwrap_or_default(),
+            tags: metadata.and_then(|m| m.tags.clone()).unwrap_or_default(),
+            version: metadata.and_then(|m| m.ver.clone()),
+            revision: metadata.and_then(|m| m.rev),
+            issued_at: claims.issued_at,
+            expires: claims.expires,
+        }
+    }
+}
+
+/// The claims of an actor running on this node, see
+/// [`WasmCloudProvider::claims_report`](crate::WasmCloudProvider::claims_report).
+#[derive(Clone, Debug)]
+pub struct ActorClaimsReport {
+    /// The pod the actor belongs to.
+    pub pod: PodKey,
+    /// The container the actor runs.
+    pub container: String,
+    /// The claims the actor was signed with.
+    pub claims: ActorClaims,
+}
+
+impl WasmCloudProvider {
+    /// Writes the provider's state to `path` as JSON for support bundles.
+    ///
+    /// The dump contains the embedded host version, every running actor grouped by pod
+    /// (its key, capabilities, links and the tail of its log) and the port map. Link
+    /// configuration values and environment variables are never included.
+    pub async fn dump_state(&self, path: &Path) -> anyhow::Result<()> {
+        let dump = {
+            let actors = self.shared.actors.read().await;
+            let port_map = self.shared.port_map.lock().await.clone();
+            dump(&actors, &port_map).await
+        };
+        tokio::fs::write(path, serde_json::to_vec_pretty(&dump)?)
+            .await
+            .map_err(|e| anyhow::anyhow!("Unable to write state dump to {}: {}", path.display(), e))
+    }
+
+    /// Returns a JSON summary of the node for operators and monitoring.
+    ///
+    /// It gives the host version, the capability providers running with the contract version
+    /// each implements and how many actors use it, the phase of every pod and its number of
+    /// actors, the ports in use and the most recent pod failures, and the memory the whole
+    /// kubelet process is using. Unlike [`dump_state`](Self::dump_state) it holds no logs or
+    /// links, so it is cheap enough to poll. Serving it, and deciding who may see it, is left to
+    /// the embedding binary.
+    pub async fn status(&self) -> serde_json::Value {
+        let fs_instances = self.shared.fs_providers.instances().await;
+        let port_map = self.shared.port_map.lock().await.clone();
+        let host_memory = resident_memory_bytes().await;
+        let actors = self.shared.actors.read().await;
+        let running = self.shared.running.lock().await;
+        status_document(
+            &actors,
+            &running,
+            fs_instances,
+            &port_map,
+            self.shared.recent_errors.list(),
+            host_memory,
+        )
+    }
+
+    /// Returns the signed claims of every actor running on this node, for compliance reporting.
+    ///
+    /// Each entry gives who signed the actor, its key, the capabilities it may use and when its
+    /// claims expire. Only what the module was signed with is included, never the environment
+    /// or link configuration the actor was started with.
+    pub async fn claims_report(&self) -> Vec<ActorClaimsReport> {
+        claims_report(&*self.shared.actors.read().await)
+    }
+
+    /// Returns every port the provider has handed out on this node with the pod holding it,
+    /// lowest port first, for tracking down port conflicts.
+    pub async fn port_allocations(&self) -> Vec<(u16, PodKey)> {
+        port_allocations(&*self.shared.port_map.lock().await)
+    }
+
+    /// Samples the kubelet process's CPU use for `duration` and returns the profile in pprof's
+    /// protobuf format, for `go tool pprof` and the like.
+    ///
+    /// This profiles the provider, such as time spent waiting on the host lock, rather than
+    /// actors, whose code runs in wasmtime and shows up as JIT frames. Only one profile can be
+    /// taken at a time. Like [`status`](Self::status), serving it and deciding who may ask for
+    /// it is left to the embedding binary. Heap profiles aren't available, as they need a
+    /// profiling allocator.
+    #[cfg(feature = "profiling")]
+    pub async fn cpu_profile(&self, duration: std::time::Duration) -> anyhow::Result<Vec<u8>> {
+        use pprof::protos::Message;
+
+        check_profile_duration(duration)?;
+        // The profiler samples every thread, so it can wait on any of them
+        tokio::task::spawn_blocking(move || -> anyhow::Result<Vec<u8>> {
+            let guard = pprof::ProfilerGuard::new(PROFILE_FREQUENCY)
+                .map_err(|e| anyhow::anyhow!("Unable to start profiler: {}", e))?;
+            std::thread::sleep(duration);
+            let profile = guard
+                .report()
+                .build()
+                .and_then(|report| report.pprof())
+                .map_err(|e| anyhow::anyhow!("Unable to build profile: {}", e))?;
+            let mut body = Vec::new();
+            profile.encode(&mut body)?;
+            Ok(body)
+        })
+        .await?
+    }
+}
+
+/// Checks that a profile is asked for a usable length of time: at least a millisecond and at
+/// most [`MAX_PROFILE_DURATION`].
+#[cfg(feature = "profiling")]
+fn check_profile_duration(duration: std::time::Duration) -> anyhow::Result<()> {
+    if duration.as_millis() == 0 || duration > MAX_PROFILE_DURATION {
+        return Err(anyhow::anyhow!(
+            "Profile duration must be more than 0 and at most {:?}, got {:?}",
+            MAX_PROFILE_DURATION,
+            duration
+        ));
+    }
+    Ok(())
+}
+
+/// The claims of every actor, taken only from what each module was signed with.
+fn claims_report(actors: &BTreeMap<PodKey, BTreeMap<String, ActorInfo>>) -> Vec<ActorClaimsReport> {
+    actors
+        .iter()
+        .flat_map(|(pod_key, containers)| {
+            containers
+                .iter()
+                .map(move |(name, actor)| ActorClaimsReport {
+                    pod: pod_key.clone(),
+                    container: name.clone(),
+                    claims: actor.claims.clone(),
+                })
+        })
+        .collect()
+}
+
+/// Every allocated port with the pod holding it, lowest port first.
+fn port_allocations(port_map: &BTreeMap<u16, PodKey>) -> Vec<(u16, PodKey)> {
+    port_map
+        .iter()
+        .map(|(port, pod_key)| (*port, pod_key.clone()))
+        .collect()
+}
+
+/// Gathers what [`WasmCloudProvider::dump_state`] writes.
+async fn dump(
+    actors: &BTreeMap<PodKey, BTreeMap<String, ActorInfo>>,
+    port_map: &BTreeMap<u16, PodKey>,
+) -> serde_json::Value {
+    let mut pods = serde_json::Map::new();
+    for (pod_key, containers) in actors.iter() {
+        let mut dumped = serde_json::Map::new();
+        for (name, actor) in containers {
+            let log_tail = match (&actor.log_path, &actor.memory_log) {
+                (Some(log_path), _) => read_log_tail(log_path.clone())
+                    .await
+                    .unwrap_or_else(|e| format!("<unable to read log: {}>", e)),
+                (None, Some(memory_log)) => memory_log_tail(memory_log),
+                (None, None) => String::new(),
+            };
+            dumped.insert(
+                name.clone(),
+                json!({
+                    "actor": actor,
+                    "log_tail": log_tail,
+                }),
+            );
+        }
+        pods.insert(
+            format!("{}/{}", pod_key.namespace(), pod_key.name()),
+            serde_json::Value::Object(dumped),
+        );
+    }
+
+    json!({
+        "host_version": WASMCLOUD_HOST_VERSION,
+        "pods": pods,
+        "port_map": port_map_snapshot(port_map),
+    })
+}
+
+/// Builds what [`WasmCloudProvider::status`] returns.
+fn status_document(
+    actors: &BTreeMap<PodKey, BTreeMap<String, ActorInfo>>,
+    running: &BTreeMap<PodKey, RunningSignal>,
+    fs_instances: usize,
+    port_map: &BTreeMap<u16, PodKey>,
+    recent_errors: Vec<RecentError>,
+    host_memory: Option<u64>,
+) -> serde_json::Value {
+    let mut actor_count = 0;
+    let mut capability_users: BTreeMap<String, usize> = BTreeMap::new();
+    let mut pods = serde_json::Map::new();
+    for (pod_key, containers) in actors.iter() {
+        actor_count += containers.len();
+        for actor in containers.values() {
+            for capability in &actor.capabilities {
+                *capability_users.entry(capability.clone()).or_default() += 1;
+            }
+        }
+        let phase = match running.get(pod_key) {
+            Some(signal) if *signal.rx.borrow() => "Running",
+            _ => "Starting",
+        };
+        pods.insert(
+            format!("{}/{}", pod_key.namespace(), pod_key.name()),
+            json!({ "phase": phase, "actors": containers.len() }),
+        );
+    }
+    let users = |capability: &str| capability_users.get(capability).copied().unwrap_or(0);
+    // The HTTP and log providers run for as long as the host does. An FS provider runs per
+    // volume binding in use.
+    let capabilities = json!({
+        HTTP_CAPABILITY: {
+            "version": capability_contract_version(HTTP_CAPABILITY),
+            "instances": 1,
+            "actors": users(HTTP_CAPABILITY),
+        },
+        LOG_CAPABILITY: {
+            "version": capability_contract_version(LOG_CAPABILITY),
+            "instances": 1,
+            "actors": users(LOG_CAPABILITY),
+        },
+        FS_CAPABILITY: {
+            "version": capability_contract_version(FS_CAPABILITY),
+            "instances": fs_instances,
+            "actors": users(FS_CAPABILITY),
+        },
+    });
+
+    let port_map = port_map_snapshot(port_map);
+    json!({
+        // The wasmCloud host doesn't account memory per actor; all actors share one process
+        // and one engine, so only the process total can be given
+        "host": {
+            "up": true,
+            "version": WASMCLOUD_HOST_VERSION,
+            "resident_memory_bytes": host_memory,
+        },
+        "capabilities": capabilities,
+        "actors": actor_count,
+        "pods": pods,
+        "ports": { "in_use": port_map.len(), "assigned": port_map },
+        "recent_errors": recent_errors,
+    })
+}
+
+/// The ports in use, each with the pod holding it.
+fn port_map_snapshot(port_map: &BTreeMap<u16, PodKey>) -> BTreeMap<String, String> {
+    port_map
+        .iter()
+        .map(|(port, pod_key)| {
+            (
+                port.to_string(),
+                format!("{}/{}", pod_key.namespace(), pod_key.name()),
+            )
+        })
+        .collect()
+}
+
+/// The resident memory of this process, which runs the wasmCloud host and so every actor. Only
+/// known on Linux.
+async fn resident_memory_bytes() -> Option<u64> {
+    let status = tokio::fs::read_to_string("/proc/self/status").await.ok()?;
+    vm_rss_bytes(&status)
+}
+
+/// Reads the resident memory from the contents of `/proc/<pid>/status`, which give it in KiB.
+fn vm_rss_bytes(status: &str) -> Option<u64> {
+    let kib: u64 = status
+        .lines()
+        .find(|line| line.starts_with("VmRSS:"))?
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()?;
+    Some(kib * 1024)
+}
+
+/// Reads at most the last [`LOG_TAIL_BYTES`] of an actor's in-memory log.
+fn memory_log_tail(log: &MemoryLog) -> String {
+    let mut tail = vec![0; LOG_TAIL_BYTES as usize];
+    let (_, read) = log.read_at(log.end().saturating_sub(LOG_TAIL_BYTES), &mut tail);
+    String::from_utf8_lossy(&tail[..read]).into_owned()
+}
+
+/// Reads at most the last [`LOG_TAIL_BYTES`] of an actor's log file.
+async fn read_log_tail(path: PathBuf) -> anyhow::Result<String> {
+    tokio::task::spawn_blocking(move || -> anyhow::Result<String> {
+        let mut file = std::fs::File::open(&path)?;
+        let len = file.metadata()?.len();
+        file.seek(SeekFrom::Start(len.saturating_sub(LOG_TAIL_BYTES)))?;
+        let mut tail = Vec::new();
+        file.read_to_end(&mut tail)?;
+        Ok(String::from_utf8_lossy(&tail).into_owned())
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+    use std::io::Write;
+
+    use wasmcloud_logging::RegisteredMemoryLog;
+
+    use crate::status::{PodStatusReport, RecentErrors, StatusReporter};
+    use crate::test_support::pod_key;
+    use crate::{Capability, LinkInfo, HTTP_CAPABILITY_PUBKEY};
+
+    /// A value the actor was started with that must never show up in diagnostics.
+    const SECRET: &str = "hunter2";
+
+    fn claims() -> ActorClaims {
+        ActorClaims {
+            issuer: "AISSUER".to_owned(),
+            subject: "MACTOR".to_owned(),
+            name: Some("greet".to_owned()),
+            capabilities: vec![HTTP_CAPABILITY.to_owned()],
+            tags: vec![],
+            version: Some("0.6.0".to_owned()),
+            revision: Some(1),
+            issued_at: 1_600_000_000,
+            expires: None,
+        }
+    }
+
+    /// An actor linked to the HTTP capability with a secret in its link config, logging to
+    /// memory.
+    fn actor(log: &str) -> ActorInfo {
+        let memory_log = RegisteredMemoryLog::new(64 * 1024).log().clone();
+        memory_log.append(log.as_bytes());
+        let link = Capability {
+            name: HTTP_CAPABILITY,
+            binding: None,
+            capability_provider_id: HTTP_CAPABILITY_PUBKEY,
+            env: vec![
+                ("PORT".to_owned(), "30000".to_owned()),
+                ("API_TOKEN".to_owned(), SECRET.to_owned()),
+            ]
+            .into_iter()
+            .collect::<HashMap<_, _>>(),
+        };
+        ActorInfo {
+            key: "MACTOR".to_owned(),
+            capabilities: vec![HTTP_CAPABILITY.to_owned()],
+            links: vec![LinkInfo::from(&link)],
+            log_path: None,
+            log_stream_paths: BTreeMap::new(),
+            claims: claims(),
+            memory_log: Some(memory_log),
+        }
+    }
+
+    fn actors() -> BTreeMap<PodKey, BTreeMap<String, ActorInfo>> {
+        let mut containers = BTreeMap::new();
+        containers.insert("greet".to_owned(), actor("hello from greet\n"));
+        let mut actors = BTreeMap::new();
+        actors.insert(pod_key("default", "greet"), containers);
+        actors
+    }
+
+    fn port_map() -> BTreeMap<u16, PodKey> {
+        let mut port_map = BTreeMap::new();
+        port_map.insert(30000, pod_key("default", "greet"));
+        port_map
+    }
+
+    #[tokio::test]
+    async fn dump_shape() {
+        let dump = dump(&actors(), &port_map()).await;
+
+        assert_eq!(dump["host_version"], WASMCLOUD_HOST_VERSION);
+        assert_eq!(dump["port_map"], json!({ "30000": "default/greet" }));
+        let greet = &dump["pods"]["default/greet"]["greet"];
+        assert_eq!(greet["log_tail"], "hello from greet\n");
+        assert_eq!(greet["actor"]["key"], "MACTOR");
+        assert_eq!(greet["actor"]["capabilities"], json!([HTTP_CAPABILITY]));
+        assert_eq!(
+            greet["actor"]["links"],
+            json!([{
+                "capability": HTTP_CAPABILITY,
+                "binding": null,
+                "provider_id": HTTP_CAPABILITY_PUBKEY,
+            }])
+        );
+        assert_eq!(greet["actor"]["claims"]["subject"], "MACTOR");
+        assert!(greet["actor"].get("memory_log").is_none());
+    }
+
+    #[tokio::test]
+    async fn dump_leaves_out_link_config() {
+        let dump = dump(&actors(), &port_map()).await;
+        assert!(!dump.to_string().contains(SECRET));
+    }
+
+    #[tokio::test]
+    async fn dump_tails_log_files() {
+        let mut log = tempfile::NamedTempFile::new().unwrap();
+        log.write_all(&vec![b'a'; LOG_TAIL_BYTES as usize]).unwrap();
+        log.write_all(b"the end\n").unwrap();
+        let mut actors = actors();
+        for actor in actors
+            .values_mut()
+            .flat_map(|containers| containers.values_mut())
+        {
+            actor.log_path = Some(log.path().to_owned());
+        }
+
+        let dump = dump(&actors, &port_map()).await;
+        let tail = dump["pods"]["default/greet"]["greet"]["log_tail"]
+            .as_str()
+            .unwrap();
+        assert_eq!(tail.len(), LOG_TAIL_BYTES as usize);
+        assert!(tail.ends_with("the end\n"));
+    }
+
+    #[tokio::test]
+    async fn status_shape() {
+        let mut running = BTreeMap::new();
+        let (tx, rx) = tokio::sync::watch::channel(true);
+        running.insert(pod_key("default", "greet"), RunningSignal { tx, rx });
+        let recent_errors = RecentErrors::default();
+        recent_errors
+            .report(
+                &pod_key("default", "broken"),
+                &PodStatusReport::Failed("ImagePullBackOff".to_owned()),
+            )
+            .await
+            .unwrap();
+
+        let status = status_document(
+            &actors(),
+            &running,
+            0,
+            &port_map(),
+            recent_errors.list(),
+            Some(4096),
+        );
+
+        assert_eq!(
+            status["host"],
+            json!({ "up": true, "version": WASMCLOUD_HOST_VERSION, "resident_memory_bytes": 4096 })
+        );
+        assert_eq!(status["actors"], 1);
+        assert_eq!(
+            status["pods"],
+            json!({ "default/greet": { "phase": "Running", "actors": 1 } })
+        );
+        assert_eq!(
+            status["capabilities"][HTTP_CAPABILITY],
+            json!({
+                "version": capability_contract_version(HTTP_CAPABILITY),
+                "instances": 1,
+                "actors": 1,
+            })
+        );
+        assert_eq!(status["capabilities"][LOG_CAPABILITY]["actors"], 0);
+        assert_eq!(status["capabilities"][FS_CAPABILITY]["instances"], 0);
+        assert_eq!(
+            status["ports"],
+            json!({ "in_use": 1, "assigned": { "30000": "default/greet" } })
+        );
+        let errors = status["recent_errors"].as_array().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["pod"], "default/broken");
+        assert_eq!(errors[0]["error"], "ImagePullBackOff");
+        assert!(!status.to_string().contains(SECRET));
+    }
+
+    #[tokio::test]
+    async fn pod_not_yet_running_is_starting() {
+        let mut running = BTreeMap::new();
+        let (tx, rx) = tokio::sync::watch::channel(false);
+        running.insert(pod_key("default", "greet"), RunningSignal { tx, rx });
+        let status = status_document(&actors(), &running, 0, &BTreeMap::new(), vec![], None);
+        assert_eq!(status["pods"]["default/greet"]["phase"], "Starting");
+
+        let status = status_document(
+            &actors(),
+            &BTreeMap::new(),
+            0,
+            &BTreeMap::new(),
+            vec![],
+            None,
+        );
+        assert_eq!(status["pods"]["default/greet"]["phase"], "Starting");
+        assert_eq!(status["host"]["resident_memory_bytes"], json!(null));
+    }
+
+    #[test]
+    fn vm_rss_is_read_in_bytes() {
+        let status = "Name:\tkrustlet\nVmPeak:\t  204800 kB\nVmRSS:\t   10240 kB\nThreads:\t12\n";
+        assert_eq!(vm_rss_bytes(status), Some(10 * 1024 * 1024));
+        assert_eq!(vm_rss_bytes("Name:\tkrustlet\n"), None);
+        assert_eq!(vm_rss_bytes("VmRSS:\tlots kB\n"), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn resident_memory_is_known_on_linux() {
+        let memory = resident_memory_bytes().await.expect("resident memory");
+        assert!(memory > 0);
+    }
+
+    #[test]
+    fn claims_report_shape() {
+        let report = claims_report(&actors());
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].pod, pod_key("default", "greet"));
+        assert_eq!(report[0].container, "greet");
+        assert_eq!(report[0].claims.subject, "MACTOR");
+        assert_eq!(report[0].claims.capabilities, vec![HTTP_CAPABILITY]);
+    }
+
+    #[test]
+    fn claims_report_leaves_out_env() {
+        let report = claims_report(&actors());
+        let claims = serde_json::to_string(&report[0].claims).unwrap();
+        assert!(!claims.contains(SECRET), "got: {}", claims);
+        assert!(!format!("{:?}", report).contains(SECRET));
+    }
+
+    #[test]
+    fn port_allocations_lowest_first() {
+        let mut port_map = port_map();
+        port_map.insert(8080, pod_key("default", "hostnet"));
+        port_map.insert(30001, pod_key("other", "greet"));
+        assert_eq!(
+            port_allocations(&port_map),
+            vec![
+                (8080, pod_key("default", "hostnet")),
+                (30000, pod_key("default", "greet")),
+                (30001, pod_key("other", "greet")),
+            ]
+        );
+        assert!(port_allocations(&BTreeMap::new()).is_empty());
+    }
+
+    #[cfg(feature = "profiling")]
+    #[test]
+    fn profile_duration_is_bounded() {
+        use std::time::Duration;
+
+        assert!(check_profile_duration(Duration::from_millis(1)).is_ok());
+        assert!(check_profile_duration(MAX_PROFILE_DURATION).is_ok());
+        assert!(check_profile_duration(Duration::from_secs(0)).is_err());
+        // Less than a millisecond can't be sampled
+        assert!(check_profile_duration(Duration::from_micros(999)).is_err());
+        assert!(check_profile_duration(MAX_PROFILE_DURATION + Duration::from_secs(1)).is_err());
+    }
+}
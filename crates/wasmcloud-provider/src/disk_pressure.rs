@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use kubelet::pod::PodKey;
+use log::{debug, error, warn};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::RwLock;
+
+use crate::events::EventBus;
+use crate::{ActorInfo, ProviderEvent};
+
+/// How often the free space on the log disk is checked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Written at the start of a trimmed log so readers know records were dropped.
+const TRIMMED_MARKER: &str = "[log trimmed by the kubelet because the node is low on disk space]\n";
+
+/// A log file of a running actor.
+struct ActorLog {
+    pod: PodKey,
+    container: String,
+    path: PathBuf,
+    size: u64,
+}
+
+/// Watches the free space on the disk holding actor logs and, when it drops below `min_free`
+/// bytes, empties actor logs, largest first, until there is enough free space again.
+///
+/// Only the logs of running actors are trimmed. Each trimmed log is left with a marker line and
+/// reported as a [`ProviderEvent::LogTrimmed`]. The task runs for as long as the process does.
+pub(crate) fn spawn_monitor(
+    log_dir: PathBuf,
+    min_free: u64,
+    actors: Arc<RwLock<BTreeMap<PodKey, BTreeMap<String, ActorInfo>>>>,
+    events: Arc<EventBus>,
+) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(CHECK_INTERVAL).await;
+            let mut free = match fs2::available_space(&log_dir) {
+                Ok(free) => free,
+                Err(e) => {
+                    error!(
+                        "Unable to check free space for log directory {}: {}",
+                        log_dir.display(),
+                        e
+                    );
+                    continue;
+                }
+            };
+            if free >= min_free {
+                continue;
+            }
+            warn!(
+                "Only {} bytes free for log directory {}, below the {} byte threshold. Trimming actor logs",
+                free,
+                log_dir.display(),
+                min_free
+            );
+
+            let mut logs = actor_logs(&actors).await;
+            logs.sort_by(|a, b| b.size.cmp(&a.size));
+            for log in logs.into_iter().filter(|log| log.size > 0) {
+                if free >= min_free {
+                    break;
+                }
+                if let Err(e) = trim(&log).await {
+                    error!("Unable to trim log {}: {}", log.path.display(), e);
+                    continue;
+                }
+                warn!(
+                    "Trimmed {} bytes of logs of container {} of pod {} in namespace {}",
+                    log.size,
+                    log.container,
+                    log.pod.name(),
+                    log.pod.namespace()
+                );
+                free += log.size;
+                events.publish(ProviderEvent::LogTrimmed {
+                    pod: log.pod,
+                    container: log.container,
+                    bytes: log.size,
+                });
+            }
+            if free < min_free {
+                warn!(
+                    "Log directory {} is still below its free space threshold with every actor log trimmed",
+                    log_dir.display()
+                );
+            }
+        }
+    });
+}
+
+/// Every log file of the running actors, with its current size.
+async fn actor_logs(
+    actors: &RwLock<BTreeMap<PodKey, BTreeMap<String, ActorInfo>>>,
+) -> Vec<ActorLog> {
+    let paths: Vec<(PodKey, String, PathBuf)> = {
+        let actors = actors.read().await;
+        actors
+            .iter()
+            .flat_map(|(pod_key, containers)| {
+                containers.iter().flat_map(move |(name, actor)| {
+                    actor
+                        .log_path
+                        .iter()
+                        .chain(actor.log_stream_paths.values())
+                        .map(move |path| (pod_key.clone(), name.clone(), path.clone()))
+                })
+            })
+            .collect()
+    };
+    let mut logs = Vec::with_capacity(paths.len());
+    for (pod, container, path) in paths {
+        match tokio::fs::metadata(&path).await {
+            Ok(metadata) => logs.push(ActorLog {
+                pod,
+                container,
+                path,
+                size: metadata.len(),
+            }),
+            // The actor may have been stopped since its path was read
+            Err(e) => debug!("Unable to read size of log {}: {}", path.display(), e),
+        }
+    }
+    logs
+}
+
+/// Empties the log, leaving only the marker. The logging capability appends, so it carries on
+/// writing after the marker.
+async fn trim(log: &ActorLog) -> std::io::Result<()> {
+    let mut file = tokio::fs::OpenOptions::new()
+        .append(true)
+        .open(&log.path)
+        .await?;
+    file.set_len(0).await?;
+    file.write_all(TRIMMED_MARKER.as_bytes()).await
+}
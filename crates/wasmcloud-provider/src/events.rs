@@ -0,0 +1,79 @@
+use kubelet::pod::PodKey;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+
+use crate::status::{PodStatusReport, StatusReporter};
+
+/// How many events are held for a subscriber that hasn't caught up yet.
+const EVENT_BUFFER: usize = 256;
+
+/// Something that happened in the provider, see
+/// [`WasmCloudProvider::subscribe_events`](crate::WasmCloudProvider::subscribe_events).
+#[derive(Clone, Debug)]
+pub enum ProviderEvent {
+    /// A pod's status changed.
+    Pod {
+        /// The pod.
+        pod: PodKey,
+        /// Its new status.
+        status: PodStatusReport,
+    },
+    /// An actor was started and linked to its capabilities.
+    ActorStarted {
+        /// The pod the actor belongs to.
+        pod: PodKey,
+        /// The container the actor runs.
+        container: String,
+        /// The actor's public key.
+        actor: String,
+    },
+    /// A capability failed its self-test.
+    CapabilityDegraded {
+        /// The capability contract ID.
+        capability: String,
+        /// Why it failed.
+        error: String,
+    },
+    /// An actor's log was emptied because the node was low on disk space.
+    LogTrimmed {
+        /// The pod the actor belongs to.
+        pod: PodKey,
+        /// The container the actor runs.
+        container: String,
+        /// How many bytes of log were dropped.
+        bytes: u64,
+    },
+}
+
+/// Publishes [`ProviderEvent`]s to every subscriber.
+pub(crate) struct EventBus {
+    tx: broadcast::Sender<ProviderEvent>,
+}
+
+impl EventBus {
+    pub(crate) fn new() -> Self {
+        let (tx, _) = broadcast::channel(EVENT_BUFFER);
+        EventBus { tx }
+    }
+
+    pub(crate) fn publish(&self, event: ProviderEvent) {
+        // Sending only fails when nobody is subscribed
+        self.tx.send(event).ok();
+    }
+
+    pub(crate) fn subscribe(&self) -> BroadcastStream<ProviderEvent> {
+        BroadcastStream::new(self.tx.subscribe())
+    }
+}
+
+/// Pod status changes reach the bus the same way they reach any other reporter.
+#[async_trait::async_trait]
+impl StatusReporter for EventBus {
+    async fn report(&self, pod_key: &PodKey, status: &PodStatusReport) -> anyhow::Result<()> {
+        self.publish(ProviderEvent::Pod {
+            pod: pod_key.clone(),
+            status: status.clone(),
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,42 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use kubelet::store::{PullPolicy, Store};
+use oci_distribution::secrets::RegistryAuth;
+use oci_distribution::Reference;
+
+/// A [`Store`] that gives up on fetching an image after a set time, so a registry that has
+/// stopped answering fails the pull quickly instead of using up the pod's start deadline.
+///
+/// It wraps every other store, so the time covers everything done to fetch the image,
+/// including checking its signature.
+pub(crate) struct TimeoutStore {
+    inner: Arc<dyn Store + Sync + Send>,
+    timeout: Duration,
+}
+
+impl TimeoutStore {
+    pub(crate) fn new(inner: Arc<dyn Store + Sync + Send>, timeout: Duration) -> Self {
+        TimeoutStore { inner, timeout }
+    }
+}
+
+#[async_trait::async_trait]
+impl Store for TimeoutStore {
+    async fn get(
+        &self,
+        image_ref: &Reference,
+        pull_policy: PullPolicy,
+        auth: &RegistryAuth,
+    ) -> anyhow::Result<Vec<u8>> {
+        tokio::time::timeout(self.timeout, self.inner.get(image_ref, pull_policy, auth))
+            .await
+            .map_err(|_| {
+                anyhow::anyhow!(
+                    "ImagePullTimeout: image {} was not fetched within {:?}",
+                    image_ref,
+                    self.timeout
+                )
+            })?
+    }
+}
@@ -0,0 +1,150 @@
+use std::path::{Component, Path, PathBuf};
+
+use crate::{EnvVars, VolumeBinding};
+
+/// Link values starting with this are replaced with the contents of the named file, which
+/// must be in one of the container's mounted volumes, e.g. `file:/etc/secrets/token`.
+pub(crate) const FILE_VALUE_PREFIX: &str = "file:";
+
+/// Replaces every `file:` value with the contents of the file it names. This keeps secret
+/// material out of the pod's env, and so out of the API, while still passing it to the
+/// capabilities in the link configuration.
+///
+/// Contents are used exactly as read, including any trailing newline.
+pub(crate) async fn resolve_file_values(
+    mut env: EnvVars,
+    volumes: &[VolumeBinding],
+) -> anyhow::Result<EnvVars> {
+    for (key, value) in env.iter_mut() {
+        let path = match value.strip_prefix(FILE_VALUE_PREFIX) {
+            Some(path) => path,
+            None => continue,
+        };
+        let host_path = volume_path(path, volumes)
+            .map_err(|e| anyhow::anyhow!("Unable to read {} from {:?}: {}", key, value, e))?;
+        let contents = tokio::fs::read_to_string(&host_path).await.map_err(|e| {
+            anyhow::anyhow!(
+                "Unable to read {} from {:?}: {} ({})",
+                key,
+                value,
+                e,
+                host_path.display()
+            )
+        })?;
+        *value = contents;
+    }
+    Ok(env)
+}
+
+/// Maps a path in the container's view of its volumes to where it is on the node. The volume
+/// with the longest matching mount path wins, as it does for nested mounts in a container.
+fn volume_path(path: &str, volumes: &[VolumeBinding]) -> anyhow::Result<PathBuf> {
+    let path = Path::new(path);
+    if !path.is_absolute() {
+        return Err(anyhow::anyhow!("the path must be absolute"));
+    }
+    if path.components().any(|c| c == Component::ParentDir) {
+        return Err(anyhow::anyhow!("the path must not contain `..`"));
+    }
+    volumes
+        .iter()
+        .filter_map(|vol| {
+            path.strip_prefix(&vol.mount_path).ok().map(|rest| {
+                (
+                    vol.mount_path.components().count(),
+                    vol.host_path.join(rest),
+                )
+            })
+        })
+        .max_by_key(|(depth, _)| *depth)
+        .map(|(_, host_path)| host_path)
+        .ok_or_else(|| anyhow::anyhow!("the path is not in any volume mounted in the container"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn volume(host_path: &Path, mount_path: &str) -> VolumeBinding {
+        VolumeBinding {
+            name: "secrets".to_owned(),
+            host_path: host_path.to_owned(),
+            mount_path: PathBuf::from(mount_path),
+            allowed_ops: None,
+        }
+    }
+
+    fn env(values: &[(&str, &str)]) -> EnvVars {
+        values
+            .iter()
+            .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn file_values_are_read_from_mounted_volumes() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("token"), "hunter2\n").unwrap();
+        let volumes = vec![volume(dir.path(), "/etc/secrets")];
+
+        let resolved = resolve_file_values(
+            env(&[("TOKEN", "file:/etc/secrets/token"), ("ROOT", "/data")]),
+            &volumes,
+        )
+        .await
+        .unwrap();
+        assert_eq!(resolved["TOKEN"], "hunter2\n");
+        assert_eq!(resolved["ROOT"], "/data");
+    }
+
+    #[tokio::test]
+    async fn missing_file_is_a_clear_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let volumes = vec![volume(dir.path(), "/etc/secrets")];
+        let err = resolve_file_values(env(&[("TOKEN", "file:/etc/secrets/token")]), &volumes)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.starts_with("Unable to read TOKEN from \"file:/etc/secrets/token\""),
+            "{}",
+            err
+        );
+        assert!(
+            err.contains(&dir.path().join("token").display().to_string()),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn deepest_mount_wins() {
+        let volumes = vec![
+            volume(Path::new("/node/outer"), "/data"),
+            volume(Path::new("/node/inner"), "/data/nested"),
+        ];
+        assert_eq!(
+            volume_path("/data/nested/token", &volumes).unwrap(),
+            PathBuf::from("/node/inner/token")
+        );
+        assert_eq!(
+            volume_path("/data/token", &volumes).unwrap(),
+            PathBuf::from("/node/outer/token")
+        );
+    }
+
+    #[test]
+    fn paths_outside_the_volumes_are_refused() {
+        let volumes = vec![volume(Path::new("/node/secrets"), "/etc/secrets")];
+        for (path, reason) in &[
+            ("etc/secrets/token", "absolute"),
+            ("/etc/secrets/../passwd", "`..`"),
+            ("/etc/passwd", "not in any volume"),
+            // A mount path only matches whole components
+            ("/etc/secrets-other/token", "not in any volume"),
+        ] {
+            let err = volume_path(path, &volumes).unwrap_err().to_string();
+            assert!(err.contains(reason), "{}: {}", path, err);
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::sync::{Arc, RwLock};
+
+use wasmcloud_actor_core::CapabilityConfiguration;
+use wasmcloud_provider_core::capabilities::{CapabilityProvider, Dispatcher};
+use wasmcloud_provider_core::core::{OP_BIND_ACTOR, OP_REMOVE_ACTOR};
+use wasmcloud_provider_core::deserialize;
+
+/// The link value holding the comma separated blobstore operations an actor may call. All
+/// operations are allowed when it is missing.
+pub(crate) const ALLOWED_OPS_KEY: &str = "ALLOWED_OPS";
+
+/// The origin of calls made by the wasmCloud host itself.
+const SYSTEM_ACTOR: &str = "system";
+
+/// Wraps a blobstore capability provider, rejecting the operations an actor's link doesn't
+/// allow before they reach it.
+#[derive(Clone)]
+pub(crate) struct AllowlistProvider<P> {
+    inner: P,
+    allowed: Arc<RwLock<HashMap<String, HashSet<String>>>>,
+}
+
+impl<P> AllowlistProvider<P> {
+    pub(crate) fn new(inner: P) -> Self {
+        AllowlistProvider {
+            inner,
+            allowed: Default::default(),
+        }
+    }
+}
+
+impl<P: CapabilityProvider + Clone + 'static> CapabilityProvider for AllowlistProvider<P> {
+    fn configure_dispatch(
+        &self,
+        dispatcher: Box<dyn Dispatcher>,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        self.inner.configure_dispatch(dispatcher)
+    }
+
+    fn handle_call(
+        &self,
+        actor: &str,
+        op: &str,
+        msg: &[u8],
+    ) -> Result<Vec<u8>, Box<dyn Error + Sync + Send>> {
+        match (op, actor) {
+            (OP_BIND_ACTOR, SYSTEM_ACTOR) => {
+                let config = deserialize::<CapabilityConfiguration>(msg)?;
+                let mut allowed = self.allowed.write().unwrap_or_else(|e| e.into_inner());
+                match config.values.get(ALLOWED_OPS_KEY) {
+                    Some(ops) => {
+                        allowed.insert(
+                            config.module.clone(),
+                            ops.split(',')
+                                .map(str::trim)
+                                .filter(|op| !op.is_empty())
+                                .map(str::to_owned)
+                                .collect(),
+                        );
+                    }
+                    None => {
+                        allowed.remove(&config.module);
+                    }
+                }
+            }
+            (OP_REMOVE_ACTOR, SYSTEM_ACTOR) => {
+                let config = deserialize::<CapabilityConfiguration>(msg)?;
+                self.allowed
+                    .write()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .remove(&config.module);
+            }
+            (_, SYSTEM_ACTOR) => {}
+            _ => {
+                let allowed = self.allowed.read().unwrap_or_else(|e| e.into_inner());
+                if let Some(ops) = allowed.get(actor) {
+                    if !ops.contains(op) {
+                        return Err(format!(
+                            "Blobstore operation {} is not allowed for this volume (allowed: {})",
+                            op,
+                            ops.iter().cloned().collect::<Vec<_>>().join(", ")
+                        )
+                        .into());
+                    }
+                }
+            }
+        }
+        self.inner.handle_call(actor, op, msg)
+    }
+
+    fn stop(&self) {
+        self.inner.stop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Mutex;
+
+    use wasmcloud_provider_core::serialize;
+
+    /// A provider that records the operations that reach it.
+    #[derive(Clone, Default)]
+    struct Recorder {
+        ops: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl CapabilityProvider for Recorder {
+        fn configure_dispatch(
+            &self,
+            _dispatcher: Box<dyn Dispatcher>,
+        ) -> Result<(), Box<dyn Error + Sync + Send>> {
+            Ok(())
+        }
+
+        fn handle_call(
+            &self,
+            _actor: &str,
+            op: &str,
+            _msg: &[u8],
+        ) -> Result<Vec<u8>, Box<dyn Error + Sync + Send>> {
+            self.ops.lock().unwrap().push(op.to_owned());
+            Ok(vec![])
+        }
+
+        fn stop(&self) {}
+    }
+
+    fn link(provider: &AllowlistProvider<Recorder>, op: &str, allowed_ops: Option<&str>) {
+        let config = CapabilityConfiguration {
+            module: "MACTOR".to_owned(),
+            values: allowed_ops
+                .map(|ops| (ALLOWED_OPS_KEY.to_owned(), ops.to_owned()))
+                .into_iter()
+                .collect(),
+        };
+        provider
+            .handle_call(SYSTEM_ACTOR, op, &serialize(config).unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn only_allowed_ops_reach_the_provider() {
+        let recorder = Recorder::default();
+        let provider = AllowlistProvider::new(recorder.clone());
+        link(
+            &provider,
+            OP_BIND_ACTOR,
+            Some("GetObjectInfo, StartDownload,"),
+        );
+
+        provider
+            .handle_call("MACTOR", "StartDownload", &[])
+            .unwrap();
+        let err = provider
+            .handle_call("MACTOR", "RemoveObject", &[])
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("RemoveObject is not allowed"),
+            "{}",
+            err
+        );
+        // Other actors linked to the same provider aren't restricted
+        provider.handle_call("MOTHER", "RemoveObject", &[]).unwrap();
+
+        assert_eq!(
+            *recorder.ops.lock().unwrap(),
+            vec![OP_BIND_ACTOR, "StartDownload", "RemoveObject"]
+        );
+    }
+
+    #[test]
+    fn all_ops_allowed_without_an_allowlist() {
+        let provider = AllowlistProvider::new(Recorder::default());
+        link(&provider, OP_BIND_ACTOR, None);
+        provider.handle_call("MACTOR", "RemoveObject", &[]).unwrap();
+    }
+
+    #[test]
+    fn allowlist_goes_with_the_link() {
+        let provider = AllowlistProvider::new(Recorder::default());
+        link(&provider, OP_BIND_ACTOR, Some("GetObjectInfo"));
+        assert!(provider.handle_call("MACTOR", "RemoveObject", &[]).is_err());
+
+        link(&provider, OP_REMOVE_ACTOR, None);
+        provider.handle_call("MACTOR", "RemoveObject", &[]).unwrap();
+
+        link(&provider, OP_BIND_ACTOR, Some("GetObjectInfo"));
+        link(&provider, OP_BIND_ACTOR, None);
+        provider.handle_call("MACTOR", "RemoveObject", &[]).unwrap();
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::{BTreeMap, HashMap};
+
+use kubelet::pod::{Pod, PodKey};
+use tokio::sync::Mutex;
+
+/// Caps how many actors of an image run on the node at once, see
+/// [`WasmCloudConfig::image_instance_limits`](crate::WasmCloudConfig::image_instance_limits).
+///
+/// A pod claims an instance for each of its containers when it starts and holds them until it
+/// is deleted, the same as its host ports, so a pod restarting after a failure never loses its
+/// place to another.
+pub(crate) struct ImageInstances {
+    /// The most instances of each limited image, keyed by its whole reference.
+    limits: HashMap<String, usize>,
+    /// The limited images each pod holds instances of, one entry per container.
+    claims: Mutex<BTreeMap<PodKey, Vec<String>>>,
+}
+
+impl ImageInstances {
+    pub(crate) fn new(limits: HashMap<String, usize>) -> Self {
+        ImageInstances {
+            limits,
+            claims: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Claims an instance of each limited image the pod runs, replacing anything it claimed
+    /// before, or claims nothing and fails with `ImageInstanceLimitExceeded` if that would take
+    /// an image over its limit.
+    pub(crate) async fn claim(&self, pod: &Pod) -> anyhow::Result<()> {
+        if self.limits.is_empty() {
+            return Ok(());
+        }
+        let mut images = vec![];
+        for container in pod.containers() {
+            if let Some(image) = container.image()? {
+                let image = image.whole();
+                if self.limits.contains_key(&image) {
+                    images.push(image);
+                }
+            }
+        }
+
+        let pod_key = PodKey::from(pod);
+        let mut claims = self.claims.lock().await;
+        for (image, limit) in &self.limits {
+            let wanted = images.iter().filter(|i| *i == image).count();
+            if wanted == 0 {
+                continue;
+            }
+            let held_by_others: usize = claims
+                .iter()
+                .filter(|(key, _)| **key != pod_key)
+                .map(|(_, held)| held.iter().filter(|i| *i == image).count())
+                .sum();
+            if held_by_others + wanted > *limit {
+                return Err(anyhow::anyhow!(
+                    "ImageInstanceLimitExceeded: pod {} needs {} instance(s) of image {}, but {} of the {} allowed on this node are already running",
+                    pod.name(),
+                    wanted,
+                    image,
+                    held_by_others,
+                    limit
+                ));
+            }
+        }
+        if images.is_empty() {
+            claims.remove(&pod_key);
+        } else {
+            claims.insert(pod_key, images);
+        }
+        Ok(())
+    }
+
+    /// Releases every instance the pod holds.
+    pub(crate) async fn release(&self, pod_key: &PodKey) {
+        self.claims.lock().await.remove(pod_key);
+    }
+}
@@ -0,0 +1,131 @@
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+use kubelet::store::{PullPolicy, Store};
+use log::debug;
+use oci_distribution::secrets::RegistryAuth;
+use oci_distribution::Reference;
+
+/// A [`Store`] that rewrites image references by prefix before fetching them, for example to
+/// pull everything from `docker.io/` through `mirror.internal/`.
+///
+/// Only the fetch sees the rewritten reference. The pod spec, and so everything reported from
+/// it, keeps the original.
+pub(crate) struct RewritingStore {
+    inner: Arc<dyn Store + Sync + Send>,
+    /// `(from, to)` prefix pairs. The first matching prefix wins.
+    rewrites: Vec<(String, String)>,
+}
+
+impl RewritingStore {
+    pub(crate) fn new(
+        inner: Arc<dyn Store + Sync + Send>,
+        rewrites: Vec<(String, String)>,
+    ) -> Self {
+        RewritingStore { inner, rewrites }
+    }
+
+    fn rewrite(&self, image_ref: &Reference) -> anyhow::Result<Option<Reference>> {
+        let whole = image_ref.whole();
+        let (from, to) = match self
+            .rewrites
+            .iter()
+            .find(|(from, _)| whole.starts_with(from.as_str()))
+        {
+            Some(rewrite) => rewrite,
+            None => return Ok(None),
+        };
+        let rewritten = format!("{}{}", to, &whole[from.len()..]);
+        debug!("Rewrote image reference {} to {}", whole, rewritten);
+        Reference::try_from(rewritten.clone())
+            .map(Some)
+            .map_err(|e| {
+                anyhow::anyhow!(
+                    "Image reference {} was rewritten to invalid reference {}: {}",
+                    whole,
+                    rewritten,
+                    e
+                )
+            })
+    }
+}
+
+#[async_trait::async_trait]
+impl Store for RewritingStore {
+    async fn get(
+        &self,
+        image_ref: &Reference,
+        pull_policy: PullPolicy,
+        auth: &RegistryAuth,
+    ) -> anyhow::Result<Vec<u8>> {
+        match self.rewrite(image_ref)? {
+            Some(rewritten) => self.inner.get(&rewritten, pull_policy, auth).await,
+            None => self.inner.get(image_ref, pull_policy, auth).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fetch, RecordingStore};
+
+    fn rewriting(rewrites: &[(&str, &str)]) -> (Arc<RecordingStore>, RewritingStore) {
+        let inner = Arc::new(RecordingStore::default());
+        let store = RewritingStore::new(
+            inner.clone(),
+            rewrites
+                .iter()
+                .map(|(from, to)| ((*from).to_owned(), (*to).to_owned()))
+                .collect(),
+        );
+        (inner, store)
+    }
+
+    #[tokio::test]
+    async fn matching_prefix_is_rewritten_before_fetch() {
+        let (inner, store) = rewriting(&[
+            ("docker.io/", "mirror.internal/"),
+            ("docker.io/library/", "other.internal/"),
+        ]);
+        fetch(&store, "docker.io/library/greet:v1").await.unwrap();
+        fetch(&store, "ghcr.io/wasmcloud/echo:v1").await.unwrap();
+        assert_eq!(
+            *inner.fetched.lock().unwrap(),
+            vec![
+                "mirror.internal/library/greet:v1",
+                "ghcr.io/wasmcloud/echo:v1"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn original_reference_is_left_alone() {
+        let (_, store) = rewriting(&[("docker.io/", "mirror.internal/")]);
+        let image_ref = Reference::try_from("docker.io/library/greet:v1".to_owned()).unwrap();
+        store
+            .get(
+                &image_ref,
+                PullPolicy::IfNotPresent,
+                &RegistryAuth::Anonymous,
+            )
+            .await
+            .unwrap();
+        assert_eq!(image_ref.whole(), "docker.io/library/greet:v1");
+    }
+
+    #[tokio::test]
+    async fn invalid_rewrite_is_an_error() {
+        let (inner, store) = rewriting(&[("docker.io/", "mirror internal/")]);
+        let err = fetch(&store, "docker.io/library/greet:v1")
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("Image reference docker.io/library/greet:v1 was rewritten to invalid reference mirror internal/library/greet:v1"),
+            "{}",
+            err
+        );
+        assert!(inner.fetched.lock().unwrap().is_empty());
+    }
+}
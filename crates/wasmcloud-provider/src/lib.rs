@@ -48,22 +48,71 @@ use kubelet::state::common::{GenericProvider, GenericProviderState};
 use kubelet::store::Store;
 use kubelet::volume::Ref;
 
-use log::{debug, info, trace};
-use tempfile::NamedTempFile;
-use tokio::sync::{Mutex, RwLock};
+use log::{debug, error, info, trace, warn};
+use serde_derive::Serialize;
+use tokio::sync::{watch, Mutex, RwLock};
+use tokio_stream::wrappers::BroadcastStream;
 use wascap::jwt::{CapabilityProvider, Claims};
 use wasmcloud_fs::FileSystemProvider;
 use wasmcloud_host::{Actor, Host, HostBuilder, NativeCapability};
 use wasmcloud_httpserver::HttpServerProvider;
-use wasmcloud_logging::{LoggingProvider, LOG_PATH_KEY};
+use wasmcloud_logging::{LoggingProvider, MemoryLog, LOG_PATH_STREAM_PREFIX};
 
 use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
+mod audit;
+mod config;
+mod config_maps;
+mod cosign;
+mod dev_dir;
+mod diagnostics;
+mod disk_pressure;
+mod events;
+mod fetch_timeout;
+mod file_values;
+mod fs_allowlist;
+mod image_limits;
+mod image_rewrite;
+mod logs;
+mod per_actor;
+mod rate_limit;
+mod redact;
+mod registry_allowlist;
+mod self_test;
+mod services;
 mod states;
+mod status;
+#[cfg(test)]
+mod test_support;
+mod tolerations;
+mod volume_swap;
 
+use audit::{AuditAction, AuditLog};
+pub use config::{CapabilityDefaults, NoCapabilities, SelfTest, WasmCloudConfig};
+use config_maps::ConfigMapWatch;
+use cosign::VerifyingStore;
+use dev_dir::DevDirStore;
+pub use diagnostics::{ActorClaims, ActorClaimsReport};
+use events::EventBus;
+pub use events::ProviderEvent;
+use fetch_timeout::TimeoutStore;
+use fs_allowlist::{AllowlistProvider, ALLOWED_OPS_KEY};
+use image_limits::ImageInstances;
+use image_rewrite::RewritingStore;
+use logs::{LogHandleFactory, LogOutput, LogStorage, LogStreams};
+use per_actor::PerActorProvider;
+use rate_limit::RateLimiter;
+use redact::Redactor;
+use registry_allowlist::AllowlistStore;
+pub use self_test::SelfTestResult;
+use services::ServiceWatch;
 use states::pod::PodState;
+use states::pod::Quarantine;
+use status::RecentErrors;
+pub use status::{PodStatusReport, StatusReporter};
 
 /// The architecture that the pod targets.
 const TARGET_WASM32_WASMCLOUD: &str = "wasm32-wasmcloud";
@@ -77,7 +126,7 @@ const HTTP_CAPABILITY: &str = "wasmcloud:httpserver";
 /// The name of the Logging capability.
 const LOG_CAPABILITY: &str = "wasmcloud:logging";
 
-/// The root directory of wasmCloud logs.
+/// The root directory of wasmCloud logs. Each namespace gets its own subdirectory.
 const LOG_DIR_NAME: &str = "wasmcloud-logs";
 
 /// The key used to define the root directory of the Filesystem capability.
@@ -86,87 +135,474 @@ const FS_CONFIG_ROOTDIR: &str = "ROOT";
 /// The root directory of wasmCloud volumes.
 const VOLUME_DIR: &str = "volumes";
 
+/// The pod annotation used to request host network port binding (the actor's HTTP capability
+/// binds the container port directly on the node). Equivalent to setting `spec.hostNetwork`.
+const HOST_NETWORK_ANNOTATION: &str = "wasmcloud.dev/host-network";
+
+/// The pod annotation listing, comma separated, the container names to start one at a time
+/// and in order. Each container is started once the previous one's actor is running.
+const START_ORDER_ANNOTATION: &str = "wasmcloud.dev/start-order";
+
+/// The pod annotation listing, comma separated, the Services its actors should be told about.
+/// Entries are a Service name in the pod's namespace or `namespace/name`.
+const SERVICES_ANNOTATION: &str = "wasmcloud.dev/services";
+
+/// The pod annotation giving the minimum wasmCloud host version, as `major.minor.patch`, its
+/// actors need. Pods asking for a newer host than [`WASMCLOUD_HOST_VERSION`] are refused.
+const MIN_HOST_VERSION_ANNOTATION: &str = "wasmcloud.dev/min-host-version";
+
+/// The prefix of the pod annotations restricting the blobstore operations an actor may call on
+/// a volume, e.g. `wasmcloud.dev/blobstore-ops-storage: StartDownload,ListObjects,GetObjectInfo`
+/// for the volume `storage`. Volumes without one allow every operation.
+const BLOBSTORE_OPS_ANNOTATION_PREFIX: &str = "wasmcloud.dev/blobstore-ops-";
+
+/// The pod annotation asking, with `true`, for capability provider instances that aren't
+/// shared with other pods. FS providers already keep each actor's volumes apart (see
+/// [`FsProviders`]), but the HTTP and logging providers are single instances per host, so the pod
+/// is given a host of its own, as with [`DEDICATED_HOST_ANNOTATION`].
+const ISOLATE_CAPABILITIES_ANNOTATION: &str = "wasmcloud.dev/isolate-capabilities";
+
+/// The pod annotation asking, with `true`, for the pod's actors to run in a wasmCloud host of
+/// their own, with their own HTTP, log and FS providers, so a failure in that host can't affect
+/// other pods. Each dedicated host costs the memory of a whole host, its engine and providers,
+/// on top of the actors. The process total in [`WasmCloudProvider::status`] shows the cost.
+const DEDICATED_HOST_ANNOTATION: &str = "wasmcloud.dev/dedicated-host";
+
+/// The pod annotation keeping only one in N of its actors' `info`, `debug` and `trace` log
+/// records. A `LOG_SAMPLE_RATE` set on a container's env wins.
+const LOG_SAMPLE_RATE_ANNOTATION: &str = "wasmcloud.dev/log-sample-rate";
+
+/// The pod annotation giving, comma separated as `capability=major.minor.patch`, the version
+/// of each capability's contract (its actor interface crate) the pod's actors were built
+/// against, e.g. `wasmcloud:httpserver=0.1.0`. Pods needing a contract version this node's
+/// providers aren't compatible with are refused, see [`capability_contract_version`].
+const CAPABILITY_VERSIONS_ANNOTATION: &str = "wasmcloud.dev/capability-versions";
+
+/// The pod annotation choosing where its actors' logs are kept, `file` or `memory`, instead of
+/// the node's choice. A pod asking for memory on a node that keeps logs in files gets a ring of
+/// [`DEFAULT_MEMORY_LOG_BYTES`](logs::DEFAULT_MEMORY_LOG_BYTES) per actor. A node that keeps
+/// logs in memory may have no writable disk, so it refuses pods asking for files.
+const LOG_STORAGE_ANNOTATION: &str = "wasmcloud.dev/log-storage";
+
+/// The pod annotation asking for its actors to run on specific CPU cores. The wasmCloud host
+/// runs every actor on threads it shares between them, so pods using it are refused.
+const CPUSET_ANNOTATION: &str = "wasmcloud.dev/cpuset";
+
+/// The version of the embedded wasmCloud host. Keep this in sync with the `wasmcloud-host`
+/// requirement in Cargo.toml.
+const WASMCLOUD_HOST_VERSION: &str = "0.16.0";
+
+/// The version of the contract each built-in capability provider implements, that is of the
+/// actor interface crate it is built with. Keep these in sync with Cargo.lock.
+fn capability_contract_version(capability: &str) -> Option<&'static str> {
+    match capability {
+        FS_CAPABILITY => Some("0.2.0"),
+        HTTP_CAPABILITY => Some("0.1.1"),
+        LOG_CAPABILITY => Some("0.1.1"),
+        _ => None,
+    }
+}
+
 /// Kubernetes' view of environment variables is an unordered map of string to string.
 type EnvVars = std::collections::HashMap<String, String>;
 
+/// The env key holding the port the HTTP capability listens on. Users may also set it in the
+/// container's env to ask for a specific port.
+const PORT_KEY: &str = "PORT";
+
+/// The env key holding the directory a container's capabilities keep durable state in.
+const STATE_DIR_KEY: &str = "STATE_DIR";
+
+/// The env key listing, comma separated, the named log streams an actor writes besides its main
+/// log. Records with a stream's name as their target go to that stream's file.
+const LOG_STREAMS_KEY: &str = "LOG_STREAMS";
+
+/// The env key holding the pod's `dnsPolicy`.
+const DNS_POLICY_KEY: &str = "DNS_POLICY";
+
+/// The env key holding the pod's `dnsConfig.nameservers` as a JSON array.
+const DNS_SERVERS_KEY: &str = "DNS_SERVERS";
+
+/// The env key holding the pod's `dnsConfig.searches` as a JSON array.
+const DNS_SEARCHES_KEY: &str = "DNS_SEARCHES";
+
+/// The env key holding the pod's `dnsConfig.options` as a JSON array of `{name, value}` objects.
+const DNS_OPTIONS_KEY: &str = "DNS_OPTIONS";
+
+/// The scheduler Kubernetes assigns to pods that don't name one.
+const DEFAULT_SCHEDULER_NAME: &str = "default-scheduler";
+
+/// Checks that the pod was scheduled by one of the schedulers this node serves. An empty list
+/// serves them all.
+fn check_scheduler_name(pod: &Pod, scheduler_names: &[String]) -> anyhow::Result<()> {
+    if scheduler_names.is_empty() {
+        return Ok(());
+    }
+    let scheduler_name = pod
+        .as_kube_pod()
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.scheduler_name.as_deref())
+        .unwrap_or(DEFAULT_SCHEDULER_NAME);
+    if scheduler_names.iter().any(|n| n == scheduler_name) {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "Cannot run {}: it was scheduled by {} but this node only serves pods from {}",
+            pod.name(),
+            scheduler_name,
+            scheduler_names.join(", ")
+        ))
+    }
+}
+
+/// Checks that the pod either names no runtime class or one of those meaning this runtime.
+fn check_runtime_class(pod: &Pod, runtime_class_names: &[String]) -> anyhow::Result<()> {
+    let runtime_class = match pod
+        .as_kube_pod()
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.runtime_class_name.as_deref())
+    {
+        Some(runtime_class) if !runtime_class.is_empty() => runtime_class,
+        _ => return Ok(()),
+    };
+    if runtime_class_names.iter().any(|n| n == runtime_class) {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "Cannot run {}: it asks for runtime class {} but this node's wasmCloud runtime is {}",
+            pod.name(),
+            runtime_class,
+            runtime_class_names.join(", ")
+        ))
+    }
+}
+
+/// The resources every container may request. Anything else must be allowed in the provider
+/// configuration.
+const SUPPORTED_RESOURCES: &[&str] = &["cpu", "memory"];
+
+/// Checks that the pod's containers only request, or set limits for, resources this node can
+/// provide.
+fn check_resources(pod: &Pod, allowed_resources: &[String]) -> anyhow::Result<()> {
+    let containers = pod
+        .as_kube_pod()
+        .spec
+        .as_ref()
+        .map(|spec| spec.containers.as_slice())
+        .unwrap_or_default();
+    for container in containers {
+        let resources = match &container.resources {
+            Some(resources) => resources,
+            None => continue,
+        };
+        let names = resources
+            .requests
+            .iter()
+            .chain(resources.limits.iter())
+            .flat_map(|quantities| quantities.keys());
+        for name in names {
+            if !SUPPORTED_RESOURCES.contains(&name.as_str())
+                && !allowed_resources.iter().any(|r| r == name)
+            {
+                return Err(anyhow::anyhow!(
+                    "Cannot run {}: container {} requests resource {} which this node does not provide",
+                    pod.name(),
+                    container.name,
+                    name
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks that no container mounts a volume when the FS capability, which is how actors see
+/// volumes, is disabled.
+fn check_fs_disabled(pod: &Pod, fs_disabled: bool) -> anyhow::Result<()> {
+    if !fs_disabled {
+        return Ok(());
+    }
+    match pod
+        .containers()
+        .iter()
+        .find(|c| c.volume_mounts().as_ref().map_or(false, |m| !m.is_empty()))
+    {
+        Some(container) => Err(anyhow::anyhow!(
+            "Cannot run {}: container {} mounts a volume, but the {} capability that gives actors volumes is disabled on this node",
+            pod.name(),
+            container.name(),
+            FS_CAPABILITY
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Checks an actor that declares no capabilities against the `no_capabilities` policy. Returns
+/// the warning to give when the actor may start anyway.
+fn check_capabilities(
+    pk: &str,
+    actor_caps: &[String],
+    no_capabilities: NoCapabilities,
+) -> anyhow::Result<Option<String>> {
+    if !actor_caps.is_empty() {
+        return Ok(None);
+    }
+    let message = format!(
+        "Actor {} declares no capabilities, so it can't be called or do anything. It was likely not built with the wasmCloud actor SDK or was signed without capability claims",
+        pk
+    );
+    match no_capabilities {
+        NoCapabilities::Fail => Err(anyhow::anyhow!(message)),
+        NoCapabilities::Warn => Ok(Some(message)),
+    }
+}
+
+/// Checks that every volume a container mounts is declared in the pod's volumes, so a typo in
+/// a mount is reported before anything is pulled.
+fn check_volume_mounts(pod: &Pod) -> anyhow::Result<()> {
+    let declared: Vec<&str> = pod
+        .as_kube_pod()
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.volumes.as_ref())
+        .map(|volumes| volumes.iter().map(|v| v.name.as_str()).collect())
+        .unwrap_or_default();
+    for container in pod.containers() {
+        if let Some(mount) = container
+            .volume_mounts()
+            .iter()
+            .flatten()
+            .find(|m| !declared.contains(&m.name.as_str()))
+        {
+            return Err(anyhow::anyhow!(
+                "Cannot run {}: container {} mounts volume {}, which is not one of the pod's volumes",
+                pod.name(),
+                container.name(),
+                mount.name
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Returns a container's state directory under `base`. It depends only on the pod's namespace
+/// and name and the container's name, so it is the same every time the pod is recreated, while
+/// no two containers share one. Kubernetes names can't contain `/`, so they can't escape `base`.
+fn state_dir(base: &Path, pod_key: &PodKey, container: &str) -> PathBuf {
+    base.join(pod_key.namespace())
+        .join(pod_key.name())
+        .join(container)
+}
+
+/// Returns the pod's resolver settings as env entries.
+///
+/// Actors don't resolve names themselves, so these are only hints passed along with every
+/// capability link. Whether they are honored is up to the capability provider making the
+/// outbound call.
+fn dns_env(pod: &Pod) -> anyhow::Result<EnvVars> {
+    let mut env = EnvVars::new();
+    let spec = match pod.as_kube_pod().spec.as_ref() {
+        Some(spec) => spec,
+        None => return Ok(env),
+    };
+    if let Some(policy) = &spec.dns_policy {
+        env.insert(DNS_POLICY_KEY.to_owned(), policy.clone());
+    }
+    if let Some(dns_config) = &spec.dns_config {
+        if let Some(nameservers) = &dns_config.nameservers {
+            env.insert(
+                DNS_SERVERS_KEY.to_owned(),
+                serde_json::to_string(nameservers)?,
+            );
+        }
+        if let Some(searches) = &dns_config.searches {
+            env.insert(
+                DNS_SEARCHES_KEY.to_owned(),
+                serde_json::to_string(searches)?,
+            );
+        }
+        if let Some(options) = &dns_config.options {
+            env.insert(DNS_OPTIONS_KEY.to_owned(), serde_json::to_string(options)?);
+        }
+    }
+    Ok(env)
+}
+
 /// A [kubelet::handle::StopHandler] implementation for a wasmCloud actor
 pub struct ActorHandle {
     /// The public key of the wasmCloud Actor that will be stopped
     pub key: String,
     host: Arc<Mutex<Host>>,
-    volumes: Vec<VolumeBinding>,
-    capabilities: Vec<String>,
+    /// The capability links made for the actor, each with its binding name.
+    links: Vec<(&'static str, Option<String>)>,
+    pod_key: PodKey,
+    audit: Arc<AuditLog>,
+    redactor: Redactor,
+    service_refresh: Option<tokio::task::JoinHandle<()>>,
+    config_reload: Option<tokio::task::JoinHandle<()>>,
+    fs_providers: Arc<FsProviders>,
+    /// The named log stream files, removed when the actor is.
+    _log_stream_files: Vec<tempfile::NamedTempFile>,
 }
 
 #[async_trait::async_trait]
 impl StopHandler for ActorHandle {
     async fn stop(&mut self) -> anyhow::Result<()> {
         debug!("stopping wasmcloud instance {}", self.key);
+        if let Some(refresh) = self.service_refresh.take() {
+            refresh.abort();
+        }
+        if let Some(reload) = self.config_reload.take() {
+            reload.abort();
+        }
         let host = self.host.clone();
-        let key = self.key.clone();
-        let volumes: Vec<VolumeBinding> = self.volumes.drain(0..).collect();
-
+        let links: Vec<(&'static str, Option<String>)> = self.links.drain(0..).collect();
         let lock = host.lock().await;
+        self.teardown(&*lock, links).await
+    }
 
+    async fn wait(&mut self) -> anyhow::Result<()> {
+        // the `stop_actor` call should handle this ok, so we just return Ok
+        Ok(())
+    }
+}
+
+impl ActorHandle {
+    /// Unlinks every capability the actor was linked to, stopping the FS providers no longer
+    /// used, then stops the actor.
+    async fn teardown(
+        &self,
+        host: &(dyn ActorHost + Sync),
+        links: Vec<(&'static str, Option<String>)>,
+    ) -> anyhow::Result<()> {
+        let key = &self.key;
+        let host_id = host.id();
         // NOTE: Not running these in parallel because the host is behind a mutex. None of these
         // calls are `&mut self`, so I think we might be able to make it just a plain `Arc` instead
         // if it starts taking a while to stop actors
+        //
+        // A failure doesn't end the stop, as every link left behind keeps its provider bound to
+        // an actor that is gone. They are all reported at the end instead
+        let mut errors: Vec<anyhow::Error> = Vec::new();
         debug!("Removing capability links");
-        for cap in self.capabilities.iter() {
-            trace!("Attempting to remove link for {} capability", cap);
-            match cap.as_str() {
-                FS_CAPABILITY => {
-                    for volume in volumes.iter() {
-                        lock.stop_provider(
-                            FS_CAPABILITY_PUBKEY,
-                            FS_CAPABILITY,
-                            Some(volume.name.clone()),
-                        )
-                        .await
-                        .map_err(|e| {
-                            anyhow::anyhow!(
-                                "unable to remove volume {:?} capability: {:?}",
-                                volume.name,
-                                e
-                            )
-                        })?;
-
-                        lock.remove_link(&key, FS_CAPABILITY, Some(volume.name.clone()))
-                            .await
-                            .map_err(|e| {
-                                anyhow::anyhow!(
-                                    "unable to unlink volume {:?} capability: {:?}",
-                                    volume.name,
-                                    e
-                                )
-                            })?;
+        for (capability, binding) in links {
+            trace!("Attempting to remove link for {} capability", capability);
+            let result = host
+                .remove_link(key, capability, binding.clone())
+                .await
+                .map_err(|e| {
+                    anyhow::anyhow!(
+                        "unable to unlink {} capability {:?}: {}",
+                        capability,
+                        binding,
+                        e
+                    )
+                });
+            self.audit.record(
+                AuditAction::Unlink,
+                &host_id,
+                &self.pod_key,
+                key,
+                Some((capability, binding.as_deref())),
+                &result,
+                &self.redactor,
+            );
+            if let Err(e) = result {
+                errors.push(e);
+            }
+
+            // Each volume name has its own FS provider, stopped once no actor is linked to it
+            if let (FS_CAPABILITY, Some(volume)) = (capability, &binding) {
+                if self.fs_providers.release(volume, key).await {
+                    if let Err(e) = host.stop_fs_provider(volume).await {
+                        errors.push(anyhow::anyhow!(
+                            "unable to remove volume {:?} capability: {}",
+                            volume,
+                            e
+                        ));
                     }
                 }
-                HTTP_CAPABILITY => {
-                    lock.remove_link(&key, HTTP_CAPABILITY, None)
-                        .await
-                        .map_err(|e| {
-                            anyhow::anyhow!("unable to unlink http capability: {:?}", e)
-                        })?;
-                }
-                LOG_CAPABILITY => {
-                    lock.remove_link(&key, LOG_CAPABILITY, None)
-                        .await
-                        .map_err(|e| anyhow::anyhow!("unable to unlink log capability: {:?}", e))?;
-                }
-                _ => info!("Found unmanged capability {}. Skipping", cap),
             }
         }
-        lock.stop_actor(&key)
+        let result = host
+            .stop_actor(key)
             .await
-            .map_err(|e| anyhow::anyhow!("unable to remove actor: {:?}", e))?;
+            .map_err(|e| anyhow::anyhow!("unable to remove actor: {}", e));
+        self.audit.record(
+            AuditAction::Stop,
+            &host_id,
+            &self.pod_key,
+            key,
+            None,
+            &result,
+            &self.redactor,
+        );
+        if let Err(e) = result {
+            errors.push(e);
+        }
 
-        Ok(())
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            Err(anyhow::anyhow!(
+                "stopping actor {} failed: {}",
+                key,
+                messages.join("; ")
+            ))
+        }
     }
+}
 
-    async fn wait(&mut self) -> anyhow::Result<()> {
-        // the `stop_actor` call should handle this ok, so we just return Ok
-        Ok(())
+/// The calls to the wasmCloud host that stopping an actor makes.
+#[async_trait::async_trait]
+trait ActorHost {
+    /// The host's id, as recorded in the audit trail.
+    fn id(&self) -> String;
+    async fn remove_link(
+        &self,
+        actor: &str,
+        capability: &str,
+        binding: Option<String>,
+    ) -> anyhow::Result<()>;
+    /// Stops the FS provider serving the volume binding.
+    async fn stop_fs_provider(&self, binding: &str) -> anyhow::Result<()>;
+    async fn stop_actor(&self, actor: &str) -> anyhow::Result<()>;
+}
+
+#[async_trait::async_trait]
+impl ActorHost for Host {
+    fn id(&self) -> String {
+        Host::id(self)
+    }
+
+    async fn remove_link(
+        &self,
+        actor: &str,
+        capability: &str,
+        binding: Option<String>,
+    ) -> anyhow::Result<()> {
+        Host::remove_link(self, actor, capability, binding)
+            .await
+            .map_err(|e| anyhow::anyhow!("{:?}", e))
+    }
+
+    async fn stop_fs_provider(&self, binding: &str) -> anyhow::Result<()> {
+        self.stop_provider(
+            FS_CAPABILITY_PUBKEY,
+            FS_CAPABILITY,
+            Some(binding.to_owned()),
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("{:?}", e))
+    }
+
+    async fn stop_actor(&self, actor: &str) -> anyhow::Result<()> {
+        Host::stop_actor(self, actor)
+            .await
+            .map_err(|e| anyhow::anyhow!("{:?}", e))
     }
 }
 
@@ -187,10 +623,142 @@ pub struct ProviderState {
     handles: Arc<RwLock<BTreeMap<PodKey, Handle<ActorHandle, LogHandleFactory>>>>,
     store: Arc<dyn Store + Sync + Send>,
     volume_path: PathBuf,
-    log_path: PathBuf,
+    log_storage: LogStorage,
     host: Arc<Mutex<Host>>,
     port_map: Arc<Mutex<BTreeMap<u16, PodKey>>>,
+    actors: Arc<RwLock<BTreeMap<PodKey, BTreeMap<String, ActorInfo>>>>,
     plugin_registry: Arc<PluginRegistry>,
+    api_rate_limiter: Arc<RateLimiter>,
+    audit: Arc<AuditLog>,
+    running: Arc<Mutex<BTreeMap<PodKey, RunningSignal>>>,
+    keep_failed: Option<Duration>,
+    no_capabilities: NoCapabilities,
+    scheduler_names: Arc<Vec<String>>,
+    fs_providers: Arc<FsProviders>,
+    log_streams: Arc<LogStreams>,
+    start_deadline: Option<Duration>,
+    status_reporters: Arc<Vec<Arc<dyn StatusReporter>>>,
+    recent_errors: Arc<RecentErrors>,
+    capability_defaults: Arc<CapabilityDefaults>,
+    quarantine: Option<Quarantine>,
+    events: Arc<EventBus>,
+    state_dir: Option<PathBuf>,
+    allowed_resources: Arc<Vec<String>>,
+    fs_disabled: bool,
+    sensitive_env: Arc<Vec<String>>,
+    link_retries: u32,
+    runtime_class_names: Arc<Vec<String>>,
+    image_instances: Arc<ImageInstances>,
+    tolerate_direct_assignment: bool,
+}
+
+/// Tells in-process waiters when all of a pod's actors have been started and linked.
+struct RunningSignal {
+    tx: watch::Sender<bool>,
+    rx: watch::Receiver<bool>,
+}
+
+impl RunningSignal {
+    fn new() -> Self {
+        let (tx, rx) = watch::channel(false);
+        RunningSignal { tx, rx }
+    }
+}
+
+/// Waits for a pod's running signal, see [`WasmCloudProvider::wait_for_running`].
+async fn wait_until_running(
+    mut rx: watch::Receiver<bool>,
+    pod_key: &PodKey,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let wait = async {
+        while !*rx.borrow() {
+            rx.changed().await.map_err(|_| {
+                anyhow::anyhow!(
+                    "Pod {} in namespace {} was removed before it was running",
+                    pod_key.name(),
+                    pod_key.namespace()
+                )
+            })?;
+        }
+        Ok(())
+    };
+    tokio::time::timeout(timeout, wait).await.map_err(|_| {
+        anyhow::anyhow!(
+            "Timed out after {:?} waiting for pod {} in namespace {} to be running",
+            timeout,
+            pod_key.name(),
+            pod_key.namespace()
+        )
+    })?
+}
+
+impl ProviderState {
+    /// Returns a receiver that sees `true` once the pod is running. Dropped when the pod is
+    /// removed from the provider.
+    async fn running_receiver(&self, pod_key: &PodKey) -> watch::Receiver<bool> {
+        let mut running = self.running.lock().await;
+        running
+            .entry(pod_key.clone())
+            .or_insert_with(RunningSignal::new)
+            .rx
+            .clone()
+    }
+
+    /// Marks the pod as running, waking anything in [`WasmCloudProvider::wait_for_running`].
+    async fn set_running(&self, pod_key: &PodKey) {
+        let mut running = self.running.lock().await;
+        let signal = running
+            .entry(pod_key.clone())
+            .or_insert_with(RunningSignal::new);
+        // We hold a receiver ourselves so this can't fail
+        signal.tx.send(true).ok();
+    }
+
+    /// Releases what the pod holds on the node: its host ports, its claims on limited images
+    /// and its running signal.
+    async fn release_pod(&self, pod_key: &PodKey) {
+        {
+            let mut port_map = self.port_map.lock().await;
+            let released = release_ports(&mut port_map, pod_key);
+            debug!(
+                "Pod {} in namespace {} releasing ports {:?}.",
+                pod_key.name(),
+                pod_key.namespace(),
+                released
+            );
+        }
+        self.image_instances.release(pod_key).await;
+        self.running.lock().await.remove(pod_key);
+    }
+}
+
+/// Removes the pod's ports from the port map, returning them.
+fn release_ports(port_map: &mut BTreeMap<u16, PodKey>, pod_key: &PodKey) -> Vec<u16> {
+    let ports: Vec<u16> = port_map
+        .iter()
+        .filter_map(|(port, holder)| if holder == pod_key { Some(*port) } else { None })
+        .collect();
+    for port in &ports {
+        port_map.remove(port);
+    }
+    ports
+}
+
+/// The pods with an actor that uses the given capability.
+fn pods_using(
+    actors: &BTreeMap<PodKey, BTreeMap<String, ActorInfo>>,
+    capability_id: &str,
+) -> Vec<PodKey> {
+    actors
+        .iter()
+        .filter(|(_, pod_actors)| {
+            pod_actors
+                .values()
+                .any(|actor| actor.capabilities.iter().any(|cap| cap == capability_id))
+        })
+        .map(|(pod_key, _)| pod_key.clone())
+        .collect()
 }
 
 #[async_trait::async_trait]
@@ -220,23 +788,80 @@ impl GenericProviderState for ProviderState {
 
 impl WasmCloudProvider {
     /// Returns a new wasmCloud provider configured to use the proper data directory
-    /// (including creating it if necessary)
+    /// (including creating it if necessary). Provider specific settings are read from the
+    /// environment, see [`WasmCloudConfig::from_env`].
     pub async fn new(
         store: Arc<dyn Store + Sync + Send>,
         config: &kubelet::config::Config,
         kubeconfig: kube::Config,
         plugin_registry: Arc<PluginRegistry>,
+    ) -> anyhow::Result<Self> {
+        let wasmcloud_config = WasmCloudConfig::from_env()?;
+        Self::new_with_config(store, config, kubeconfig, plugin_registry, wasmcloud_config).await
+    }
+
+    /// Returns a new wasmCloud provider using the given provider specific configuration.
+    pub async fn new_with_config(
+        store: Arc<dyn Store + Sync + Send>,
+        config: &kubelet::config::Config,
+        kubeconfig: kube::Config,
+        plugin_registry: Arc<PluginRegistry>,
+        wasmcloud_config: WasmCloudConfig,
     ) -> anyhow::Result<Self> {
         let client = kube::Client::new(kubeconfig);
+        // Verify and check the registry after rewriting, so both apply to the registry the
+        // image is actually fetched from. The registry is checked first so nothing, not even a
+        // signature, is fetched from one that isn't allowed
+        let store: Arc<dyn Store + Sync + Send> = match &wasmcloud_config.cosign_public_key {
+            Some(public_key) => Arc::new(
+                VerifyingStore::new(store, oci_distribution::Client::default(), public_key)
+                    .map_err(|e| anyhow::anyhow!("Invalid cosign public key: {}", e))?,
+            ),
+            None => store,
+        };
+        let store: Arc<dyn Store + Sync + Send> = if wasmcloud_config.allowed_registries.is_empty()
+        {
+            store
+        } else {
+            Arc::new(AllowlistStore::new(
+                store,
+                wasmcloud_config.allowed_registries.clone(),
+            ))
+        };
+        let store: Arc<dyn Store + Sync + Send> = if wasmcloud_config.image_rewrites.is_empty() {
+            store
+        } else {
+            Arc::new(RewritingStore::new(
+                store,
+                wasmcloud_config.image_rewrites.clone(),
+            ))
+        };
+        let store: Arc<dyn Store + Sync + Send> = match wasmcloud_config.image_fetch_timeout {
+            Some(timeout) => Arc::new(TimeoutStore::new(store, timeout)),
+            None => store,
+        };
+        let store: Arc<dyn Store + Sync + Send> = match &wasmcloud_config.dev_dir {
+            Some(dev_dir) => Arc::new(DevDirStore::new(store, dev_dir.clone())),
+            None => store,
+        };
+        let audit = AuditLog::new(wasmcloud_config.audit_log.as_deref())?;
         let host = HostBuilder::new().build();
         host.start()
             .await
             .map_err(|e| anyhow::anyhow!("Unable to start wasmCloud host: {}", e.to_string()))?;
-        let log_path = config.data_dir.join(LOG_DIR_NAME);
+        let log_storage = match wasmcloud_config.log_memory_bytes {
+            Some(capacity) => LogStorage::Memory(capacity),
+            None => LogStorage::Disk(config.data_dir.join(LOG_DIR_NAME)),
+        };
         let volume_path = config.data_dir.join(VOLUME_DIR);
         let port_map = Arc::new(Mutex::new(BTreeMap::<u16, PodKey>::new()));
-        tokio::fs::create_dir_all(&log_path).await?;
-        tokio::fs::create_dir_all(&volume_path).await?;
+        if let LogStorage::Disk(log_path) = &log_storage {
+            ensure_writable_dir(log_path).await?;
+        }
+        ensure_writable_dir(&volume_path).await?;
+        if let Some(state_dir) = &wasmcloud_config.state_dir {
+            ensure_writable_dir(state_dir).await?;
+        }
 
         // wasmCloud has native and portable capabilities.
         //
@@ -251,39 +876,291 @@ impl WasmCloudProvider {
         //
         // Here we are using the native capabilties as statically linked libraries that will
         // be compiled into the wasmcloud-provider binary.
-        info!("Loading HTTP capability");
-        let http_provider = HttpServerProvider::new();
-        let data =
-            NativeCapability::from_instance(http_provider, None, get_claims(HTTP_CAPABILITY))
-                .map_err(|e| anyhow::anyhow!("Failed to instantiate HTTP capability: {}", e))?;
-
-        host.start_native_capability(data)
-            .await
-            .map_err(|e| anyhow::anyhow!("Failed to add HTTP capability: {}", e))?;
-
-        info!("Loading log capability");
-        let logging_provider = LoggingProvider::new();
-        let logging_capability =
-            NativeCapability::from_instance(logging_provider, None, get_claims(LOG_CAPABILITY))
-                .map_err(|e| anyhow::anyhow!("Failed to instantiate log capability: {}", e))?;
-        host.start_native_capability(logging_capability)
-            .await
-            .map_err(|e| anyhow::anyhow!("Failed to add log capability: {}", e))?;
-        Ok(Self {
+        start_builtin_capabilities(&host).await?;
+        let recent_errors = Arc::new(RecentErrors::default());
+        let events = Arc::new(EventBus::new());
+        let provider = Self {
             shared: ProviderState {
                 client,
                 handles: Default::default(),
                 store,
                 volume_path,
-                log_path,
+                log_storage,
                 host: Arc::new(Mutex::new(host)),
                 port_map,
+                actors: Default::default(),
                 plugin_registry,
+                api_rate_limiter: Arc::new(RateLimiter::new(
+                    wasmcloud_config.kube_api_qps,
+                    wasmcloud_config.kube_api_burst,
+                )),
+                audit: Arc::new(audit),
+                running: Default::default(),
+                keep_failed: wasmcloud_config.keep_failed,
+                no_capabilities: wasmcloud_config.no_capabilities,
+                scheduler_names: Arc::new(wasmcloud_config.scheduler_names),
+                fs_providers: Default::default(),
+                log_streams: Arc::new(LogStreams::new(
+                    wasmcloud_config.max_log_streams,
+                    wasmcloud_config.max_node_log_streams,
+                )),
+                start_deadline: wasmcloud_config.start_deadline,
+                status_reporters: Arc::new(vec![recent_errors.clone(), events.clone()]),
+                recent_errors,
+                capability_defaults: Arc::new(wasmcloud_config.capability_defaults),
+                quarantine: wasmcloud_config
+                    .quarantine_failures
+                    .map(|failures| Quarantine {
+                        failures,
+                        window: wasmcloud_config.quarantine_window,
+                    }),
+                events,
+                state_dir: wasmcloud_config.state_dir,
+                allowed_resources: Arc::new(wasmcloud_config.allowed_resources),
+                fs_disabled: wasmcloud_config.disable_fs,
+                sensitive_env: Arc::new(wasmcloud_config.sensitive_env),
+                link_retries: wasmcloud_config.link_retries,
+                runtime_class_names: Arc::new(wasmcloud_config.runtime_class_names),
+                image_instances: Arc::new(ImageInstances::new(
+                    wasmcloud_config.image_instance_limits,
+                )),
+                tolerate_direct_assignment: wasmcloud_config.tolerate_direct_assignment,
             },
-        })
+        };
+
+        if let (LogStorage::Disk(log_path), Some(min_free)) = (
+            &provider.shared.log_storage,
+            wasmcloud_config.log_min_free_bytes,
+        ) {
+            disk_pressure::spawn_monitor(
+                log_path.clone(),
+                min_free,
+                provider.shared.actors.clone(),
+                provider.shared.events.clone(),
+            );
+        }
+
+        if let Some(dev_dir) = wasmcloud_config.dev_dir {
+            warn!(
+                "Running every module in dev directory {}. This is for development clusters only",
+                dev_dir.display()
+            );
+            dev_dir::spawn_watcher(
+                dev_dir,
+                config.node_name.clone(),
+                provider.shared.client.clone(),
+            );
+        }
+
+        if wasmcloud_config.self_test != SelfTest::Off {
+            let failed: Vec<String> = provider
+                .self_test()
+                .await
+                .into_iter()
+                .filter_map(|result| {
+                    result
+                        .error
+                        .map(|e| format!("{}: {}", result.capability, e))
+                })
+                .collect();
+            if !failed.is_empty() {
+                let message = format!("Capability self-test failed. {}", failed.join("; "));
+                if wasmcloud_config.self_test == SelfTest::Fail {
+                    return Err(anyhow::anyhow!(message));
+                }
+                warn!("{}", message);
+            }
+        }
+        Ok(provider)
+    }
+
+    /// Checks that each built-in capability works on this node: the log capability writes a
+    /// line, the FS capability binds a throwaway root and the HTTP capability listens on a free
+    /// port. Fresh provider instances are used, so running pods are unaffected.
+    pub async fn self_test(&self) -> Vec<SelfTestResult> {
+        let results = self_test::run().await;
+        for result in &results {
+            match &result.error {
+                None => info!("Capability {} passed its self-test", result.capability),
+                Some(e) => {
+                    error!(
+                        "Capability {} failed its self-test: {}",
+                        result.capability, e
+                    );
+                    self.shared
+                        .events
+                        .publish(ProviderEvent::CapabilityDegraded {
+                            capability: result.capability.to_owned(),
+                            error: e.clone(),
+                        });
+                }
+            }
+        }
+        results
+    }
+
+    /// Adds a reporter that is told about every pod status change, alongside the status the
+    /// kubelet keeps updated in Kubernetes. Reporters must be added before the provider is
+    /// handed to the kubelet.
+    pub fn add_status_reporter(&mut self, reporter: Arc<dyn StatusReporter>) {
+        Arc::make_mut(&mut self.shared.status_reporters).push(reporter);
+    }
+
+    /// Subscribes to the provider's lifecycle events: pod status changes, actors being started
+    /// and capabilities failing their self-test.
+    ///
+    /// Events are only seen from the moment of subscribing. Publishing never waits for
+    /// subscribers; instead up to 256 events are held for each one. A subscriber that falls
+    /// further behind than that receives a `Lagged` error giving how many events it missed and
+    /// then carries on from the oldest event still held.
+    pub fn subscribe_events(&self) -> BroadcastStream<ProviderEvent> {
+        self.shared.events.subscribe()
+    }
+
+    /// Waits until all of the pod's actors have been started and linked.
+    ///
+    /// This lets in-process consumers wait on the provider's own state machine rather than
+    /// watching the pod through the API. Returns an error if `timeout` passes first or the pod
+    /// is removed before it is running.
+    pub async fn wait_for_running(
+        &self,
+        pod_key: &PodKey,
+        timeout: Duration,
+    ) -> anyhow::Result<()> {
+        let rx = self.shared.running_receiver(pod_key).await;
+        wait_until_running(rx, pod_key, timeout).await
+    }
+
+    /// Stops the actors of every pod that uses the given capability, e.g. `wasmcloud:httpserver`,
+    /// leaving all other pods running, so the capability can be reloaded with
+    /// [`reload_capabilities`](Self::reload_capabilities). Returns the pods that were drained.
+    ///
+    /// All of a drained pod's actors are stopped, not just those using the capability, as a
+    /// partly running pod would be worse than a stopped one. Their host ports and image
+    /// instance claims are released. The pods themselves are left in place, reported as stopped
+    /// to status reporters; delete them once the capability is back to have them started again.
+    pub async fn drain_capability(&self, capability_id: &str) -> anyhow::Result<Vec<PodKey>> {
+        let drained = pods_using(&*self.shared.actors.read().await, capability_id);
+        info!(
+            "Draining {} pods using capability {}",
+            drained.len(),
+            capability_id
+        );
+        for pod_key in &drained {
+            {
+                let mut handles = self.shared.handles.write().await;
+                if let Some(handle) = handles.get_mut(pod_key) {
+                    handle.stop().await.map_err(|e| {
+                        anyhow::anyhow!(
+                            "Unable to drain pod {} in namespace {}: {}",
+                            pod_key.name(),
+                            pod_key.namespace(),
+                            e
+                        )
+                    })?;
+                }
+            }
+            self.shared.actors.write().await.remove(pod_key);
+            self.shared.release_pod(pod_key).await;
+            status::report_status(
+                &self.shared.status_reporters,
+                pod_key,
+                PodStatusReport::Stopped,
+            )
+            .await;
+        }
+        Ok(drained)
+    }
+
+    /// Replaces the running HTTP and log capability providers with fresh instances, re-reading
+    /// their claims, without restarting the kubelet.
+    ///
+    /// Actors are linked to a specific provider instance, so this refuses to run while any
+    /// actor is linked to one of them rather than leaving those actors without it. FS providers
+    /// are started per volume binding and are replaced whenever their pods are.
+    pub async fn reload_capabilities(&self) -> anyhow::Result<()> {
+        // Holding the host lock keeps actors from being linked while the providers are swapped
+        let host = self.shared.host.lock().await;
+        {
+            let actors = self.shared.actors.read().await;
+            let in_use: Vec<String> = actors
+                .iter()
+                .filter(|(_, pod_actors)| {
+                    pod_actors.values().any(|actor| {
+                        actor
+                            .capabilities
+                            .iter()
+                            .any(|cap| cap == HTTP_CAPABILITY || cap == LOG_CAPABILITY)
+                    })
+                })
+                .map(|(pod_key, _)| format!("{}/{}", pod_key.namespace(), pod_key.name()))
+                .collect();
+            if !in_use.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "Unable to reload capabilities while actors are linked to them. Remove these pods first: {}",
+                    in_use.join(", ")
+                ));
+            }
+        }
+
+        info!("Reloading capabilities");
+        for (pubkey, capid) in &[
+            (HTTP_CAPABILITY_PUBKEY, HTTP_CAPABILITY),
+            (LOG_CAPABILITY_PUBKEY, LOG_CAPABILITY),
+        ] {
+            host.stop_provider(pubkey, capid, None)
+                .await
+                .map_err(|e| anyhow::anyhow!("Unable to stop {} capability: {}", capid, e))?;
+        }
+        start_builtin_capabilities(&host).await
     }
 }
 
+/// Starts the HTTP and log capability providers that are compiled into this binary.
+async fn start_builtin_capabilities(host: &Host) -> anyhow::Result<()> {
+    info!("Loading HTTP capability");
+    let http_provider = HttpServerProvider::new();
+    let data = NativeCapability::from_instance(http_provider, None, get_claims(HTTP_CAPABILITY))
+        .map_err(|e| anyhow::anyhow!("Failed to instantiate HTTP capability: {}", e))?;
+
+    host.start_native_capability(data)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to add HTTP capability: {}", e))?;
+
+    info!("Loading log capability");
+    let logging_provider = LoggingProvider::new();
+    let logging_capability =
+        NativeCapability::from_instance(logging_provider, None, get_claims(LOG_CAPABILITY))
+            .map_err(|e| anyhow::anyhow!("Failed to instantiate log capability: {}", e))?;
+    host.start_native_capability(logging_capability)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to add log capability: {}", e))
+}
+
+/// Creates `path` if needed and checks that files can be written in it by creating and
+/// removing a probe file, so an unusable data directory is reported at startup rather than
+/// when the first pod is started.
+async fn ensure_writable_dir(path: &Path) -> anyhow::Result<()> {
+    tokio::fs::create_dir_all(path).await.map_err(|e| {
+        anyhow::anyhow!(
+            "Unable to create directory {}: {}. Check that the kubelet data directory exists and is writable by this user",
+            path.display(),
+            e
+        )
+    })?;
+    let probe = path.join(".write-probe");
+    tokio::fs::write(&probe, b"").await.map_err(|e| {
+        anyhow::anyhow!(
+            "Directory {} is not writable: {}. Check its permissions and that the disk is not full or mounted read-only",
+            path.display(),
+            e
+        )
+    })?;
+    tokio::fs::remove_file(&probe)
+        .await
+        .map_err(|e| anyhow::anyhow!("Unable to remove probe file {}: {}", probe.display(), e))
+}
+
 struct ModuleRunContext {
     modules: HashMap<String, Vec<u8>>,
     volumes: HashMap<String, Ref>,
@@ -310,7 +1187,16 @@ impl Provider for WasmCloudProvider {
     }
 
     async fn initialize_pod_state(&self, pod: &Pod) -> anyhow::Result<Self::PodState> {
-        Ok(PodState::new(pod))
+        if self.shared.tolerate_direct_assignment {
+            tolerations::tolerate_direct_assignment(self.shared.client.clone(), pod).await;
+        }
+        Ok(PodState::new(
+            pod,
+            self.shared.status_reporters.clone(),
+            self.shared.client.clone(),
+            self.shared.quarantine,
+            self.shared.api_rate_limiter.clone(),
+        ))
     }
 
     async fn logs(
@@ -320,13 +1206,15 @@ impl Provider for WasmCloudProvider {
         container_name: String,
         sender: kubelet::log::Sender,
     ) -> anyhow::Result<()> {
+        let pod_key = PodKey::new(&namespace, &pod_name);
         let mut handles = self.shared.handles.write().await;
         let handle = handles
-            .get_mut(&PodKey::new(&namespace, &pod_name))
+            .get_mut(&pod_key)
             .ok_or_else(|| ProviderError::PodNotFound {
                 pod_name: pod_name.clone(),
             })?;
-        handle.output(&container_name, sender).await
+        let stream = self.shared.log_streams.try_open(&pod_key)?;
+        LogStreams::with_stream(stream, handle.output(&container_name, sender)).await
     }
 
     fn plugin_registry(&self) -> Option<Arc<PluginRegistry>> {
@@ -346,6 +1234,46 @@ impl GenericProvider for WasmCloudProvider {
                 pod.name()
             ));
         }
+        if let Some(required) = pod.annotations().get(MIN_HOST_VERSION_ANNOTATION) {
+            let required_version = parse_version(required).map_err(|e| {
+                anyhow::anyhow!(
+                    "Cannot run {}: invalid {} annotation: {}",
+                    pod.name(),
+                    MIN_HOST_VERSION_ANNOTATION,
+                    e
+                )
+            })?;
+            if parse_version(WASMCLOUD_HOST_VERSION)? < required_version {
+                return Err(anyhow::anyhow!(
+                    "Cannot run {}: requires wasmCloud host {} or newer but this node runs {}",
+                    pod.name(),
+                    required,
+                    WASMCLOUD_HOST_VERSION
+                ));
+            }
+        }
+        if let Some(required) = pod.annotations().get(CAPABILITY_VERSIONS_ANNOTATION) {
+            check_capability_versions(pod, required)?;
+        }
+        check_volume_mounts(pod)?;
+        if let Some(storage) = pod.annotations().get(LOG_STORAGE_ANNOTATION) {
+            if storage != "file" && storage != "memory" {
+                return Err(anyhow::anyhow!(
+                    "Cannot run {}: invalid {} annotation {:?}, expected \"file\" or \"memory\"",
+                    pod.name(),
+                    LOG_STORAGE_ANNOTATION,
+                    storage
+                ));
+            }
+        }
+        if pod.annotations().contains_key(CPUSET_ANNOTATION) {
+            return Err(anyhow::anyhow!(
+                "Cannot run {}: the {} annotation is unsupported on this host. wasmCloud host {} runs all actors on threads it shares between them, so an actor can't be pinned to CPU cores",
+                pod.name(),
+                CPUSET_ANNOTATION,
+                WASMCLOUD_HOST_VERSION
+            ));
+        }
         Ok(())
     }
 
@@ -358,6 +1286,7 @@ impl GenericProvider for WasmCloudProvider {
                 container.name()
             ));
         }
+        check_mount_propagation(container)?;
         if let Some(image) = container.image()? {
             if image.whole().starts_with("k8s.gcr.io/kube-proxy") {
                 return Err(anyhow::anyhow!("Cannot run kube-proxy"));
@@ -368,6 +1297,76 @@ impl GenericProvider for WasmCloudProvider {
     }
 }
 
+/// Parses a `major.minor.patch` version. Missing trailing parts count as 0 and a leading `v` is
+/// allowed, so `v0.16` is `0.16.0`.
+fn parse_version(version: &str) -> anyhow::Result<(u64, u64, u64)> {
+    let trimmed = version.trim();
+    let trimmed = trimmed.strip_prefix('v').unwrap_or(trimmed);
+    let parts = trimmed
+        .split('.')
+        .map(|part| part.parse::<u64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| anyhow::anyhow!("{:?} is not a version: {}", version, e))?;
+    match parts.as_slice() {
+        [major] => Ok((*major, 0, 0)),
+        [major, minor] => Ok((*major, *minor, 0)),
+        [major, minor, patch] => Ok((*major, *minor, *patch)),
+        _ => Err(anyhow::anyhow!(
+            "{:?} is not a major.minor.patch version",
+            version
+        )),
+    }
+}
+
+/// Checks the contract versions in the pod's capability versions annotation against the ones
+/// this node's providers implement. A provider is compatible with an actor built against the
+/// same major version, or minor version before 1.0, if it is no older.
+fn check_capability_versions(pod: &Pod, required: &str) -> anyhow::Result<()> {
+    let invalid = |e: anyhow::Error| {
+        anyhow::anyhow!(
+            "Cannot run {}: invalid {} annotation: {}",
+            pod.name(),
+            CAPABILITY_VERSIONS_ANNOTATION,
+            e
+        )
+    };
+    for entry in required.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let mut parts = entry.splitn(2, '=');
+        let capability = parts.next().unwrap_or_default().trim();
+        let version = parts
+            .next()
+            .ok_or_else(|| invalid(anyhow::anyhow!("{:?} is not capability=version", entry)))?
+            .trim();
+        let required_version = parse_version(version).map_err(invalid)?;
+        let provided = capability_contract_version(capability).ok_or_else(|| {
+            invalid(anyhow::anyhow!(
+                "{} is not a capability this node provides",
+                capability
+            ))
+        })?;
+        let provided_version = parse_version(provided)?;
+        let series = |(major, minor, _): (u64, u64, u64)| {
+            if major == 0 {
+                (0, minor)
+            } else {
+                (major, 0)
+            }
+        };
+        if series(provided_version) != series(required_version)
+            || provided_version < required_version
+        {
+            return Err(anyhow::anyhow!(
+                "CapabilityVersionMismatch: cannot run {}: its actors need {} contract {} but this node's provider implements {}",
+                pod.name(),
+                capability,
+                version,
+                provided
+            ));
+        }
+    }
+    Ok(())
+}
+
 fn has_args(container: &kubelet::container::Container) -> bool {
     match &container.args() {
         None => false,
@@ -375,63 +1374,516 @@ fn has_args(container: &kubelet::container::Container) -> bool {
     }
 }
 
-struct VolumeBinding {
-    name: String,
-    host_path: PathBuf,
+/// Rejects volume mounts asking for mount propagation.
+///
+/// Volumes are handed to actors through the FS capability as plain host directories, so there
+/// are no mounts to propagate. Only the default (`None`) is accepted rather than silently
+/// ignoring `HostToContainer` or `Bidirectional`.
+fn check_mount_propagation(container: &kubelet::container::Container) -> anyhow::Result<()> {
+    for mount in container.volume_mounts().iter().flatten() {
+        match mount.mount_propagation.as_deref() {
+            None | Some("None") => {}
+            Some(propagation) => {
+                return Err(anyhow::anyhow!(
+                    "Cannot run {}: volume mount {} uses mountPropagation {} which is not supported on wasmCloud",
+                    container.name(),
+                    mount.name,
+                    propagation
+                ))
+            }
+        }
+    }
+    Ok(())
 }
 
-/// Capability describes a wasmCloud capability.
+/// The link to the FS capability for one of the pod's volumes.
 ///
-/// Capabilities are made available to actors through a two-part processthread:
-/// - They must be registered
-/// - For each actor, the capability must be configured
-struct Capability {
-    name: &'static str,
-    binding: Option<String>,
-    capability_provider_id: &'static str,
-    env: EnvVars,
+/// The binding is the volume name, which is what the actor addresses the volume by, e.g.
+/// `blobstore::host("storage")`, so it has to stay the same whichever pod the actor runs in.
+/// Pods are kept apart by the root directory each link carries instead, see [`FsProviders`].
+fn fs_capability(defaults: &CapabilityDefaults, env: &EnvVars, vol: &VolumeBinding) -> Capability {
+    let mut fsenv = capability_env(defaults, FS_CAPABILITY, env);
+    fsenv.insert(
+        FS_CONFIG_ROOTDIR.to_owned(),
+        vol.host_path.as_path().to_str().unwrap().to_owned(),
+    );
+    if let Some(ops) = &vol.allowed_ops {
+        fsenv.insert(ALLOWED_OPS_KEY.to_owned(), ops.clone());
+    }
+    Capability {
+        name: FS_CAPABILITY,
+        binding: Some(vol.name.clone()),
+        capability_provider_id: FS_CAPABILITY_PUBKEY,
+        env: fsenv,
+    }
 }
 
-/// Holds our tempfile handle.
-struct LogHandleFactory {
-    temp: NamedTempFile,
+/// Counts the actors using each FS capability provider.
+///
+/// A provider is started per binding, which is the volume name, so pods with a volume of the
+/// same name share one. The host can't tell those pods apart, but the provider can: it gives
+/// every actor linked to it an instance of its own (see [`PerActorProvider`]), rooted at the
+/// directory of that actor's own volume. The provider is only started for the first actor and
+/// stopped after the last one, so one pod stopping doesn't take it from the others.
+///
+/// The host keeps a single link per actor and binding, so the same actor can't use a volume
+/// name in two pods at once: the second link would replace the first pod's root directory.
+/// That is refused when the second pod starts.
+#[derive(Default)]
+struct FsProviders {
+    /// The actors linked under each binding, with the pod each one runs in.
+    bindings: Mutex<BTreeMap<String, Vec<(String, PodKey)>>>,
 }
 
-impl kubelet::log::HandleFactory<tokio::fs::File> for LogHandleFactory {
-    /// Creates `tokio::fs::File` on demand for log reading.
-    fn new_handle(&self) -> tokio::fs::File {
-        tokio::fs::File::from_std(self.temp.reopen().unwrap())
+impl FsProviders {
+    /// Registers the pod's actor as a user of the binding, returning true if the provider needs
+    /// starting. Fails if the actor already uses the binding in another pod.
+    async fn acquire(&self, binding: &str, actor: &str, pod_key: &PodKey) -> anyhow::Result<bool> {
+        let mut bindings = self.bindings.lock().await;
+        let users = bindings.entry(binding.to_owned()).or_default();
+        if let Some((_, other)) = users.iter().find(|(a, p)| a == actor && p != pod_key) {
+            return Err(anyhow::anyhow!(
+                "Actor {} already uses a volume named {} in pod {} in namespace {}, and one actor can only be linked to a volume name once per host",
+                actor,
+                binding,
+                other.name(),
+                other.namespace()
+            ));
+        }
+        users.push((actor.to_owned(), pod_key.clone()));
+        Ok(users.len() == 1)
+    }
+
+    /// The number of FS providers running.
+    async fn instances(&self) -> usize {
+        self.bindings.lock().await.len()
+    }
+
+    /// Drops the actor as a user of the binding, returning true if the provider should now be
+    /// stopped.
+    async fn release(&self, binding: &str, actor: &str) -> bool {
+        let mut bindings = self.bindings.lock().await;
+        let users = match bindings.get_mut(binding) {
+            Some(users) => users,
+            None => return false,
+        };
+        match users.iter().position(|(a, _)| a == actor) {
+            Some(index) => {
+                users.remove(index);
+            }
+            None => return false,
+        }
+        if users.is_empty() {
+            bindings.remove(binding);
+            true
+        } else {
+            false
+        }
     }
 }
 
-/// Run the given WASM data as a wasmCloud actor with the given public key.
+/// Whether the pod asked for a host of its own, either directly or by asking for capability
+/// provider instances that aren't shared with other pods.
+fn wants_dedicated_host(pod: &Pod) -> bool {
+    [DEDICATED_HOST_ANNOTATION, ISOLATE_CAPABILITIES_ANNOTATION]
+        .iter()
+        .any(|annotation| pod.annotations().get(*annotation).map(String::as_str) == Some("true"))
+}
+
+/// A wasmCloud host started for a single pod, see [`DEDICATED_HOST_ANNOTATION`].
+#[derive(Clone)]
+struct DedicatedHost {
+    host: Arc<Mutex<Host>>,
+    /// The FS providers running in this host, which are separate from the shared host's.
+    fs_providers: Arc<FsProviders>,
+}
+
+impl DedicatedHost {
+    /// Starts a host with the built-in capabilities loaded.
+    async fn start() -> anyhow::Result<Self> {
+        let host = HostBuilder::new().build();
+        host.start().await.map_err(|e| {
+            anyhow::anyhow!(
+                "Unable to start dedicated wasmCloud host: {}",
+                e.to_string()
+            )
+        })?;
+        start_builtin_capabilities(&host).await?;
+        Ok(DedicatedHost {
+            host: Arc::new(Mutex::new(host)),
+            fs_providers: Default::default(),
+        })
+    }
+
+    /// Stops the host along with anything still running in it.
+    async fn stop(&self) {
+        self.host.lock().await.stop().await;
+    }
+}
+
+struct VolumeBinding {
+    name: String,
+    host_path: PathBuf,
+    /// Where the container mounts the volume.
+    mount_path: PathBuf,
+    /// The blobstore operations the actor may call on this volume, comma separated.
+    allowed_ops: Option<String>,
+}
+
+/// What the provider knows about a running actor, kept per container for diagnostics.
+#[derive(Clone, Debug, Serialize)]
+struct ActorInfo {
+    key: String,
+    capabilities: Vec<String>,
+    links: Vec<LinkInfo>,
+    /// The log file, unless logs are kept in memory.
+    log_path: Option<PathBuf>,
+    /// The named log stream files, keyed by their link key.
+    log_stream_paths: BTreeMap<String, PathBuf>,
+    claims: ActorClaims,
+    #[serde(skip)]
+    memory_log: Option<Arc<MemoryLog>>,
+}
+
+/// A capability link set for an actor. The link configuration values are deliberately not
+/// kept as they can contain secrets.
+#[derive(Clone, Debug, Serialize)]
+struct LinkInfo {
+    capability: String,
+    binding: Option<String>,
+    provider_id: String,
+}
+
+impl From<&Capability> for LinkInfo {
+    fn from(cap: &Capability) -> Self {
+        LinkInfo {
+            capability: cap.name.to_owned(),
+            binding: cap.binding.clone(),
+            provider_id: cap.capability_provider_id.to_owned(),
+        }
+    }
+}
+
+/// Capability describes a wasmCloud capability.
+///
+/// Capabilities are made available to actors through a two-part processthread:
+/// - They must be registered
+/// - For each actor, the capability must be configured
+struct Capability {
+    name: &'static str,
+    binding: Option<String>,
+    capability_provider_id: &'static str,
+    env: EnvVars,
+}
+
+/// Undoes a partially started actor if `wasmcloud_run` doesn't finish.
+///
+/// Everything started or linked on the host is recorded as it happens. If the run fails, or
+/// panics, before [`StartRollback::disarm`] is called, dropping the guard stops and unlinks it
+/// all again so the host isn't left holding an actor with half its links for the next pod.
+/// The actor's log file is removed with it, unless `keep_failed` asks for it to be kept.
+struct StartRollback {
+    host: Arc<Mutex<Host>>,
+    pod_key: PodKey,
+    audit: Arc<AuditLog>,
+    redactor: Redactor,
+    log_output: Option<LogOutput>,
+    keep_failed: Option<Duration>,
+    fs_providers: Arc<FsProviders>,
+    /// The FS bindings acquired, each with the actor it was acquired for.
+    fs_bindings: Vec<(String, String)>,
+    actor: Option<String>,
+    links: Vec<(&'static str, Option<String>)>,
+    armed: bool,
+}
+
+impl StartRollback {
+    fn new(
+        host: Arc<Mutex<Host>>,
+        pod_key: PodKey,
+        audit: Arc<AuditLog>,
+        redactor: Redactor,
+        log_output: LogOutput,
+        keep_failed: Option<Duration>,
+        fs_providers: Arc<FsProviders>,
+    ) -> Self {
+        StartRollback {
+            host,
+            pod_key,
+            audit,
+            redactor,
+            log_output: Some(log_output),
+            keep_failed,
+            fs_providers,
+            fs_bindings: Vec::new(),
+            actor: None,
+            links: Vec::new(),
+            armed: true,
+        }
+    }
+
+    /// The actor's log.
+    fn log_output(&self) -> &LogOutput {
+        // Only taken when the guard is disarmed or dropped
+        self.log_output.as_ref().unwrap()
+    }
+
+    /// The actor is fully started and linked, so there is nothing to undo. Hands back the log
+    /// file.
+    fn disarm(mut self) -> LogOutput {
+        self.armed = false;
+        self.log_output.take().unwrap()
+    }
+}
+
+impl Drop for StartRollback {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        let log_output = self.log_output.take();
+        // Dropping can't wait on the host lock, so the cleanup runs as its own task
+        let runtime = match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => runtime,
+            Err(_) => {
+                error!(
+                    "Unable to roll back partially started actor for pod {} in namespace {}: no runtime",
+                    self.pod_key.name(),
+                    self.pod_key.namespace()
+                );
+                return;
+            }
+        };
+        // Logs kept in memory can't outlive the actor
+        if let (Some(ttl), Some(LogOutput::File(log_output))) = (self.keep_failed, log_output) {
+            match log_output.into_temp_path().keep() {
+                Ok(path) => {
+                    info!(
+                        "Keeping log {} of failed pod {} in namespace {} for {:?}",
+                        path.display(),
+                        self.pod_key.name(),
+                        self.pod_key.namespace(),
+                        ttl
+                    );
+                    runtime.spawn(async move {
+                        tokio::time::sleep(ttl).await;
+                        if let Err(e) = tokio::fs::remove_file(&path).await {
+                            error!("Unable to remove kept log {}: {}", path.display(), e);
+                        }
+                    });
+                }
+                Err(e) => error!("Unable to keep log of failed actor: {}", e),
+            }
+        }
+        let host = self.host.clone();
+        let pod_key = self.pod_key.clone();
+        let audit = self.audit.clone();
+        let redactor = self.redactor.clone();
+        let fs_providers = self.fs_providers.clone();
+        let fs_bindings: Vec<(String, String)> = self.fs_bindings.drain(0..).collect();
+        let actor = self.actor.take();
+        let links: Vec<(&'static str, Option<String>)> = self.links.drain(0..).collect();
+        runtime.spawn(async move {
+            warn!(
+                "Rolling back partially started actor for pod {} in namespace {}",
+                pod_key.name(),
+                pod_key.namespace()
+            );
+            let lock = host.lock().await;
+            let host_id = lock.id();
+            if let Some(actor) = actor {
+                for (capability, binding) in links {
+                    let result = lock
+                        .remove_link(&actor, capability, binding.clone())
+                        .await
+                        .map_err(|e| {
+                            anyhow::anyhow!("unable to unlink {} capability: {:?}", capability, e)
+                        });
+                    audit.record(
+                        AuditAction::Unlink,
+                        &host_id,
+                        &pod_key,
+                        &actor,
+                        Some((capability, binding.as_deref())),
+                        &result,
+                        &redactor,
+                    );
+                }
+                let result = lock
+                    .stop_actor(&actor)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("unable to remove actor: {:?}", e));
+                audit.record(
+                    AuditAction::Stop,
+                    &host_id,
+                    &pod_key,
+                    &actor,
+                    None,
+                    &result,
+                    &redactor,
+                );
+            }
+            for (binding, fs_actor) in fs_bindings {
+                if !fs_providers.release(&binding, &fs_actor).await {
+                    continue;
+                }
+                if let Err(e) = lock
+                    .stop_provider(FS_CAPABILITY_PUBKEY, FS_CAPABILITY, Some(binding.clone()))
+                    .await
+                {
+                    error!(
+                        "Unable to remove volume {:?} capability during rollback: {:?}",
+                        binding, e
+                    );
+                }
+            }
+        });
+    }
+}
+
+/// The magic number every WebAssembly binary, core module or component, starts with.
+const WASM_MAGIC: &[u8] = b"\0asm";
+
+/// The header of a WebAssembly binary: the magic number, then little endian version and
+/// layer fields of two bytes each.
+const WASM_HEADER_LEN: usize = 8;
+
+/// The version and layer fields of a core module.
+const CORE_MODULE_VERSION: [u8; 2] = [1, 0];
+const CORE_MODULE_LAYER: [u8; 2] = [0, 0];
+
+/// The layer field of a component, whose version field counts component model drafts.
+const COMPONENT_LAYER: [u8; 2] = [1, 0];
+
+/// Checks that the module is a core WebAssembly module, the only format the embedded wasmCloud
+/// host can run, so a component or something that isn't WebAssembly at all fails with a clear
+/// reason instead of a parse error.
+fn check_module_format(data: &[u8]) -> anyhow::Result<()> {
+    if !data.starts_with(WASM_MAGIC) {
+        return Err(anyhow::anyhow!(
+            "UnsupportedModuleFormat: the image is not a WebAssembly module"
+        ));
+    }
+    if data.len() < WASM_HEADER_LEN {
+        return Err(anyhow::anyhow!(
+            "UnsupportedModuleFormat: the image is a truncated WebAssembly module of {} bytes",
+            data.len()
+        ));
+    }
+    let (version, layer) = ([data[4], data[5]], [data[6], data[7]]);
+    match layer {
+        CORE_MODULE_LAYER if version == CORE_MODULE_VERSION => Ok(()),
+        CORE_MODULE_LAYER => Err(anyhow::anyhow!(
+            "UnsupportedModuleFormat: the image is a version {} WebAssembly module, but wasmCloud host {} only runs version 1",
+            u16::from_le_bytes(version),
+            WASMCLOUD_HOST_VERSION
+        )),
+        COMPONENT_LAYER => Err(anyhow::anyhow!(
+            "UnsupportedModuleFormat: the image is a WebAssembly component, but wasmCloud host {} only runs core modules signed as actors",
+            WASMCLOUD_HOST_VERSION
+        )),
+        _ => Err(anyhow::anyhow!(
+            "UnsupportedModuleFormat: the image is a WebAssembly binary of unknown layer {}",
+            u16::from_le_bytes(layer)
+        )),
+    }
+}
+
+/// Run the given WASM data as a wasmCloud actor with the given public key.
 ///
 /// The provided capabilities will be configured for this actor, but the capabilities
 /// must first be loaded into the host by some other process, such as register_native_capabilities().
+#[allow(clippy::too_many_arguments)]
 async fn wasmcloud_run(
     host: Arc<Mutex<Host>>,
+    pod_key: PodKey,
+    audit: Arc<AuditLog>,
     data: Vec<u8>,
     env: EnvVars,
+    redactor: Redactor,
     volumes: Vec<VolumeBinding>,
-    log_path: &Path,
+    log_storage: &LogStorage,
     port_assigned: u16,
-) -> anyhow::Result<ContainerHandle<ActorHandle, LogHandleFactory>> {
+    service_watch: Option<ServiceWatch>,
+    config_map_watch: Option<ConfigMapWatch>,
+    keep_failed: Option<Duration>,
+    no_capabilities: NoCapabilities,
+    fs_disabled: bool,
+    link_retries: u32,
+    fs_providers: Arc<FsProviders>,
+    log_streams: Arc<LogStreams>,
+    capability_defaults: Arc<CapabilityDefaults>,
+) -> anyhow::Result<(ContainerHandle<ActorHandle, LogHandleFactory>, ActorInfo)> {
     let mut capabilities: Vec<Capability> = Vec::new();
+    let env = file_values::resolve_file_values(env, &volumes).await?;
     info!("sending actor to wasmCloud host");
-    let log_output = NamedTempFile::new_in(&log_path)?;
+    let log_output = LogOutput::new(log_storage, &pod_key).await?;
+    let mut rollback = StartRollback::new(
+        host.clone(),
+        pod_key.clone(),
+        audit.clone(),
+        redactor.clone(),
+        log_output,
+        keep_failed,
+        fs_providers.clone(),
+    );
 
+    check_module_format(&data)?;
     let load =
         Actor::from_slice(&data).map_err(|e| anyhow::anyhow!("Error loading WASM: {}", e))?;
     let pk = load.public_key();
 
     let actor_caps = load.capabilities();
+    let claims = ActorClaims::from(&load.claims());
+    if fs_disabled && actor_caps.contains(&FS_CAPABILITY.to_owned()) {
+        return Err(anyhow::anyhow!(
+            "Actor {} declares the {} capability, which is disabled on this node",
+            pk,
+            FS_CAPABILITY
+        ));
+    }
+    if let Some(message) = check_capabilities(&pk, &actor_caps, no_capabilities)? {
+        warn!("{}", message);
+        // The actor has no logging link, so this is all `kubectl logs` will show
+        rollback
+            .log_output()
+            .write_line(&format!("WARNING: {}", message))?;
+    }
+    let mut links: Vec<LinkInfo> = Vec::new();
 
+    let mut log_stream_files = Vec::new();
+    let mut log_stream_paths = BTreeMap::new();
     if actor_caps.contains(&LOG_CAPABILITY.to_owned()) {
-        let mut logenv = env.clone();
-        logenv.insert(
-            LOG_PATH_KEY.to_string(),
-            log_output.path().to_str().unwrap().to_owned(),
-        );
+        let mut logenv = capability_env(&capability_defaults, LOG_CAPABILITY, &env);
+        // Where logs are written is up to the provider, not the pod
+        logenv.retain(|key, _| !key.starts_with(LOG_PATH_STREAM_PREFIX));
+        let (log_key, log_value) = rollback.log_output().link_value();
+        logenv.insert(log_key.to_string(), log_value);
+        let stream_names: Vec<String> = env
+            .get(LOG_STREAMS_KEY)
+            .map(|names| {
+                names
+                    .split(',')
+                    .map(|n| n.trim().to_lowercase())
+                    .filter(|n| !n.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        if let Some(invalid) = stream_names
+            .iter()
+            .find(|n| !n.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+        {
+            return Err(anyhow::anyhow!(
+                "Invalid {} entry {:?}: stream names may only contain letters, digits and '-'",
+                LOG_STREAMS_KEY,
+                invalid
+            ));
+        }
+        for (key, file) in rollback.log_output().stream_files(&stream_names)? {
+            logenv.insert(key.clone(), file.path().to_string_lossy().into_owned());
+            log_stream_paths.insert(key, file.path().to_owned());
+            log_stream_files.push(file);
+        }
         capabilities.push(Capability {
             name: LOG_CAPABILITY,
             binding: None,
@@ -441,8 +1893,8 @@ async fn wasmcloud_run(
     }
 
     if actor_caps.contains(&HTTP_CAPABILITY.to_owned()) {
-        let mut httpenv = env.clone();
-        httpenv.insert("PORT".to_string(), port_assigned.to_string());
+        let mut httpenv = capability_env(&capability_defaults, HTTP_CAPABILITY, &env);
+        httpenv.insert(PORT_KEY.to_string(), port_assigned.to_string());
         capabilities.push(Capability {
             name: HTTP_CAPABILITY,
             binding: None,
@@ -459,63 +1911,196 @@ async fn wasmcloud_run(
                     vol.name,
                     vol.host_path.display()
                 );
-                let mut fsenv = env.clone();
-                fsenv.insert(
-                    FS_CONFIG_ROOTDIR.to_owned(),
-                    vol.host_path.as_path().to_str().unwrap().to_owned(),
-                );
-                let fs_provider = FileSystemProvider::new();
-                let fs_capability = NativeCapability::from_instance(
-                    fs_provider,
-                    Some(vol.name.clone()),
-                    get_claims(FS_CAPABILITY),
-                )
-                .map_err(|e| {
-                    anyhow::anyhow!("Failed to instantiate File System capability: {}", e)
-                })?;
-                lock.start_native_capability(fs_capability)
-                    .await
-                    .map_err(|e| anyhow::anyhow!("Failed to add File System capability: {}", e))?;
-                capabilities.push(Capability {
-                    name: FS_CAPABILITY,
-                    binding: Some(vol.name.clone()),
-                    capability_provider_id: FS_CAPABILITY_PUBKEY,
-                    env: fsenv,
-                });
+                let capability = fs_capability(&capability_defaults, &env, vol);
+                if fs_providers.acquire(&vol.name, &pk, &pod_key).await? {
+                    if let Err(e) = start_fs_provider(&lock, &vol.name).await {
+                        fs_providers.release(&vol.name, &pk).await;
+                        return Err(e);
+                    }
+                } else {
+                    debug!(
+                        "File System capability for volume name: '{}' already running",
+                        vol.name
+                    );
+                }
+                rollback.fs_bindings.push((vol.name.clone(), pk.clone()));
+                capabilities.push(capability);
             }
         }
 
-        lock.start_actor(load)
+        let host_id = lock.id();
+        let result = lock
+            .start_actor(load)
             .await
-            .map_err(|e| anyhow::anyhow!("Error adding actor: {}", e))?;
-        for cap in capabilities {
+            .map_err(|e| anyhow::anyhow!("Error adding actor: {}", e));
+        audit.record(
+            AuditAction::Start,
+            &host_id,
+            &pod_key,
+            &pk,
+            None,
+            &result,
+            &redactor,
+        );
+        result?;
+        rollback.actor = Some(pk.clone());
+        for cap in capabilities.iter() {
             info!("configuring capability {}", cap.name);
-            lock.set_link(
+            let result = set_link_with_retries(&lock, &pk, cap, link_retries)
+                .await
+                .map_err(|e| anyhow::anyhow!("Error configuring capabilities for module: {}", e));
+            audit.record(
+                AuditAction::Link,
+                &host_id,
+                &pod_key,
                 &pk,
-                cap.name,
-                cap.binding.clone(),
-                cap.capability_provider_id.to_owned(),
-                cap.env.clone(),
-            )
-            .await
-            .map_err(|e| anyhow::anyhow!("Error configuring capabilities for module: {}", e))?;
+                Some((cap.name, cap.binding.as_deref())),
+                &result,
+                &redactor,
+            );
+            result?;
+            rollback.links.push((cap.name, cap.binding.clone()));
+            links.push(LinkInfo::from(cap));
         }
     }
 
-    let log_handle_factory = LogHandleFactory { temp: log_output };
+    // The actor is fully linked, so the links move from the rollback to the handle
+    let linked = std::mem::take(&mut rollback.links);
+    let log_output = rollback.disarm();
+
+    // Both watches update the same links, so they share the capabilities' current env
+    let capabilities = Arc::new(Mutex::new(capabilities));
+    let service_refresh = service_watch.map(|watch| {
+        services::spawn_refresh(host.clone(), pk.clone(), capabilities.clone(), watch)
+    });
+    let config_reload = config_map_watch.map(|watch| {
+        config_maps::spawn_reload(host.clone(), pk.clone(), capabilities.clone(), watch)
+    });
+
+    let actor_info = ActorInfo {
+        key: pk.clone(),
+        capabilities: actor_caps,
+        links,
+        log_path: log_output.path().map(Path::to_owned),
+        log_stream_paths,
+        claims,
+        memory_log: log_output.memory(),
+    };
+    let log_handle_factory = LogHandleFactory {
+        output: log_output,
+        pod_key: pod_key.clone(),
+        streams: log_streams,
+    };
 
     info!("wasmCloud actor executing");
-    Ok(ContainerHandle::new(
-        ActorHandle {
-            host,
-            key: pk,
-            volumes,
-            capabilities: actor_caps,
-        },
-        log_handle_factory,
+    Ok((
+        ContainerHandle::new(
+            ActorHandle {
+                host,
+                key: pk,
+                links: linked,
+                pod_key,
+                audit,
+                redactor,
+                service_refresh,
+                config_reload,
+                fs_providers,
+                _log_stream_files: log_stream_files,
+            },
+            log_handle_factory,
+        ),
+        actor_info,
     ))
 }
 
+/// Returns the link configuration for a capability: the container's env on top of the node's
+/// defaults for that capability.
+fn capability_env(defaults: &CapabilityDefaults, capability: &str, env: &EnvVars) -> EnvVars {
+    let mut capability_env = defaults.get(capability).cloned().unwrap_or_default();
+    capability_env.extend(env.iter().map(|(k, v)| (k.clone(), v.clone())));
+    capability_env
+}
+
+/// How long to wait before the first retry of a failed link. Each retry waits twice as long as
+/// the last.
+const LINK_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// Links the actor to the capability, retrying up to `retries` times if the link fails
+/// transiently.
+async fn set_link_with_retries(
+    host: &Host,
+    actor: &str,
+    cap: &Capability,
+    retries: u32,
+) -> anyhow::Result<()> {
+    retry_transient(actor, cap.name, retries, move || {
+        host.set_link(
+            actor,
+            cap.name,
+            cap.binding.clone(),
+            cap.capability_provider_id.to_owned(),
+            cap.env.clone(),
+        )
+    })
+    .await
+}
+
+/// Runs `link` until it succeeds, fails with an error that isn't transient or has been
+/// retried `retries` times, waiting [`LINK_RETRY_DELAY`] before the first retry.
+async fn retry_transient<F, Fut, E>(
+    actor: &str,
+    capability: &str,
+    retries: u32,
+    mut link: F,
+) -> anyhow::Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<(), E>>,
+    E: std::fmt::Display,
+{
+    let mut delay = LINK_RETRY_DELAY;
+    let mut attempt = 0;
+    loop {
+        match link().await {
+            Err(e) if attempt < retries && is_transient(&e.to_string()) => {
+                attempt += 1;
+                warn!(
+                    "Linking actor {} to {} failed transiently, retrying in {:?} ({}/{}): {}",
+                    actor, capability, delay, attempt, retries, e
+                );
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            result => return result.map_err(|e| anyhow::anyhow!("{}", e)),
+        }
+    }
+}
+
+/// Whether a link error is worth retrying. The host reports errors only as text, so transient
+/// ones are recognised by the timeouts and closed mailboxes of a provider that is busy or still
+/// starting. Anything else, such as a provider rejecting its configuration, won't go away by
+/// retrying.
+fn is_transient(error: &str) -> bool {
+    let error = error.to_lowercase();
+    ["timed out", "timeout", "mailbox"]
+        .iter()
+        .any(|transient| error.contains(transient))
+}
+
+/// Starts an FS capability provider under the given binding name.
+async fn start_fs_provider(host: &Host, binding: &str) -> anyhow::Result<()> {
+    let fs_provider = AllowlistProvider::new(PerActorProvider::new(FileSystemProvider::new));
+    let fs_capability = NativeCapability::from_instance(
+        fs_provider,
+        Some(binding.to_owned()),
+        get_claims(FS_CAPABILITY),
+    )
+    .map_err(|e| anyhow::anyhow!("Failed to instantiate File System capability: {}", e))?;
+    host.start_native_capability(fs_capability)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to add File System capability: {}", e))
+}
+
 // This code contains the embedded claims needed to register the 3 providers. The public key comes
 // from the `sub` claim on each token. These tokens were generated with the following commands:
 //
@@ -541,3 +2126,928 @@ fn get_claims(capid: &str) -> Claims<CapabilityProvider> {
 
     Claims::<CapabilityProvider>::decode(token).unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+    use wasmcloud_actor_core::CapabilityConfiguration;
+    use wasmcloud_provider_core::capabilities::{CapabilityProvider, Dispatcher};
+    use wasmcloud_provider_core::core::{OP_BIND_ACTOR, OP_REMOVE_ACTOR};
+    use wasmcloud_provider_core::{deserialize, serialize};
+
+    use crate::test_support;
+
+    fn rollback(log_output: LogOutput, keep_failed: Option<Duration>) -> StartRollback {
+        StartRollback::new(
+            Arc::new(Mutex::new(HostBuilder::new().build())),
+            test_support::pod_key("default", "greet"),
+            Arc::new(AuditLog::new(None).unwrap()),
+            Redactor::default(),
+            log_output,
+            keep_failed,
+            Arc::new(FsProviders::default()),
+        )
+    }
+
+    /// An actor log file in `dir`, and its path.
+    fn file_log(dir: &Path) -> (LogOutput, PathBuf) {
+        let file = tempfile::NamedTempFile::new_in(dir).unwrap();
+        let path = file.path().to_owned();
+        (LogOutput::File(file), path)
+    }
+
+    #[tokio::test]
+    async fn disarmed_rollback_hands_back_the_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let (log, path) = file_log(dir.path());
+        let log = rollback(log, None).disarm();
+        assert!(path.exists());
+        drop(log);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn panicking_start_is_rolled_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let (log, path) = file_log(dir.path());
+        let guard = rollback(log, None);
+        let start = tokio::spawn(async move {
+            let _guard = guard;
+            panic!("start failed halfway");
+        });
+        assert!(start.await.unwrap_err().is_panic());
+        assert!(!path.exists(), "the failed actor's log was left behind");
+    }
+
+    /// A stand-in for the FS provider that answers every call with the root directory it was
+    /// linked with.
+    #[derive(Clone, Default)]
+    struct RootedProvider {
+        root: Arc<std::sync::Mutex<String>>,
+    }
+
+    impl CapabilityProvider for RootedProvider {
+        fn configure_dispatch(
+            &self,
+            _dispatcher: Box<dyn Dispatcher>,
+        ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+            Ok(())
+        }
+
+        fn handle_call(
+            &self,
+            _actor: &str,
+            op: &str,
+            msg: &[u8],
+        ) -> Result<Vec<u8>, Box<dyn std::error::Error + Sync + Send>> {
+            if op == OP_BIND_ACTOR {
+                let config = deserialize::<CapabilityConfiguration>(msg)?;
+                *self.root.lock().unwrap() = config.values[FS_CONFIG_ROOTDIR].clone();
+            }
+            Ok(self.root.lock().unwrap().as_bytes().to_vec())
+        }
+
+        fn stop(&self) {}
+    }
+
+    fn storage_volume(host_path: &str) -> VolumeBinding {
+        VolumeBinding {
+            name: "storage".to_owned(),
+            host_path: PathBuf::from(host_path),
+            mount_path: PathBuf::from("/storage"),
+            allowed_ops: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn pods_with_the_same_volume_name_keep_their_own_volumes() {
+        let fs_providers = FsProviders::default();
+        let pod_a = test_support::pod_key("default", "a");
+        let pod_b = test_support::pod_key("other", "b");
+        let defaults = CapabilityDefaults::default();
+        let link_a = fs_capability(&defaults, &EnvVars::new(), &storage_volume("/volumes/a"));
+        let link_b = fs_capability(&defaults, &EnvVars::new(), &storage_volume("/volumes/b"));
+
+        // Both actors reach their volume by the name they were built with
+        assert_eq!(link_a.binding.as_deref(), Some("storage"));
+        assert_eq!(link_b.binding.as_deref(), Some("storage"));
+
+        // The first pod starts the provider, and the second shares it
+        assert!(fs_providers
+            .acquire("storage", "MACTORA", &pod_a)
+            .await
+            .unwrap());
+        assert!(!fs_providers
+            .acquire("storage", "MACTORB", &pod_b)
+            .await
+            .unwrap());
+        assert_eq!(fs_providers.instances().await, 1);
+
+        // Within the provider each actor only sees its own pod's volume
+        let provider = PerActorProvider::new(RootedProvider::default);
+        for (actor, link) in [("MACTORA", &link_a), ("MACTORB", &link_b)].iter() {
+            let config = CapabilityConfiguration {
+                module: actor.to_string(),
+                values: link.env.clone(),
+            };
+            provider
+                .handle_call("system", OP_BIND_ACTOR, &serialize(config).unwrap())
+                .unwrap();
+        }
+        let root = |actor: &str| provider.handle_call(actor, "GetObjectInfo", &[]).unwrap();
+        assert_eq!(root("MACTORA"), b"/volumes/a");
+        assert_eq!(root("MACTORB"), b"/volumes/b");
+
+        // Stopping the second pod leaves the provider to the first
+        let config = CapabilityConfiguration {
+            module: "MACTORB".to_owned(),
+            values: Default::default(),
+        };
+        provider
+            .handle_call("system", OP_REMOVE_ACTOR, &serialize(config).unwrap())
+            .unwrap();
+        assert!(!fs_providers.release("storage", "MACTORB").await);
+        assert_eq!(root("MACTORA"), b"/volumes/a");
+        assert!(provider
+            .handle_call("MACTORB", "GetObjectInfo", &[])
+            .is_err());
+        assert!(fs_providers.release("storage", "MACTORA").await);
+        assert_eq!(fs_providers.instances().await, 0);
+    }
+
+    #[tokio::test]
+    async fn one_actor_cannot_use_a_volume_name_in_two_pods() {
+        let fs_providers = FsProviders::default();
+        let pod_a = test_support::pod_key("default", "a");
+        fs_providers
+            .acquire("storage", "MACTOR", &pod_a)
+            .await
+            .unwrap();
+        let err = fs_providers
+            .acquire("storage", "MACTOR", &test_support::pod_key("default", "b"))
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("pod a in namespace default"),
+            "{}",
+            err
+        );
+
+        // Another of the pod's containers running the actor is counted, not refused
+        assert!(!fs_providers
+            .acquire("storage", "MACTOR", &pod_a)
+            .await
+            .unwrap());
+        assert!(!fs_providers.release("storage", "MACTOR").await);
+        assert!(fs_providers.release("storage", "MACTOR").await);
+        // Releasing what was never acquired stops nothing
+        assert!(!fs_providers.release("storage", "MACTOR").await);
+    }
+
+    #[tokio::test]
+    async fn failed_start_keeps_its_log_for_keep_failed() {
+        let dir = tempfile::tempdir().unwrap();
+        let (log, path) = file_log(dir.path());
+        drop(rollback(log, Some(Duration::from_millis(100))));
+        assert!(path.exists(), "the failed actor's log was not kept");
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(!path.exists(), "the kept log was not removed");
+    }
+
+    #[tokio::test]
+    async fn failed_start_removes_its_log_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let (log, path) = file_log(dir.path());
+        drop(rollback(log, None));
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn wait_until_running_resolves_once_signalled() {
+        let pod_key = test_support::pod_key("default", "greet");
+        let signal = RunningSignal::new();
+        let rx = signal.rx.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            signal.tx.send(true).unwrap();
+            // Keep the signal, as the provider does, until the pod is removed
+            tokio::time::sleep(Duration::from_secs(60)).await;
+        });
+        wait_until_running(rx, &pod_key, Duration::from_secs(10))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn wait_until_running_returns_at_once_if_running() {
+        let pod_key = test_support::pod_key("default", "greet");
+        let signal = RunningSignal::new();
+        signal.tx.send(true).unwrap();
+        wait_until_running(signal.rx.clone(), &pod_key, Duration::from_millis(1))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn wait_until_running_times_out() {
+        let pod_key = test_support::pod_key("default", "greet");
+        let signal = RunningSignal::new();
+        let err = wait_until_running(signal.rx.clone(), &pod_key, Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Timed out"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn wait_until_running_fails_if_pod_removed() {
+        let pod_key = test_support::pod_key("default", "greet");
+        let signal = RunningSignal::new();
+        let rx = signal.rx.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            drop(signal);
+        });
+        let err = wait_until_running(rx, &pod_key, Duration::from_secs(10))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("removed"), "{}", err);
+    }
+
+    #[test]
+    fn parse_version_fills_in_missing_parts() {
+        assert_eq!(parse_version("0.16.0").unwrap(), (0, 16, 0));
+        assert_eq!(parse_version(" v0.16 ").unwrap(), (0, 16, 0));
+        assert_eq!(parse_version("1").unwrap(), (1, 0, 0));
+        // Compared as numbers, not strings
+        assert!(parse_version("0.9.0").unwrap() < parse_version("0.16.0").unwrap());
+    }
+
+    #[test]
+    fn parse_version_rejects_non_versions() {
+        for invalid in &["", "latest", "0.16.0-rc1", "1.2.3.4", "v", "0..1"] {
+            assert!(parse_version(invalid).is_err(), "{:?} parsed", invalid);
+        }
+    }
+
+    fn requiring_host(version: &str) -> Pod {
+        test_support::pod(json!({
+            "metadata": {
+                "name": "greet",
+                "annotations": { MIN_HOST_VERSION_ANNOTATION: version }
+            },
+            "spec": { "containers": [{ "name": "greet", "image": "greet:1" }] }
+        }))
+    }
+
+    #[test]
+    fn min_host_version_at_or_below_this_host_accepted() {
+        for version in &["0.15.2", "0.16", WASMCLOUD_HOST_VERSION] {
+            WasmCloudProvider::validate_pod_runnable(&requiring_host(version)).unwrap();
+        }
+    }
+
+    #[test]
+    fn min_host_version_above_this_host_refused() {
+        let err = WasmCloudProvider::validate_pod_runnable(&requiring_host("0.17.0")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Cannot run greet: requires wasmCloud host 0.17.0 or newer but this node runs {}",
+                WASMCLOUD_HOST_VERSION
+            )
+        );
+
+        let err = WasmCloudProvider::validate_pod_runnable(&requiring_host("newest")).unwrap_err();
+        assert!(err.to_string().contains("invalid"), "{}", err);
+    }
+
+    fn mounting(propagation: Option<&str>) -> kubelet::container::Container {
+        let mut mount = json!({ "name": "storage", "mountPath": "/storage" });
+        if let Some(propagation) = propagation {
+            mount["mountPropagation"] = json!(propagation);
+        }
+        test_support::container(json!({
+            "name": "greet",
+            "image": "greet:1",
+            "volumeMounts": [mount]
+        }))
+    }
+
+    #[test]
+    fn default_mount_propagation_accepted() {
+        check_mount_propagation(&mounting(None)).unwrap();
+        check_mount_propagation(&mounting(Some("None"))).unwrap();
+    }
+
+    #[test]
+    fn mount_propagation_rejected() {
+        for propagation in &["Bidirectional", "HostToContainer"] {
+            let err = check_mount_propagation(&mounting(Some(propagation))).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "Cannot run greet: volume mount storage uses mountPropagation {} which is not supported on wasmCloud",
+                    propagation
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn dns_env_from_pod_spec() {
+        let pod = test_support::pod(json!({
+            "spec": {
+                "containers": [{ "name": "greet", "image": "greet:1" }],
+                "dnsPolicy": "None",
+                "dnsConfig": {
+                    "nameservers": ["10.0.0.10", "1.1.1.1"],
+                    "searches": ["default.svc.cluster.local"],
+                    "options": [{ "name": "ndots", "value": "2" }, { "name": "edns0" }]
+                }
+            }
+        }));
+        let env = dns_env(&pod).unwrap();
+        assert_eq!(env[DNS_POLICY_KEY], "None");
+        assert_eq!(env[DNS_SERVERS_KEY], r#"["10.0.0.10","1.1.1.1"]"#);
+        assert_eq!(env[DNS_SEARCHES_KEY], r#"["default.svc.cluster.local"]"#);
+        let options: serde_json::Value = serde_json::from_str(&env[DNS_OPTIONS_KEY]).unwrap();
+        assert_eq!(
+            options,
+            json!([{ "name": "ndots", "value": "2" }, { "name": "edns0" }])
+        );
+    }
+
+    #[test]
+    fn dns_env_only_has_what_the_pod_sets() {
+        let pod = test_support::pod(json!({
+            "spec": {
+                "containers": [{ "name": "greet", "image": "greet:1" }],
+                "dnsConfig": { "searches": ["example.com"] }
+            }
+        }));
+        let env = dns_env(&pod).unwrap();
+        assert_eq!(env.len(), 1);
+        assert_eq!(env[DNS_SEARCHES_KEY], r#"["example.com"]"#);
+
+        let pod = test_support::pod(json!({
+            "spec": { "containers": [{ "name": "greet", "image": "greet:1" }] }
+        }));
+        assert!(dns_env(&pod).unwrap().is_empty());
+    }
+
+    #[test]
+    fn actor_with_capabilities_passes() {
+        let caps = vec![HTTP_CAPABILITY.to_owned()];
+        for policy in &[NoCapabilities::Warn, NoCapabilities::Fail] {
+            assert!(check_capabilities("MACTOR", &caps, *policy)
+                .unwrap()
+                .is_none());
+        }
+    }
+
+    #[test]
+    fn actor_without_capabilities_warns_or_fails() {
+        let warning = check_capabilities("MACTOR", &[], NoCapabilities::Warn)
+            .unwrap()
+            .unwrap();
+        assert!(
+            warning.starts_with("Actor MACTOR declares no capabilities"),
+            "{}",
+            warning
+        );
+
+        let err = check_capabilities("MACTOR", &[], NoCapabilities::Fail).unwrap_err();
+        assert_eq!(err.to_string(), warning);
+    }
+
+    #[test]
+    fn no_capabilities_policy_parses() {
+        assert_eq!(
+            "warn".parse::<NoCapabilities>().unwrap(),
+            NoCapabilities::Warn
+        );
+        assert_eq!(
+            "fail".parse::<NoCapabilities>().unwrap(),
+            NoCapabilities::Fail
+        );
+        assert!("ignore".parse::<NoCapabilities>().is_err());
+    }
+
+    fn scheduled_by(scheduler_name: Option<&str>) -> Pod {
+        let mut spec = json!({ "containers": [{ "name": "greet", "image": "greet:1" }] });
+        if let Some(scheduler_name) = scheduler_name {
+            spec["schedulerName"] = json!(scheduler_name);
+        }
+        test_support::pod(json!({ "metadata": { "name": "greet" }, "spec": spec }))
+    }
+
+    #[test]
+    fn any_scheduler_served_by_default() {
+        check_scheduler_name(&scheduled_by(Some("custom")), &[]).unwrap();
+        check_scheduler_name(&scheduled_by(None), &[]).unwrap();
+    }
+
+    #[test]
+    fn pod_without_scheduler_name_uses_the_default_scheduler() {
+        let served = vec![DEFAULT_SCHEDULER_NAME.to_owned()];
+        check_scheduler_name(&scheduled_by(None), &served).unwrap();
+        check_scheduler_name(&scheduled_by(Some(DEFAULT_SCHEDULER_NAME)), &served).unwrap();
+    }
+
+    #[test]
+    fn unserved_scheduler_refused() {
+        let served = vec!["wasm-scheduler".to_owned(), "edge-scheduler".to_owned()];
+        check_scheduler_name(&scheduled_by(Some("edge-scheduler")), &served).unwrap();
+        let err = check_scheduler_name(&scheduled_by(None), &served).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Cannot run greet: it was scheduled by default-scheduler but this node only serves pods from wasm-scheduler, edge-scheduler"
+        );
+    }
+
+    #[tokio::test]
+    async fn writable_dir_is_created_without_leaving_the_probe() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logs").join("nested");
+        ensure_writable_dir(&path).await.unwrap();
+        assert!(path.is_dir());
+        assert_eq!(std::fs::read_dir(&path).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn uncreatable_dir_names_the_path() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let path = file.path().join("logs");
+        let err = ensure_writable_dir(&path).await.unwrap_err().to_string();
+        assert!(
+            err.starts_with(&format!("Unable to create directory {}", path.display())),
+            "{}",
+            err
+        );
+        assert!(err.contains("writable by this user"), "{}", err);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn read_only_dir_names_the_path() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logs");
+        std::fs::create_dir(&path).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o555)).unwrap();
+        // Permissions don't apply to root, so there is nothing to check
+        if std::fs::write(path.join("root-check"), b"").is_ok() {
+            return;
+        }
+        let err = ensure_writable_dir(&path).await.unwrap_err().to_string();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(
+            err.starts_with(&format!("Directory {} is not writable", path.display())),
+            "{}",
+            err
+        );
+        assert!(err.contains("mounted read-only"), "{}", err);
+    }
+
+    fn annotated(annotations: serde_json::Value) -> Pod {
+        test_support::pod(json!({
+            "metadata": { "annotations": annotations },
+            "spec": { "containers": [{ "name": "greet", "image": "greet:1" }] }
+        }))
+    }
+
+    #[test]
+    fn isolated_pods_get_a_dedicated_host() {
+        assert!(wants_dedicated_host(&annotated(
+            json!({ ISOLATE_CAPABILITIES_ANNOTATION: "true" })
+        )));
+        assert!(wants_dedicated_host(&annotated(
+            json!({ DEDICATED_HOST_ANNOTATION: "true" })
+        )));
+        assert!(!wants_dedicated_host(&annotated(json!({}))));
+        assert!(!wants_dedicated_host(&annotated(
+            json!({ ISOLATE_CAPABILITIES_ANNOTATION: "yes", DEDICATED_HOST_ANNOTATION: "false" })
+        )));
+    }
+
+    #[test]
+    fn container_env_wins_over_capability_defaults() {
+        let mut defaults = CapabilityDefaults::new();
+        defaults.insert(
+            HTTP_CAPABILITY.to_owned(),
+            vec![
+                ("PORT".to_owned(), "8080".to_owned()),
+                ("BIND_ADDR".to_owned(), "127.0.0.1".to_owned()),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        let env: EnvVars = vec![("PORT".to_owned(), "9090".to_owned())]
+            .into_iter()
+            .collect();
+
+        let http = capability_env(&defaults, HTTP_CAPABILITY, &env);
+        assert_eq!(http["PORT"], "9090");
+        assert_eq!(http["BIND_ADDR"], "127.0.0.1");
+        // Defaults only apply to their own capability
+        let logging = capability_env(&defaults, LOG_CAPABILITY, &env);
+        assert_eq!(logging, env);
+    }
+
+    /// An actor using the given capabilities.
+    fn actor_using(capabilities: &[&str]) -> ActorInfo {
+        let capabilities: Vec<String> = capabilities.iter().map(|c| (*c).to_owned()).collect();
+        ActorInfo {
+            key: "MACTOR".to_owned(),
+            capabilities: capabilities.clone(),
+            links: vec![],
+            log_path: None,
+            log_stream_paths: BTreeMap::new(),
+            claims: ActorClaims {
+                issuer: "AISSUER".to_owned(),
+                subject: "MACTOR".to_owned(),
+                name: None,
+                capabilities,
+                tags: vec![],
+                version: None,
+                revision: None,
+                issued_at: 1_600_000_000,
+                expires: None,
+            },
+            memory_log: None,
+        }
+    }
+
+    #[test]
+    fn only_pods_using_the_capability_are_drained() {
+        let http = test_support::pod_key("default", "http");
+        let logging = test_support::pod_key("default", "logging");
+        let mixed = test_support::pod_key("default", "mixed");
+        let mut actors = BTreeMap::new();
+        actors.insert(
+            http.clone(),
+            vec![("greet".to_owned(), actor_using(&[HTTP_CAPABILITY]))]
+                .into_iter()
+                .collect(),
+        );
+        actors.insert(
+            logging,
+            vec![("log".to_owned(), actor_using(&[LOG_CAPABILITY]))]
+                .into_iter()
+                .collect(),
+        );
+        // One container using the capability drains the whole pod
+        actors.insert(
+            mixed.clone(),
+            vec![
+                ("log".to_owned(), actor_using(&[LOG_CAPABILITY])),
+                ("greet".to_owned(), actor_using(&[HTTP_CAPABILITY])),
+            ]
+            .into_iter()
+            .collect(),
+        );
+
+        assert_eq!(pods_using(&actors, HTTP_CAPABILITY), vec![http, mixed]);
+        assert!(pods_using(&actors, FS_CAPABILITY).is_empty());
+    }
+
+    #[test]
+    fn released_ports_are_only_the_pods_own() {
+        let drained = test_support::pod_key("default", "drained");
+        let other = test_support::pod_key("default", "other");
+        let mut port_map = BTreeMap::new();
+        port_map.insert(30000, drained.clone());
+        port_map.insert(30001, other.clone());
+        port_map.insert(30002, drained.clone());
+
+        assert_eq!(release_ports(&mut port_map, &drained), vec![30000, 30002]);
+        assert_eq!(
+            port_map.into_iter().collect::<Vec<_>>(),
+            vec![(30001, other)]
+        );
+    }
+
+    /// A pod whose containers mount the given volumes, one list per container.
+    fn pod_mounting(mounts: &[&[&str]]) -> Pod {
+        let containers: Vec<serde_json::Value> = mounts
+            .iter()
+            .enumerate()
+            .map(|(i, volumes)| {
+                let mounts: Vec<serde_json::Value> = volumes
+                    .iter()
+                    .map(|v| json!({ "name": v, "mountPath": format!("/{}", v) }))
+                    .collect();
+                json!({ "name": format!("c{}", i), "image": "greet", "volumeMounts": mounts })
+            })
+            .collect();
+        test_support::pod(json!({ "spec": { "containers": containers } }))
+    }
+
+    #[test]
+    fn volume_mounts_refused_when_fs_is_disabled() {
+        let pod = pod_mounting(&[&[], &["data"]]);
+        let err = check_fs_disabled(&pod, true).unwrap_err();
+        assert!(err.to_string().contains("container c1"), "got: {}", err);
+        assert!(err.to_string().contains(FS_CAPABILITY), "got: {}", err);
+    }
+
+    #[test]
+    fn pods_without_mounts_run_when_fs_is_disabled() {
+        assert!(check_fs_disabled(&pod_mounting(&[&[], &[]]), true).is_ok());
+        assert!(check_fs_disabled(&pod_mounting(&[&["data"]]), false).is_ok());
+    }
+
+    #[test]
+    fn timeouts_and_mailbox_errors_are_transient() {
+        assert!(is_transient("Request timed out"));
+        assert!(is_transient("Timeout waiting for provider"));
+        assert!(is_transient("Mailbox closed"));
+        assert!(!is_transient("Invalid configuration: PORT is not a number"));
+        assert!(!is_transient(""));
+    }
+
+    /// Runs [`retry_transient`] over the given link outcomes, returning its result and how
+    /// many attempts were made.
+    async fn link_attempts(
+        outcomes: &[Result<(), &'static str>],
+        retries: u32,
+    ) -> (anyhow::Result<()>, usize) {
+        let attempts = std::sync::atomic::AtomicUsize::new(0);
+        let result = retry_transient("MACTOR", HTTP_CAPABILITY, retries, || {
+            let attempt = attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let outcome = outcomes[attempt];
+            async move { outcome }
+        })
+        .await;
+        (result, attempts.into_inner())
+    }
+
+    #[tokio::test]
+    async fn transient_link_failures_are_retried() {
+        let (result, attempts) =
+            link_attempts(&[Err("timed out"), Err("mailbox closed"), Ok(())], 2).await;
+        assert!(result.is_ok());
+        assert_eq!(attempts, 3);
+    }
+
+    #[tokio::test]
+    async fn link_retries_are_limited() {
+        let (result, attempts) = link_attempts(&[Err("timed out"), Err("timed out")], 1).await;
+        assert_eq!(result.unwrap_err().to_string(), "timed out");
+        assert_eq!(attempts, 2);
+    }
+
+    #[tokio::test]
+    async fn other_link_failures_are_not_retried() {
+        let (result, attempts) = link_attempts(&[Err("invalid configuration"), Ok(())], 3).await;
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    /// A host recording the teardown calls made to it, failing to unlink `failing`.
+    #[derive(Default)]
+    struct RecordingHost {
+        failing: Option<&'static str>,
+        calls: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl ActorHost for RecordingHost {
+        fn id(&self) -> String {
+            "NHOST".to_owned()
+        }
+
+        async fn remove_link(
+            &self,
+            _actor: &str,
+            capability: &str,
+            binding: Option<String>,
+        ) -> anyhow::Result<()> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("unlink {} {:?}", capability, binding));
+            match self.failing {
+                Some(failing) if failing == capability => {
+                    Err(anyhow::anyhow!("provider not responding"))
+                }
+                _ => Ok(()),
+            }
+        }
+
+        async fn stop_fs_provider(&self, binding: &str) -> anyhow::Result<()> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("stop provider {}", binding));
+            Ok(())
+        }
+
+        async fn stop_actor(&self, actor: &str) -> anyhow::Result<()> {
+            self.calls.lock().unwrap().push(format!("stop {}", actor));
+            Ok(())
+        }
+    }
+
+    const CUSTOM_CAPABILITY: &str = "example:custom";
+
+    fn actor_handle(fs_providers: Arc<FsProviders>) -> ActorHandle {
+        ActorHandle {
+            key: "MACTOR".to_owned(),
+            host: Arc::new(Mutex::new(HostBuilder::new().build())),
+            links: vec![],
+            pod_key: test_support::pod_key("default", "greet"),
+            audit: Arc::new(AuditLog::new(None).unwrap()),
+            redactor: Redactor::default(),
+            service_refresh: None,
+            config_reload: None,
+            fs_providers,
+            _log_stream_files: vec![],
+        }
+    }
+
+    /// Registers the handle's actor with the FS provider for a volume the way starting it does,
+    /// returning the binding it is linked under.
+    async fn link_volume(handle: &ActorHandle, volume: &str) -> Option<String> {
+        let link = fs_capability(
+            &CapabilityDefaults::default(),
+            &EnvVars::new(),
+            &VolumeBinding {
+                name: volume.to_owned(),
+                host_path: PathBuf::from("/volumes").join(volume),
+                mount_path: PathBuf::from("/").join(volume),
+                allowed_ops: None,
+            },
+        );
+        let binding = link.binding.as_deref().unwrap();
+        assert!(handle
+            .fs_providers
+            .acquire(binding, &handle.key, &handle.pod_key)
+            .await
+            .unwrap());
+        link.binding
+    }
+
+    #[tokio::test]
+    async fn every_link_is_removed_on_stop() {
+        let fs_providers = Arc::new(FsProviders::default());
+        let handle = actor_handle(fs_providers.clone());
+        let binding = link_volume(&handle, "data").await;
+        let host = RecordingHost::default();
+
+        handle
+            .teardown(
+                &host,
+                vec![
+                    (CUSTOM_CAPABILITY, Some("custom".to_owned())),
+                    (FS_CAPABILITY, binding),
+                ],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            *host.calls.lock().unwrap(),
+            vec![
+                "unlink example:custom Some(\"custom\")",
+                "unlink wasmcloud:blobstore Some(\"data\")",
+                "stop provider data",
+                "stop MACTOR",
+            ]
+        );
+        assert_eq!(fs_providers.instances().await, 0);
+    }
+
+    #[tokio::test]
+    async fn failed_unlink_does_not_end_the_stop() {
+        let fs_providers = Arc::new(FsProviders::default());
+        let handle = actor_handle(fs_providers.clone());
+        let binding = link_volume(&handle, "data").await;
+        let host = RecordingHost {
+            failing: Some(CUSTOM_CAPABILITY),
+            ..Default::default()
+        };
+
+        let err = handle
+            .teardown(
+                &host,
+                vec![
+                    (CUSTOM_CAPABILITY, None),
+                    (HTTP_CAPABILITY, None),
+                    (FS_CAPABILITY, binding),
+                ],
+            )
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("provider not responding"),
+            "got: {}",
+            err
+        );
+        // The remaining links, the FS provider and the actor are still taken down
+        assert_eq!(host.calls.lock().unwrap().len(), 5);
+        assert_eq!(
+            host.calls.lock().unwrap().last().map(String::as_str),
+            Some("stop MACTOR")
+        );
+        assert_eq!(fs_providers.instances().await, 0);
+    }
+
+    #[test]
+    fn mounts_must_name_a_declared_volume() {
+        let pod = test_support::pod(json!({
+            "spec": {
+                "containers": [
+                    {
+                        "name": "first",
+                        "image": "greet",
+                        "volumeMounts": [{ "name": "config", "mountPath": "/config" }]
+                    },
+                    {
+                        "name": "second",
+                        "image": "greet",
+                        "volumeMounts": [{ "name": "confg", "mountPath": "/config" }]
+                    }
+                ],
+                "volumes": [{ "name": "config", "emptyDir": {} }]
+            }
+        }));
+        let err = check_volume_mounts(&pod).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("container second mounts volume confg"),
+            "got: {}",
+            err
+        );
+    }
+
+    #[test]
+    fn mounts_of_declared_volumes_pass() {
+        let pod = test_support::pod(json!({
+            "spec": {
+                "containers": [{
+                    "name": "greet",
+                    "image": "greet",
+                    "volumeMounts": [{ "name": "config", "mountPath": "/config" }]
+                }],
+                "volumes": [{ "name": "config", "emptyDir": {} }]
+            }
+        }));
+        assert!(check_volume_mounts(&pod).is_ok());
+        let no_mounts = test_support::pod(json!({
+            "spec": { "containers": [{ "name": "greet", "image": "greet" }] }
+        }));
+        assert!(check_volume_mounts(&no_mounts).is_ok());
+    }
+
+    #[test]
+    fn mounts_without_any_volumes_fail() {
+        let pod = test_support::pod(json!({
+            "spec": {
+                "containers": [{
+                    "name": "greet",
+                    "image": "greet",
+                    "volumeMounts": [{ "name": "config", "mountPath": "/config" }]
+                }]
+            }
+        }));
+        assert!(check_volume_mounts(&pod).is_err());
+    }
+
+    #[test]
+    fn core_modules_pass_the_format_check() {
+        assert!(check_module_format(b"\0asm\x01\x00\x00\x00").is_ok());
+        assert!(check_module_format(b"\0asm\x01\x00\x00\x00\x01\x04\x01\x60\x00\x00").is_ok());
+    }
+
+    #[test]
+    fn components_are_refused() {
+        let err = check_module_format(b"\0asm\x0d\x00\x01\x00").unwrap_err();
+        assert!(err.to_string().contains("component"), "got: {}", err);
+    }
+
+    #[test]
+    fn other_core_module_versions_are_not_taken_for_components() {
+        let err = check_module_format(b"\0asm\x02\x00\x00\x00").unwrap_err();
+        assert!(err.to_string().contains("version 2"), "got: {}", err);
+        assert!(!err.to_string().contains("component"), "got: {}", err);
+        let err = check_module_format(b"\0asm\x01\x00\x02\x00").unwrap_err();
+        assert!(err.to_string().contains("unknown layer 2"), "got: {}", err);
+    }
+
+    #[test]
+    fn truncated_and_foreign_data_is_refused() {
+        let err = check_module_format(b"\0asm\x01\x00\x00").unwrap_err();
+        assert!(err.to_string().contains("truncated"), "got: {}", err);
+        let err = check_module_format(b"\0as").unwrap_err();
+        assert!(
+            err.to_string().contains("not a WebAssembly module"),
+            "got: {}",
+            err
+        );
+        assert!(check_module_format(b"\x7fELF\x02\x01\x01\x00").is_err());
+        assert!(check_module_format(b"").is_err());
+    }
+}
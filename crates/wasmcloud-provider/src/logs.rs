@@ -0,0 +1,504 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io::{SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use kubelet::pod::{Pod, PodKey};
+use tempfile::NamedTempFile;
+use tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
+use wasmcloud_logging::{
+    MemoryLog, RegisteredMemoryLog, LOG_MEMORY_KEY, LOG_PATH_KEY, LOG_PATH_STREAM_PREFIX,
+};
+
+use crate::LOG_STORAGE_ANNOTATION;
+
+/// Where actor logs are kept.
+#[derive(Clone, Debug)]
+pub(crate) enum LogStorage {
+    /// Files under this directory, one subdirectory per namespace.
+    Disk(PathBuf),
+    /// In memory, at most this many bytes per actor. Lost when the kubelet restarts.
+    Memory(usize),
+}
+
+/// The most bytes kept per actor for a pod that asks for its logs in memory on a node that keeps
+/// logs in files.
+pub(crate) const DEFAULT_MEMORY_LOG_BYTES: usize = 1024 * 1024;
+
+impl LogStorage {
+    /// Where the pod's actor logs are kept: the node's storage, unless the pod's log storage
+    /// annotation asks for the other.
+    pub(crate) fn for_pod(&self, pod: &Pod) -> anyhow::Result<LogStorage> {
+        let requested = pod.annotations().get(LOG_STORAGE_ANNOTATION);
+        match (requested.map(String::as_str), self) {
+            (None, _)
+            | (Some("file"), LogStorage::Disk(_))
+            | (Some("memory"), LogStorage::Memory(_)) => Ok(self.clone()),
+            (Some("memory"), LogStorage::Disk(_)) => {
+                Ok(LogStorage::Memory(DEFAULT_MEMORY_LOG_BYTES))
+            }
+            (Some("file"), LogStorage::Memory(_)) => Err(anyhow::anyhow!(
+                "Cannot run {}: it asks for its logs in files, but this node keeps actor logs in memory only",
+                pod.name()
+            )),
+            (Some(other), _) => Err(anyhow::anyhow!(
+                "Cannot run {}: invalid {} annotation {:?}, expected \"file\" or \"memory\"",
+                pod.name(),
+                LOG_STORAGE_ANNOTATION,
+                other
+            )),
+        }
+    }
+}
+
+/// The log of a single actor.
+pub(crate) enum LogOutput {
+    File(NamedTempFile),
+    Memory(RegisteredMemoryLog),
+}
+
+impl LogOutput {
+    /// Creates the log for an actor in the given pod.
+    pub(crate) async fn new(storage: &LogStorage, pod_key: &PodKey) -> anyhow::Result<Self> {
+        match storage {
+            LogStorage::Disk(log_path) => {
+                // Logs are partitioned by namespace so a busy namespace doesn't leave one giant
+                // directory
+                let log_dir = log_path.join(pod_key.namespace());
+                tokio::fs::create_dir_all(&log_dir).await.map_err(|e| {
+                    anyhow::anyhow!(
+                        "Unable to create log directory {}: {}",
+                        log_dir.display(),
+                        e
+                    )
+                })?;
+                Ok(LogOutput::File(NamedTempFile::new_in(&log_dir)?))
+            }
+            LogStorage::Memory(capacity) => {
+                Ok(LogOutput::Memory(RegisteredMemoryLog::new(*capacity)))
+            }
+        }
+    }
+
+    /// The link value telling the logging capability where to write.
+    pub(crate) fn link_value(&self) -> (&'static str, String) {
+        match self {
+            LogOutput::File(temp) => (LOG_PATH_KEY, temp.path().to_str().unwrap().to_owned()),
+            LogOutput::Memory(log) => (LOG_MEMORY_KEY, log.name().to_owned()),
+        }
+    }
+
+    /// Creates a file for each named stream next to the log, returning the link value and the
+    /// file for each. Streams need a file to go to, so with logs kept in memory there are none
+    /// and every record goes to the one log.
+    pub(crate) fn stream_files(
+        &self,
+        names: &[String],
+    ) -> std::io::Result<Vec<(String, NamedTempFile)>> {
+        let dir = match self {
+            LogOutput::File(temp) => match temp.path().parent() {
+                Some(dir) => dir,
+                None => return Ok(vec![]),
+            },
+            LogOutput::Memory(_) => return Ok(vec![]),
+        };
+        names
+            .iter()
+            .map(|name| {
+                let file = tempfile::Builder::new()
+                    .suffix(&format!("-{}.log", name))
+                    .tempfile_in(dir)?;
+                Ok((
+                    format!("{}{}", LOG_PATH_STREAM_PREFIX, name.to_uppercase()),
+                    file,
+                ))
+            })
+            .collect()
+    }
+
+    /// The log file, if the log is kept on disk.
+    pub(crate) fn path(&self) -> Option<&Path> {
+        match self {
+            LogOutput::File(temp) => Some(temp.path()),
+            LogOutput::Memory(_) => None,
+        }
+    }
+
+    /// The in-memory log, if the log isn't kept on disk.
+    pub(crate) fn memory(&self) -> Option<Arc<MemoryLog>> {
+        match self {
+            LogOutput::File(_) => None,
+            LogOutput::Memory(log) => Some(log.log().clone()),
+        }
+    }
+
+    /// Writes a line to the log directly rather than through the logging capability.
+    pub(crate) fn write_line(&self, line: &str) -> std::io::Result<()> {
+        match self {
+            LogOutput::File(temp) => writeln!(temp.reopen()?, "{}", line),
+            LogOutput::Memory(log) => {
+                log.log().append(format!("{}\n", line).as_bytes());
+                Ok(())
+            }
+        }
+    }
+}
+
+tokio::task_local! {
+    /// The stream counted for the reader the current task is opening, see
+    /// [`LogStreams::with_stream`].
+    static OPENING: RefCell<Option<LogStream>>;
+}
+
+/// Counts the log streams open against each pod so one pod can't be flooded with them, and
+/// across the node so that everyone tailing logs at once can't saturate its disk.
+pub(crate) struct LogStreams {
+    /// The most streams open at once per pod. Unlimited when unset.
+    max: Option<usize>,
+    /// The most streams open at once across every pod on the node. Unlimited when unset.
+    max_node: Option<usize>,
+    active: Mutex<BTreeMap<PodKey, usize>>,
+}
+
+impl LogStreams {
+    pub(crate) fn new(max: Option<usize>, max_node: Option<usize>) -> Self {
+        LogStreams {
+            max,
+            max_node,
+            active: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Counts a new stream against the pod, unless the pod or the node as a whole already has
+    /// as many open as allowed. The check and the count are made under one lock, so concurrent
+    /// requests can't all pass the check and go over the limits. The stream counts until the
+    /// returned guard is dropped.
+    pub(crate) fn try_open(self: &Arc<Self>, pod_key: &PodKey) -> anyhow::Result<LogStream> {
+        let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(max_node) = self.max_node {
+            if active.values().sum::<usize>() >= max_node {
+                return Err(anyhow::anyhow!(
+                    "This node already has the maximum of {} log streams open across all pods, try again once one is closed",
+                    max_node
+                ));
+            }
+        }
+        if let Some(max) = self.max {
+            if active.get(pod_key).copied().unwrap_or(0) >= max {
+                return Err(anyhow::anyhow!(
+                    "Pod {} in namespace {} already has the maximum of {} log streams open, close one and try again",
+                    pod_key.name(),
+                    pod_key.namespace(),
+                    max
+                ));
+            }
+        }
+        *active.entry(pod_key.clone()).or_insert(0) += 1;
+        Ok(LogStream {
+            streams: self.clone(),
+            pod_key: pod_key.clone(),
+        })
+    }
+
+    /// Runs `open`, which opens a log reader, handing the reader `stream` to hold. The reader is
+    /// created by the kubelet through [`LogHandleFactory::new_handle`], which takes no
+    /// arguments, so the stream is passed along with the task instead. It is dropped if `open`
+    /// fails before creating the reader.
+    pub(crate) async fn with_stream<T>(stream: LogStream, open: impl Future<Output = T>) -> T {
+        OPENING.scope(RefCell::new(Some(stream)), open).await
+    }
+
+    /// Counts a stream without checking the limits, for readers opened other than through
+    /// [`LogStreams::with_stream`].
+    fn open(self: &Arc<Self>, pod_key: &PodKey) -> LogStream {
+        let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        *active.entry(pod_key.clone()).or_insert(0) += 1;
+        LogStream {
+            streams: self.clone(),
+            pod_key: pod_key.clone(),
+        }
+    }
+}
+
+/// Counts as an open stream until dropped.
+pub(crate) struct LogStream {
+    streams: Arc<LogStreams>,
+    pod_key: PodKey,
+}
+
+impl Drop for LogStream {
+    fn drop(&mut self) {
+        let mut active = self
+            .streams
+            .active
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if let Some(count) = active.get_mut(&self.pod_key) {
+            *count -= 1;
+            if *count == 0 {
+                active.remove(&self.pod_key);
+            }
+        }
+    }
+}
+
+/// Holds our log output.
+pub(crate) struct LogHandleFactory {
+    pub(crate) output: LogOutput,
+    pub(crate) pod_key: PodKey,
+    pub(crate) streams: Arc<LogStreams>,
+}
+
+impl kubelet::log::HandleFactory<LogReader> for LogHandleFactory {
+    /// Creates a [`LogReader`] on demand for log reading.
+    ///
+    /// Every call opens the file anew, so each reader (such as concurrent `kubectl logs -f`
+    /// streams) gets its own cursor and closing one leaves the others and the writer alone.
+    /// In-memory readers likewise each keep their own position.
+    ///
+    /// The reader holds the stream counted against the pod's and the node's log stream limits
+    /// when it was requested, see [`LogStreams::with_stream`], until it is dropped.
+    fn new_handle(&self) -> LogReader {
+        let source = match &self.output {
+            LogOutput::File(temp) => {
+                let file = temp.reopen().unwrap();
+                let len = file.try_clone().unwrap();
+                LogSource::File(tokio::fs::File::from_std(file), len)
+            }
+            LogOutput::Memory(log) => LogSource::Memory(MemoryReader {
+                log: log.log().clone(),
+                pos: 0,
+            }),
+        };
+        let stream = OPENING
+            .try_with(|opening| opening.borrow_mut().take())
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| self.streams.open(&self.pod_key));
+        LogReader {
+            source,
+            _stream: stream,
+        }
+    }
+}
+
+/// A reader over an actor's log, wherever it is kept.
+///
+/// Seeking before the start of the log goes to its start rather than failing, so tailing more
+/// lines than a short or still empty log holds returns all of it.
+pub(crate) struct LogReader {
+    source: LogSource,
+    _stream: LogStream,
+}
+
+enum LogSource {
+    /// The log file, and a second handle to it only used to look up its length.
+    File(tokio::fs::File, std::fs::File),
+    Memory(MemoryReader),
+}
+
+impl AsyncRead for LogReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        match &mut self.get_mut().source {
+            LogSource::File(file, _) => Pin::new(file).poll_read(cx, buf),
+            LogSource::Memory(reader) => Pin::new(reader).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncSeek for LogReader {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> std::io::Result<()> {
+        match &mut self.get_mut().source {
+            LogSource::File(file, len) => {
+                let position = match position {
+                    SeekFrom::End(offset) if offset < 0 => {
+                        SeekFrom::Start(len.metadata()?.len().saturating_sub(offset.unsigned_abs()))
+                    }
+                    position => position,
+                };
+                Pin::new(file).start_seek(position)
+            }
+            LogSource::Memory(reader) => Pin::new(reader).start_seek(position),
+        }
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+        match &mut self.get_mut().source {
+            LogSource::File(file, _) => Pin::new(file).poll_complete(cx),
+            LogSource::Memory(reader) => Pin::new(reader).poll_complete(cx),
+        }
+    }
+}
+
+/// Reads a [`MemoryLog`] from its own position. Reaching the end behaves like reaching the
+/// end of a file that may still grow, and seeking before the start goes to the start.
+struct MemoryReader {
+    log: Arc<MemoryLog>,
+    pos: u64,
+}
+
+impl AsyncRead for MemoryReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let reader = self.get_mut();
+        let (pos, read) = reader.log.read_at(reader.pos, buf.initialize_unfilled());
+        buf.advance(read);
+        reader.pos = pos + read as u64;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncSeek for MemoryReader {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> std::io::Result<()> {
+        let reader = self.get_mut();
+        let target = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => offset_by(reader.log.end(), offset),
+            SeekFrom::Current(offset) => offset_by(reader.pos, offset),
+        };
+        reader.pos = target.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to an overflowing position",
+            )
+        })?;
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+        Poll::Ready(Ok(self.pos))
+    }
+}
+
+fn offset_by(base: u64, offset: i64) -> Option<u64> {
+    if offset >= 0 {
+        base.checked_add(offset as u64)
+    } else {
+        Some(base.saturating_sub(offset.unsigned_abs()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use kubelet::log::HandleFactory;
+
+    use crate::test_support;
+
+    #[test]
+    fn streams_open_up_to_the_pod_limit() {
+        let streams = Arc::new(LogStreams::new(Some(2), None));
+        let pod_key = test_support::pod_key("default", "greet");
+        let first = streams.try_open(&pod_key).unwrap();
+        let _second = streams.try_open(&pod_key).unwrap();
+        let err = streams.try_open(&pod_key).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Pod greet in namespace default already has the maximum of 2 log streams open, close one and try again"
+        );
+        // Other pods have limits of their own
+        streams
+            .try_open(&test_support::pod_key("default", "other"))
+            .unwrap();
+
+        drop(first);
+        streams.try_open(&pod_key).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reader_holds_its_stream() {
+        let streams = Arc::new(LogStreams::new(Some(1), None));
+        let pod_key = test_support::pod_key("default", "greet");
+        let factory = LogHandleFactory {
+            output: LogOutput::Memory(RegisteredMemoryLog::new(1024)),
+            pod_key: pod_key.clone(),
+            streams: streams.clone(),
+        };
+
+        let stream = streams.try_open(&pod_key).unwrap();
+        let reader = LogStreams::with_stream(stream, async { factory.new_handle() }).await;
+        assert!(streams.try_open(&pod_key).is_err());
+        drop(reader);
+
+        // A stream whose reader was never created is given back
+        let stream = streams.try_open(&pod_key).unwrap();
+        LogStreams::with_stream(stream, async {}).await;
+        streams.try_open(&pod_key).unwrap();
+    }
+
+    #[test]
+    fn streams_open_up_to_the_node_limit() {
+        let streams = Arc::new(LogStreams::new(None, Some(2)));
+        let first = streams
+            .try_open(&test_support::pod_key("default", "greet"))
+            .unwrap();
+        let _second = streams
+            .try_open(&test_support::pod_key("other", "echo"))
+            .unwrap();
+        let err = streams
+            .try_open(&test_support::pod_key("default", "third"))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "This node already has the maximum of 2 log streams open across all pods, try again once one is closed"
+        );
+
+        drop(first);
+        streams
+            .try_open(&test_support::pod_key("default", "third"))
+            .unwrap();
+    }
+
+    #[test]
+    fn concurrent_opens_stay_within_the_node_limit() {
+        let streams = Arc::new(LogStreams::new(None, Some(3)));
+        let barrier = Arc::new(std::sync::Barrier::new(16));
+        let opened: Vec<_> = (0..16)
+            .map(|i| {
+                let streams = streams.clone();
+                let barrier = barrier.clone();
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    streams.try_open(&test_support::pod_key("default", &format!("pod-{}", i)))
+                })
+            })
+            .map(|thread| thread.join().unwrap())
+            .filter_map(Result::ok)
+            .collect();
+        assert_eq!(opened.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn reader_holds_its_node_stream() {
+        let streams = Arc::new(LogStreams::new(None, Some(1)));
+        let pod_key = test_support::pod_key("default", "greet");
+        let factory = LogHandleFactory {
+            output: LogOutput::Memory(RegisteredMemoryLog::new(1024)),
+            pod_key: pod_key.clone(),
+            streams: streams.clone(),
+        };
+
+        let stream = streams.try_open(&pod_key).unwrap();
+        let reader = LogStreams::with_stream(stream, async { factory.new_handle() }).await;
+        assert!(streams
+            .try_open(&test_support::pod_key("other", "echo"))
+            .is_err());
+        drop(reader);
+        streams
+            .try_open(&test_support::pod_key("other", "echo"))
+            .unwrap();
+    }
+}
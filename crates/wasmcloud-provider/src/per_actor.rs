@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::{Arc, RwLock};
+
+use wasmcloud_actor_core::CapabilityConfiguration;
+use wasmcloud_provider_core::capabilities::{CapabilityProvider, Dispatcher};
+use wasmcloud_provider_core::core::{OP_BIND_ACTOR, OP_REMOVE_ACTOR};
+use wasmcloud_provider_core::deserialize;
+
+/// The origin of calls made by the wasmCloud host itself.
+const SYSTEM_ACTOR: &str = "system";
+
+/// Gives every actor bound to it a provider instance of its own.
+///
+/// The FS provider keeps a single root directory, set by whichever actor was bound last, so
+/// actors sharing a binding name would otherwise all see the last one's root. That happens
+/// whenever containers mount different subPaths of one volume, or pods use volumes with the
+/// same name.
+#[derive(Clone)]
+pub(crate) struct PerActorProvider<P> {
+    new_instance: fn() -> P,
+    /// Answers the host's own calls, such as health checks.
+    base: P,
+    instances: Arc<RwLock<HashMap<String, P>>>,
+    dispatcher: Arc<RwLock<Option<Box<dyn Dispatcher>>>>,
+}
+
+impl<P> PerActorProvider<P> {
+    pub(crate) fn new(new_instance: fn() -> P) -> Self {
+        PerActorProvider {
+            new_instance,
+            base: new_instance(),
+            instances: Default::default(),
+            dispatcher: Default::default(),
+        }
+    }
+}
+
+/// Hands calls from an instance back to the dispatcher the host gave the wrapper.
+struct SharedDispatcher(Arc<RwLock<Option<Box<dyn Dispatcher>>>>);
+
+impl Dispatcher for SharedDispatcher {
+    fn dispatch(
+        &self,
+        actor: &str,
+        op: &str,
+        msg: &[u8],
+    ) -> Result<Vec<u8>, Box<dyn Error + Sync + Send>> {
+        match &*self.0.read().unwrap_or_else(|e| e.into_inner()) {
+            Some(dispatcher) => dispatcher.dispatch(actor, op, msg),
+            None => Err("no dispatcher has been configured".into()),
+        }
+    }
+}
+
+impl<P: CapabilityProvider + Clone + 'static> CapabilityProvider for PerActorProvider<P> {
+    fn configure_dispatch(
+        &self,
+        dispatcher: Box<dyn Dispatcher>,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        *self.dispatcher.write().unwrap_or_else(|e| e.into_inner()) = Some(dispatcher);
+        self.base
+            .configure_dispatch(Box::new(SharedDispatcher(self.dispatcher.clone())))
+    }
+
+    fn handle_call(
+        &self,
+        actor: &str,
+        op: &str,
+        msg: &[u8],
+    ) -> Result<Vec<u8>, Box<dyn Error + Sync + Send>> {
+        match (op, actor) {
+            (OP_BIND_ACTOR, SYSTEM_ACTOR) => {
+                let config = deserialize::<CapabilityConfiguration>(msg)?;
+                let instance = (self.new_instance)();
+                instance.configure_dispatch(Box::new(SharedDispatcher(self.dispatcher.clone())))?;
+                let result = instance.handle_call(actor, op, msg)?;
+                if let Some(previous) = self
+                    .instances
+                    .write()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .insert(config.module, instance)
+                {
+                    previous.stop();
+                }
+                Ok(result)
+            }
+            (OP_REMOVE_ACTOR, SYSTEM_ACTOR) => {
+                let config = deserialize::<CapabilityConfiguration>(msg)?;
+                let instance = self
+                    .instances
+                    .write()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .remove(&config.module);
+                match instance {
+                    Some(instance) => {
+                        let result = instance.handle_call(actor, op, msg);
+                        instance.stop();
+                        result
+                    }
+                    None => Ok(vec![]),
+                }
+            }
+            (_, SYSTEM_ACTOR) => self.base.handle_call(actor, op, msg),
+            _ => {
+                let instance = self
+                    .instances
+                    .read()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .get(actor)
+                    .cloned()
+                    .ok_or_else(|| format!("Actor {} is not bound to this capability", actor))?;
+                instance.handle_call(actor, op, msg)
+            }
+        }
+    }
+
+    fn stop(&self) {
+        let instances: Vec<P> = self
+            .instances
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .drain()
+            .map(|(_, instance)| instance)
+            .collect();
+        for instance in instances {
+            instance.stop();
+        }
+        self.base.stop()
+    }
+}
@@ -0,0 +1,146 @@
+use std::time::{Duration, Instant};
+
+use tokio::sync::Mutex;
+
+/// A token bucket that bounds how quickly starting pods call the Kubernetes API.
+///
+/// Tokens refill continuously at `qps` up to a maximum of `burst`.
+pub(crate) struct RateLimiter {
+    qps: f64,
+    burst: f64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, qps: f64, burst: f64) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * qps).min(burst);
+        self.last_refill = now;
+    }
+}
+
+impl RateLimiter {
+    pub(crate) fn new(qps: f64, burst: u32) -> Self {
+        let burst = f64::from(burst.max(1));
+        RateLimiter {
+            qps,
+            burst,
+            bucket: Mutex::new(Bucket {
+                tokens: burst,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Waits until `n` tokens are available and takes them. A QPS of 0 (or less) disables
+    /// limiting entirely.
+    ///
+    /// The tokens are reserved as soon as the bucket is locked, borrowing against future refills
+    /// if it is short, and the caller then sleeps off the debt without holding the bucket. Later
+    /// callers reserve behind the debt, so they are still served in order and the rate is kept,
+    /// but none of them waits on the lock itself.
+    pub(crate) async fn acquire(&self, n: usize) {
+        if self.qps <= 0.0 || n == 0 {
+            return;
+        }
+        let wait = {
+            let mut bucket = self.bucket.lock().await;
+            bucket.refill(self.qps, self.burst);
+            bucket.tokens -= n as f64;
+            -bucket.tokens / self.qps
+        };
+        if wait > 0.0 {
+            tokio::time::sleep(Duration::from_secs_f64(wait)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{Arc, Mutex as StdMutex};
+
+    /// Acquires a token for each of `calls` concurrent callers, recording when each was let
+    /// through, as an API server counting requests would.
+    async fn concurrent_calls(limiter: RateLimiter, calls: usize) -> Vec<Instant> {
+        let limiter = Arc::new(limiter);
+        let calls_made = Arc::new(StdMutex::new(Vec::new()));
+        let tasks: Vec<_> = (0..calls)
+            .map(|_| {
+                let limiter = limiter.clone();
+                let calls_made = calls_made.clone();
+                tokio::spawn(async move {
+                    limiter.acquire(1).await;
+                    calls_made.lock().unwrap().push(Instant::now());
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        let mut calls_made = calls_made.lock().unwrap().clone();
+        calls_made.sort();
+        calls_made
+    }
+
+    #[tokio::test]
+    async fn concurrent_callers_stay_under_qps() {
+        let (qps, burst, calls) = (20.0, 4, 24);
+        let start = Instant::now();
+        let calls_made = concurrent_calls(RateLimiter::new(qps, burst), calls).await;
+        assert_eq!(calls_made.len(), calls);
+
+        // The burst goes through at once, and every call after it waits for its own refill
+        for (i, made) in calls_made.iter().enumerate().skip(burst as usize) {
+            let earliest = (i + 1 - burst as usize) as f64 / qps;
+            let elapsed = made.duration_since(start).as_secs_f64();
+            assert!(
+                elapsed >= earliest - 0.001,
+                "call {} made after {}s, before its token at {}s",
+                i,
+                elapsed,
+                earliest
+            );
+        }
+        // No more than the burst plus a second's worth of calls in any second
+        for (i, made) in calls_made.iter().enumerate() {
+            let within_second = calls_made[i..]
+                .iter()
+                .take_while(|later| later.duration_since(*made) < Duration::from_secs(1))
+                .count();
+            assert!(within_second <= qps as usize + burst as usize);
+        }
+    }
+
+    #[tokio::test]
+    async fn large_acquire_does_not_hold_the_bucket() {
+        let limiter = Arc::new(RateLimiter::new(10.0, 1));
+        limiter.acquire(1).await;
+        let large = {
+            let limiter = limiter.clone();
+            tokio::spawn(async move { limiter.acquire(5).await })
+        };
+        // Let the large caller reserve its tokens and start sleeping
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(
+            limiter.bucket.try_lock().is_ok(),
+            "the bucket is locked while the large caller waits"
+        );
+        large.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn zero_qps_disables_limiting() {
+        let start = Instant::now();
+        let calls_made = concurrent_calls(RateLimiter::new(0.0, 1), 100).await;
+        assert_eq!(calls_made.len(), 100);
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use kubelet::container::Container;
+
+use crate::EnvVars;
+
+/// What a masked value is replaced with.
+const REDACTED: &str = "[REDACTED]";
+
+/// Masks sensitive env values in text the provider emits about a container, such as its
+/// status message and audit records.
+///
+/// A value is sensitive if the container's env takes it from a Secret, or if its key matches
+/// one of the node's sensitive key patterns.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Redactor {
+    /// The values to mask, longest first so a value containing another is masked whole.
+    values: Arc<Vec<String>>,
+}
+
+impl Redactor {
+    pub(crate) fn new(container: &Container, env: &EnvVars, patterns: &[String]) -> Self {
+        let from_secrets: BTreeSet<&str> = container
+            .env()
+            .iter()
+            .flatten()
+            .filter(|e| {
+                e.value_from
+                    .as_ref()
+                    .and_then(|from| from.secret_key_ref.as_ref())
+                    .is_some()
+            })
+            .map(|e| e.name.as_str())
+            .collect();
+        let mut values: Vec<String> = env
+            .iter()
+            .filter(|(key, value)| {
+                !value.is_empty()
+                    && (from_secrets.contains(key.as_str())
+                        || patterns.iter().any(|p| matches_pattern(p, key)))
+            })
+            .map(|(_, value)| value.clone())
+            .collect();
+        values.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+        values.dedup();
+        Redactor {
+            values: Arc::new(values),
+        }
+    }
+
+    /// Returns the text with every sensitive value replaced.
+    pub(crate) fn redact(&self, text: &str) -> String {
+        self.values
+            .iter()
+            .fold(text.to_owned(), |text, value| text.replace(value, REDACTED))
+    }
+}
+
+/// Matches an env key against a pattern, ignoring case. `*` in the pattern matches any run of
+/// characters, so `*_TOKEN` matches `GITHUB_TOKEN`. A pattern without one must match the whole
+/// key.
+fn matches_pattern(pattern: &str, key: &str) -> bool {
+    let pattern = pattern.to_uppercase();
+    let key = key.to_uppercase();
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let mut rest = match key.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let parts: Vec<&str> = parts.collect();
+    let (last, middle) = match parts.split_last() {
+        Some(split) => split,
+        None => return rest.is_empty(),
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use serde_json::json;
+
+    fn env(vars: &[(&str, &str)]) -> EnvVars {
+        vars.iter()
+            .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+            .collect()
+    }
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|p| (*p).to_owned()).collect()
+    }
+
+    #[test]
+    fn pattern_without_a_glob_matches_the_whole_key() {
+        assert!(matches_pattern("PASSWORD", "password"));
+        assert!(!matches_pattern("PASSWORD", "DB_PASSWORD"));
+        assert!(!matches_pattern("PASSWORD", "PASSWORD_FILE"));
+        assert!(!matches_pattern("", "PASSWORD"));
+    }
+
+    #[test]
+    fn glob_matches_any_run_of_characters() {
+        assert!(matches_pattern("*_TOKEN", "GITHUB_TOKEN"));
+        assert!(matches_pattern("*_TOKEN", "_TOKEN"));
+        assert!(!matches_pattern("*_TOKEN", "TOKEN"));
+        assert!(matches_pattern("API_*", "API_KEY"));
+        assert!(matches_pattern("*SECRET*", "SECRET"));
+        assert!(matches_pattern("*SECRET*", "MY_SECRET_VALUE"));
+        assert!(matches_pattern("*", ""));
+        assert!(matches_pattern("**", "ANYTHING"));
+    }
+
+    #[test]
+    fn globbed_parts_must_not_overlap() {
+        // The key has to hold both parts, one after the other
+        assert!(!matches_pattern("AB*BC", "ABC"));
+        assert!(matches_pattern("AB*BC", "ABBC"));
+        assert!(!matches_pattern("*KEY*KEY", "KEY"));
+        assert!(matches_pattern("*KEY*KEY", "KEY_KEY"));
+        assert!(matches_pattern("A*B*C", "AXBYC"));
+        assert!(!matches_pattern("A*B*C", "AXCYB"));
+    }
+
+    #[test]
+    fn secret_and_matching_values_are_redacted() {
+        let container = test_support::container(json!({
+            "name": "greet",
+            "image": "greet",
+            "env": [
+                {
+                    "name": "DB_URL",
+                    "valueFrom": { "secretKeyRef": { "name": "db", "key": "url" } }
+                },
+                { "name": "GITHUB_TOKEN", "value": "ghp_abc" },
+                { "name": "GREETING", "value": "hello" }
+            ]
+        }));
+        let env = env(&[
+            ("DB_URL", "postgres://db"),
+            ("GITHUB_TOKEN", "ghp_abc"),
+            ("GREETING", "hello"),
+        ]);
+        let redactor = Redactor::new(&container, &env, &patterns(&["*_token"]));
+        assert_eq!(
+            redactor.redact("hello postgres://db with ghp_abc"),
+            "hello [REDACTED] with [REDACTED]"
+        );
+    }
+
+    #[test]
+    fn longer_values_are_redacted_whole() {
+        let container = test_support::container(json!({ "name": "greet", "image": "greet" }));
+        let env = env(&[
+            ("SHORT_KEY", "abc"),
+            ("LONG_KEY", "abcdef"),
+            ("EMPTY_KEY", ""),
+        ]);
+        let redactor = Redactor::new(&container, &env, &patterns(&["*_KEY"]));
+        assert_eq!(redactor.redact("abcdef abc"), "[REDACTED] [REDACTED]");
+        // An empty value would match everywhere, so it is never masked
+        assert_eq!(redactor.redact("text"), "text");
+    }
+}
@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use kubelet::store::{PullPolicy, Store};
+use oci_distribution::secrets::RegistryAuth;
+use oci_distribution::Reference;
+
+/// A [`Store`] that refuses to fetch images from registries that aren't allowed.
+///
+/// It sits after any image rewrites, so it is the registry the image would actually be pulled
+/// from that is checked, and before signature verification, so nothing at all is fetched from a
+/// registry that isn't allowed.
+pub(crate) struct AllowlistStore {
+    inner: Arc<dyn Store + Sync + Send>,
+    /// Registry hosts, e.g. `ghcr.io` or `registry.internal:5000`.
+    registries: Vec<String>,
+}
+
+impl AllowlistStore {
+    pub(crate) fn new(inner: Arc<dyn Store + Sync + Send>, registries: Vec<String>) -> Self {
+        AllowlistStore { inner, registries }
+    }
+}
+
+#[async_trait::async_trait]
+impl Store for AllowlistStore {
+    async fn get(
+        &self,
+        image_ref: &Reference,
+        pull_policy: PullPolicy,
+        auth: &RegistryAuth,
+    ) -> anyhow::Result<Vec<u8>> {
+        let registry = image_ref.registry();
+        if !self.registries.iter().any(|r| r == registry) {
+            return Err(anyhow::anyhow!(
+                "RegistryNotAllowed: image {} is from registry {}, but this node only pulls from {}",
+                image_ref,
+                registry,
+                self.registries.join(", ")
+            ));
+        }
+        self.inner.get(image_ref, pull_policy, auth).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fetch, RecordingStore};
+
+    fn allowing(registries: &[&str]) -> (Arc<RecordingStore>, AllowlistStore) {
+        let inner = Arc::new(RecordingStore::default());
+        let store = AllowlistStore::new(
+            inner.clone(),
+            registries.iter().map(|r| (*r).to_owned()).collect(),
+        );
+        (inner, store)
+    }
+
+    #[tokio::test]
+    async fn allowed_registries_are_fetched_from() {
+        let (inner, store) = allowing(&["ghcr.io", "registry.internal:5000"]);
+        fetch(&store, "ghcr.io/wasmcloud/echo:v1").await.unwrap();
+        fetch(&store, "registry.internal:5000/greet:v1")
+            .await
+            .unwrap();
+        assert_eq!(
+            *inner.fetched.lock().unwrap(),
+            vec![
+                "ghcr.io/wasmcloud/echo:v1",
+                "registry.internal:5000/greet:v1"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn other_registries_are_refused_without_fetching() {
+        let (inner, store) = allowing(&["ghcr.io"]);
+        let err = fetch(&store, "docker.io/library/greet:v1")
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().starts_with("RegistryNotAllowed"),
+            "got: {}",
+            err
+        );
+        // The port is part of the registry, so the same host on another port isn't allowed
+        assert!(fetch(&store, "ghcr.io:8443/wasmcloud/echo:v1")
+            .await
+            .is_err());
+        assert!(inner.fetched.lock().unwrap().is_empty());
+    }
+}
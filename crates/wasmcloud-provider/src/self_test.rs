@@ -0,0 +1,197 @@
+//! Checks that each built-in capability provider works on this node before pods are accepted.
+//!
+//! Each check runs against a fresh instance of the provider, bound to a throwaway actor, so the
+//! instances registered with the host and any actors linked to them are left alone. As the
+//! providers are compiled into this binary, a fresh instance exercises the same code and native
+//! libraries as the registered one.
+
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+use wasmcloud_actor_core::{CapabilityConfiguration, HealthCheckRequest, HealthCheckResponse};
+use wasmcloud_actor_logging::{WriteLogArgs, OP_LOG};
+use wasmcloud_fs::FileSystemProvider;
+use wasmcloud_httpserver::HttpServerProvider;
+use wasmcloud_logging::{LoggingProvider, LOG_PATH_KEY};
+use wasmcloud_provider_core::capabilities::CapabilityProvider;
+use wasmcloud_provider_core::core::{OP_BIND_ACTOR, OP_HEALTH_REQUEST, OP_REMOVE_ACTOR};
+use wasmcloud_provider_core::{deserialize, serialize};
+
+use crate::{FS_CAPABILITY, FS_CONFIG_ROOTDIR, HTTP_CAPABILITY, LOG_CAPABILITY, PORT_KEY};
+
+/// The actor the checks bind the providers to.
+const SELF_TEST_ACTOR: &str = "krustlet-self-test";
+
+/// The origin of calls made by the wasmCloud host itself.
+const SYSTEM_ACTOR: &str = "system";
+
+/// How long the HTTP server is given to start listening.
+const HTTP_LISTEN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The outcome of checking one capability.
+#[derive(Clone, Debug)]
+pub struct SelfTestResult {
+    /// The capability contract ID, e.g. `wasmcloud:httpserver`.
+    pub capability: &'static str,
+    /// Why the check failed. `None` if it passed.
+    pub error: Option<String>,
+}
+
+impl SelfTestResult {
+    /// Whether the capability passed its check.
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Runs every check, returning one result per capability.
+pub(crate) async fn run() -> Vec<SelfTestResult> {
+    // The providers are synchronous and may block, so keep them off the async workers
+    let checks: [(&'static str, fn() -> anyhow::Result<()>); 3] = [
+        (LOG_CAPABILITY, check_logging),
+        (FS_CAPABILITY, check_fs),
+        (HTTP_CAPABILITY, check_http),
+    ];
+    let mut results = Vec::with_capacity(checks.len());
+    for (capability, check) in checks.iter().copied() {
+        let error = match tokio::task::spawn_blocking(check).await {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some(e.to_string()),
+            Err(e) => Some(format!("the check panicked: {}", e)),
+        };
+        results.push(SelfTestResult { capability, error });
+    }
+    results
+}
+
+/// Writes a line through the log capability and reads it back from the log file.
+fn check_logging() -> anyhow::Result<()> {
+    let log_file = tempfile::NamedTempFile::new()?;
+    let provider = LoggingProvider::new();
+    bind(
+        &provider,
+        vec![(
+            LOG_PATH_KEY,
+            log_file.path().to_str().unwrap_or_default().to_owned(),
+        )],
+    )?;
+    let text = "wasmCloud provider self-test";
+    let result = call(
+        &provider,
+        SELF_TEST_ACTOR,
+        OP_LOG,
+        &serialize(WriteLogArgs {
+            level: "info".to_owned(),
+            target: SELF_TEST_ACTOR.to_owned(),
+            text: text.to_owned(),
+        })
+        .map_err(|e| anyhow::anyhow!("{}", e))?,
+    )
+    .and_then(|_| {
+        let written = std::fs::read_to_string(log_file.path())?;
+        if written.contains(text) {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!(
+                "the line logged was not written to {}",
+                log_file.path().display()
+            ))
+        }
+    });
+    unbind(&provider)?;
+    result
+}
+
+/// Binds the FS capability to a throwaway root directory and asks it for its health.
+fn check_fs() -> anyhow::Result<()> {
+    let root = tempfile::tempdir()?;
+    let provider = FileSystemProvider::new();
+    bind(
+        &provider,
+        vec![(
+            FS_CONFIG_ROOTDIR,
+            root.path().to_str().unwrap_or_default().to_owned(),
+        )],
+    )?;
+    let result = check_health(&provider);
+    unbind(&provider)?;
+    result
+}
+
+/// Binds the HTTP capability to a free local port and checks that it starts listening.
+fn check_http() -> anyhow::Result<()> {
+    // Let the OS pick a free port, then hand it to the provider
+    let port = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?
+        .local_addr()?
+        .port();
+    let provider = HttpServerProvider::new();
+    bind(&provider, vec![(PORT_KEY, port.to_string())])?;
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    let deadline = Instant::now() + HTTP_LISTEN_TIMEOUT;
+    let result = loop {
+        match TcpStream::connect_timeout(&addr, Duration::from_millis(200)) {
+            Ok(_) => break Ok(()),
+            Err(e) if Instant::now() >= deadline => {
+                break Err(anyhow::anyhow!(
+                    "the server did not start listening on port {} within {:?}: {}",
+                    port,
+                    HTTP_LISTEN_TIMEOUT,
+                    e
+                ))
+            }
+            Err(_) => std::thread::sleep(Duration::from_millis(100)),
+        }
+    };
+    unbind(&provider)?;
+    result
+}
+
+fn bind(provider: &dyn CapabilityProvider, values: Vec<(&str, String)>) -> anyhow::Result<()> {
+    let config = CapabilityConfiguration {
+        module: SELF_TEST_ACTOR.to_owned(),
+        values: values
+            .into_iter()
+            .map(|(key, value)| (key.to_owned(), value))
+            .collect::<HashMap<_, _>>(),
+    };
+    let msg = serialize(config).map_err(|e| anyhow::anyhow!("{}", e))?;
+    call(provider, SYSTEM_ACTOR, OP_BIND_ACTOR, &msg)
+        .map(|_| ())
+        .map_err(|e| anyhow::anyhow!("unable to bind: {}", e))
+}
+
+fn unbind(provider: &dyn CapabilityProvider) -> anyhow::Result<()> {
+    let config = CapabilityConfiguration {
+        module: SELF_TEST_ACTOR.to_owned(),
+        values: HashMap::new(),
+    };
+    let msg = serialize(config).map_err(|e| anyhow::anyhow!("{}", e))?;
+    call(provider, SYSTEM_ACTOR, OP_REMOVE_ACTOR, &msg)
+        .map(|_| ())
+        .map_err(|e| anyhow::anyhow!("unable to unbind: {}", e))
+}
+
+fn check_health(provider: &dyn CapabilityProvider) -> anyhow::Result<()> {
+    let msg = serialize(HealthCheckRequest { placeholder: true })
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+    let response = call(provider, SYSTEM_ACTOR, OP_HEALTH_REQUEST, &msg)?;
+    let health: HealthCheckResponse =
+        deserialize(&response).map_err(|e| anyhow::anyhow!("invalid health response: {}", e))?;
+    if health.healthy {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("reported unhealthy: {}", health.message))
+    }
+}
+
+fn call(
+    provider: &dyn CapabilityProvider,
+    actor: &str,
+    op: &str,
+    msg: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    provider
+        .handle_call(actor, op, msg)
+        .map_err(|e| anyhow::anyhow!("{} failed: {}", op, e))
+}
@@ -0,0 +1,303 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use k8s_openapi::api::core::v1::{Endpoints, Service};
+use kube::api::Api;
+use kubelet::pod::Pod;
+use log::{debug, error, info};
+use serde_derive::Serialize;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use wasmcloud_host::Host;
+
+use crate::rate_limit::RateLimiter;
+use crate::{Capability, SERVICES_ANNOTATION};
+
+/// The env key holding the resolved services as a JSON object keyed by `name.namespace`.
+pub(crate) const SERVICES_KEY: &str = "SERVICES";
+
+/// How often the watched services are looked up again to pick up endpoint changes.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A Service named in the services annotation.
+#[derive(Clone, Debug)]
+pub(crate) struct ServiceRef {
+    namespace: String,
+    name: String,
+}
+
+/// The services an actor watches and where to look them up.
+pub(crate) struct ServiceWatch {
+    pub(crate) client: kube::Client,
+    pub(crate) api_rate_limiter: Arc<RateLimiter>,
+    pub(crate) services: Vec<ServiceRef>,
+    /// The value the actor was linked with.
+    pub(crate) current: String,
+}
+
+/// What an actor is told about a Service.
+#[derive(Debug, Serialize)]
+struct ServiceInfo {
+    cluster_ip: Option<String>,
+    ports: Vec<PortInfo>,
+    /// The ready endpoints as `ip:port`.
+    endpoints: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct PortInfo {
+    name: Option<String>,
+    port: i32,
+}
+
+/// Returns the services listed in the pod's services annotation. Entries are either a name in
+/// the pod's own namespace or `namespace/name`.
+pub(crate) fn watched_services(pod: &Pod) -> anyhow::Result<Vec<ServiceRef>> {
+    let list = match pod.annotations().get(SERVICES_ANNOTATION) {
+        Some(list) => list,
+        None => return Ok(vec![]),
+    };
+    list.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|entry| {
+            let mut parts = entry.splitn(2, '/');
+            let first = parts.next().unwrap_or_default();
+            let (namespace, name) = match parts.next() {
+                Some(name) => (first.to_owned(), name.to_owned()),
+                None => (pod.namespace().to_owned(), first.to_owned()),
+            };
+            if namespace.is_empty() || name.is_empty() || name.contains('/') {
+                return Err(anyhow::anyhow!(
+                    "Cannot run {}: {} annotation has invalid service {:?}",
+                    pod.name(),
+                    SERVICES_ANNOTATION,
+                    entry
+                ));
+            }
+            Ok(ServiceRef { namespace, name })
+        })
+        .collect()
+}
+
+/// Looks up the services' ClusterIPs and ready endpoints, returning them as the JSON value of
+/// [`SERVICES_KEY`]. Each service costs two API calls.
+pub(crate) async fn resolve_services(
+    client: &kube::Client,
+    api_rate_limiter: &RateLimiter,
+    services: &[ServiceRef],
+) -> anyhow::Result<String> {
+    let mut resolved = BTreeMap::new();
+    for service in services {
+        api_rate_limiter.acquire(2).await;
+        let svc = Api::<Service>::namespaced(client.clone(), &service.namespace)
+            .get(&service.name)
+            .await
+            .map_err(|e| {
+                anyhow::anyhow!(
+                    "Unable to get service {} in namespace {}: {}",
+                    service.name,
+                    service.namespace,
+                    e
+                )
+            })?;
+        let endpoints = Api::<Endpoints>::namespaced(client.clone(), &service.namespace)
+            .get(&service.name)
+            .await
+            .map_err(|e| {
+                anyhow::anyhow!(
+                    "Unable to get endpoints for service {} in namespace {}: {}",
+                    service.name,
+                    service.namespace,
+                    e
+                )
+            })?;
+        resolved.insert(
+            format!("{}.{}", service.name, service.namespace),
+            service_info(svc, endpoints),
+        );
+    }
+    Ok(serde_json::to_string(&resolved)?)
+}
+
+fn service_info(svc: Service, endpoints: Endpoints) -> ServiceInfo {
+    let spec = svc.spec.unwrap_or_default();
+    let ports = spec
+        .ports
+        .unwrap_or_default()
+        .into_iter()
+        .map(|p| PortInfo {
+            name: p.name,
+            port: p.port,
+        })
+        .collect();
+    let mut addresses = Vec::new();
+    for subset in endpoints.subsets.unwrap_or_default() {
+        let ports = subset.ports.unwrap_or_default();
+        for address in subset.addresses.unwrap_or_default() {
+            for port in ports.iter() {
+                addresses.push(format!("{}:{}", address.ip, port.port));
+            }
+        }
+    }
+    addresses.sort();
+    ServiceInfo {
+        cluster_ip: spec.cluster_ip,
+        ports,
+        endpoints: addresses,
+    }
+}
+
+/// Keeps an actor's links up to date with its watched services.
+///
+/// The services are looked up every [`REFRESH_INTERVAL`]. When the result differs from
+/// the last value, every link is set again with the new value so the capability providers see it.
+/// Lookup failures are logged and retried on the next tick; the last known value is kept.
+pub(crate) fn spawn_refresh(
+    host: Arc<Mutex<Host>>,
+    actor: String,
+    capabilities: Arc<Mutex<Vec<Capability>>>,
+    watch: ServiceWatch,
+) -> JoinHandle<()> {
+    let ServiceWatch {
+        client,
+        api_rate_limiter,
+        services,
+        mut current,
+    } = watch;
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(REFRESH_INTERVAL).await;
+            let resolved = match resolve_services(&client, &api_rate_limiter, &services).await {
+                Ok(resolved) => resolved,
+                Err(e) => {
+                    error!("Unable to refresh services for actor {}: {}", actor, e);
+                    continue;
+                }
+            };
+            if resolved == current {
+                continue;
+            }
+            info!("Services changed for actor {}, updating links", actor);
+            let mut capabilities = capabilities.lock().await;
+            let lock = host.lock().await;
+            for cap in capabilities.iter_mut() {
+                cap.env.insert(SERVICES_KEY.to_owned(), resolved.clone());
+                debug!("Updating {} link for actor {}", cap.name, actor);
+                if let Err(e) = lock
+                    .set_link(
+                        &actor,
+                        cap.name,
+                        cap.binding.clone(),
+                        cap.capability_provider_id.to_owned(),
+                        cap.env.clone(),
+                    )
+                    .await
+                {
+                    error!(
+                        "Unable to update {} link for actor {}: {}",
+                        cap.name, actor, e
+                    );
+                }
+            }
+            current = resolved;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    use crate::test_support;
+
+    fn pod(services: &str) -> Pod {
+        test_support::pod(json!({
+            "metadata": {
+                "namespace": "apps",
+                "annotations": { SERVICES_ANNOTATION: services }
+            },
+            "spec": { "containers": [{ "name": "greet", "image": "greet:1" }] }
+        }))
+    }
+
+    fn names(services: Vec<ServiceRef>) -> Vec<String> {
+        services
+            .into_iter()
+            .map(|s| format!("{}/{}", s.namespace, s.name))
+            .collect()
+    }
+
+    #[test]
+    fn watched_services_default_to_the_pods_namespace() {
+        let services = watched_services(&pod("db, cache/redis,,")).unwrap();
+        assert_eq!(names(services), ["apps/db", "cache/redis"]);
+    }
+
+    #[test]
+    fn no_annotation_watches_nothing() {
+        let pod = test_support::pod(json!({
+            "spec": { "containers": [{ "name": "greet", "image": "greet:1" }] }
+        }));
+        assert!(watched_services(&pod).unwrap().is_empty());
+    }
+
+    #[test]
+    fn invalid_services_rejected() {
+        for invalid in &["/db", "cache/", "a/b/c"] {
+            let err = watched_services(&pod(invalid)).unwrap_err();
+            assert!(err.to_string().contains(invalid), "{}", err);
+        }
+    }
+
+    #[test]
+    fn service_info_has_cluster_ip_ports_and_ready_endpoints() {
+        let svc: Service = serde_json::from_value(json!({
+            "metadata": { "name": "db", "namespace": "apps" },
+            "spec": {
+                "clusterIP": "10.96.0.20",
+                "ports": [{ "name": "sql", "port": 5432 }]
+            }
+        }))
+        .unwrap();
+        let endpoints: Endpoints = serde_json::from_value(json!({
+            "metadata": { "name": "db", "namespace": "apps" },
+            "subsets": [{
+                "addresses": [{ "ip": "10.244.1.7" }, { "ip": "10.244.0.3" }],
+                "notReadyAddresses": [{ "ip": "10.244.2.9" }],
+                "ports": [{ "name": "sql", "port": 5432 }]
+            }]
+        }))
+        .unwrap();
+
+        let info = serde_json::to_value(service_info(svc, endpoints)).unwrap();
+        assert_eq!(
+            info,
+            json!({
+                "cluster_ip": "10.96.0.20",
+                "ports": [{ "name": "sql", "port": 5432 }],
+                "endpoints": ["10.244.0.3:5432", "10.244.1.7:5432"]
+            })
+        );
+    }
+
+    #[test]
+    fn service_without_endpoints() {
+        let svc: Service = serde_json::from_value(json!({
+            "metadata": { "name": "db" },
+            "spec": { "clusterIP": "None" }
+        }))
+        .unwrap();
+        let endpoints: Endpoints =
+            serde_json::from_value(json!({ "metadata": { "name": "db" } })).unwrap();
+
+        let info = serde_json::to_value(service_info(svc, endpoints)).unwrap();
+        assert_eq!(
+            info,
+            json!({ "cluster_ip": "None", "ports": [], "endpoints": [] })
+        );
+    }
+}
@@ -1,8 +1,15 @@
+use std::sync::Arc;
+
+use crate::rate_limit::RateLimiter;
+use crate::redact::Redactor;
+use crate::DedicatedHost;
 use crate::ModuleRunContext;
 use crate::ProviderState;
 use krator::{ObjectState, SharedState};
 use kubelet::container::{Container, ContainerKey, Status};
 use kubelet::pod::Pod;
+use tokio::sync::{oneshot, watch};
+use tokio::time::Instant;
 
 pub(crate) mod running;
 pub(crate) mod terminated;
@@ -12,6 +19,18 @@ pub(crate) struct ContainerState {
     pod: Pod,
     container_key: ContainerKey,
     run_context: SharedState<ModuleRunContext>,
+    /// Notified once the actor has been started and its handle registered.
+    started: Option<oneshot::Sender<()>>,
+    /// When the actor must have started by, if the provider has a start deadline.
+    start_deadline: Option<Instant>,
+    /// Becomes true when the pod is deleted.
+    deleted: watch::Receiver<bool>,
+    /// Masks the container's sensitive env values, once its env has been resolved.
+    redactor: Redactor,
+    /// The pod's own wasmCloud host, which the actor runs in instead of the shared one.
+    dedicated_host: Option<DedicatedHost>,
+    /// Meters the container's status patches.
+    api_rate_limiter: Arc<RateLimiter>,
 }
 
 impl ContainerState {
@@ -19,11 +38,22 @@ impl ContainerState {
         pod: Pod,
         container_key: ContainerKey,
         run_context: SharedState<ModuleRunContext>,
+        started: oneshot::Sender<()>,
+        start_deadline: Option<Instant>,
+        deleted: watch::Receiver<bool>,
+        dedicated_host: Option<DedicatedHost>,
+        api_rate_limiter: Arc<RateLimiter>,
     ) -> Self {
         ContainerState {
             pod,
             container_key,
             run_context,
+            started: Some(started),
+            start_deadline,
+            deleted,
+            redactor: Redactor::default(),
+            dedicated_host,
+            api_rate_limiter,
         }
     }
 }
@@ -23,9 +23,10 @@ impl State<ContainerState> for Running {
 
     async fn status(
         &self,
-        _state: &mut ContainerState,
+        state: &mut ContainerState,
         _container: &Container,
     ) -> anyhow::Result<Status> {
+        state.api_rate_limiter.acquire(1).await;
         Ok(Status::running())
     }
 }
@@ -34,9 +34,9 @@ impl State<ContainerState> for Terminated {
                 "Pod {} container {} exited with error: {}",
                 state.pod.name(),
                 container.name(),
-                &self.message
+                state.redactor.redact(&self.message)
             );
-            Transition::Complete(Err(anyhow::anyhow!(self.message.clone())))
+            Transition::Complete(Err(anyhow::anyhow!(state.redactor.redact(&self.message))))
         } else {
             Transition::Complete(Ok(()))
         }
@@ -44,9 +44,13 @@ impl State<ContainerState> for Terminated {
 
     async fn status(
         &self,
-        _state: &mut ContainerState,
+        state: &mut ContainerState,
         _container: &Container,
     ) -> anyhow::Result<Status> {
-        Ok(Status::terminated(&self.message, self.failed))
+        state.api_rate_limiter.acquire(1).await;
+        Ok(Status::terminated(
+            &state.redactor.redact(&self.message),
+            self.failed,
+        ))
     }
 }
@@ -1,20 +1,37 @@
 use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::convert::TryFrom;
+use std::net::{Ipv4Addr, TcpListener};
 use std::ops::Deref;
+use std::path::{Component, Path, PathBuf};
 use std::sync::Arc;
 
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use rand::Rng;
-use tokio::sync::Mutex;
+use tokio::sync::{watch, Mutex};
+use tokio::time::Instant;
+use wasmcloud_logging::LOG_SAMPLE_RATE_KEY;
 
 use kubelet::container::state::prelude::*;
 use kubelet::pod::{Handle as PodHandle, Pod, PodKey};
 use kubelet::provider::Provider;
 
+use crate::config_maps::{config_map_volumes, ConfigMapWatch};
+use crate::dns_env;
+use crate::redact::Redactor;
+use crate::services::{resolve_services, watched_services, ServiceWatch, SERVICES_KEY};
+use crate::state_dir;
 use crate::wasmcloud_run;
+use crate::EnvVars;
+use crate::ProviderEvent;
 use crate::ProviderState;
 use crate::VolumeBinding;
 use crate::WasmCloudProvider;
+use crate::BLOBSTORE_OPS_ANNOTATION_PREFIX;
+use crate::HOST_NETWORK_ANNOTATION;
+use crate::HTTP_CAPABILITY;
+use crate::LOG_SAMPLE_RATE_ANNOTATION;
+use crate::PORT_KEY;
+use crate::STATE_DIR_KEY;
 
 use super::running::Running;
 use super::terminated::Terminated;
@@ -53,21 +70,242 @@ async fn find_available_port(
     Err(PortAllocationError)
 }
 
+/// How many randomly assigned ports are tried before giving up when something outside the
+/// provider holds them.
+const PORT_BIND_ATTEMPTS: usize = 10;
+
+/// Checks that the port can be bound on the node. `port_map` only knows about ports our own
+/// actors hold, so this catches ports taken by other processes, which the HTTP capability
+/// would otherwise fail to bind with an unhelpful error deep in the start.
+fn check_bindable(port: u16) -> std::io::Result<()> {
+    TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)).map(|_| ())
+}
+
+/// Assigns a random free port, skipping any that something else on the node is bound to.
+async fn find_bindable_port(
+    port_map: &Arc<Mutex<BTreeMap<u16, PodKey>>>,
+    pod: &Pod,
+) -> anyhow::Result<u16> {
+    for _ in 0..PORT_BIND_ATTEMPTS {
+        let port = find_available_port(port_map, pod).await?;
+        match check_bindable(port) {
+            Ok(()) => return Ok(port),
+            Err(e) => {
+                warn!(
+                    "Assigned port {} can't be bound, trying another: {}",
+                    port, e
+                );
+                port_map.lock().await.remove(&port);
+            }
+        }
+    }
+    Err(anyhow::anyhow!(
+        "PortBindFailed: none of {} randomly assigned ports could be bound on this node",
+        PORT_BIND_ATTEMPTS
+    ))
+}
+
+/// Releases an explicitly requested port if it can't be bound, as there is no other port the
+/// user would accept.
+async fn release_unbindable(
+    port_map: &Arc<Mutex<BTreeMap<u16, PodKey>>>,
+    port: u16,
+) -> anyhow::Result<u16> {
+    if let Err(e) = check_bindable(port) {
+        port_map.lock().await.remove(&port);
+        return Err(anyhow::anyhow!(
+            "PortBindFailed: port {} is in use by another process on this node: {}",
+            port,
+            e
+        ));
+    }
+    Ok(port)
+}
+
+/// Returns true if the pod asked to bind its ports directly on the node, either through
+/// `spec.hostNetwork` or the host network annotation.
+fn uses_host_network(pod: &Pod) -> bool {
+    let spec_host_network = pod
+        .as_kube_pod()
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.host_network)
+        .unwrap_or(false);
+    spec_host_network
+        || pod
+            .annotations()
+            .get(HOST_NETWORK_ANNOTATION)
+            .map(|v| v == "true")
+            .unwrap_or(false)
+}
+
+/// Checks that none of the node ports the pod's containers ask for, through `hostPort` or as
+/// host network container ports, are already held by another pod. This refuses a conflicting
+/// pod before any of its containers start, naming the pod in the way; the ports are still only
+/// claimed as each container starts.
+pub(crate) async fn check_host_ports(
+    port_map: &Arc<Mutex<BTreeMap<u16, PodKey>>>,
+    pod: &Pod,
+) -> anyhow::Result<()> {
+    let host_network = uses_host_network(pod);
+    let pod_key = PodKey::from(pod);
+    let lock = port_map.lock().await;
+    for container in pod.containers() {
+        for c_port in container.ports().iter().flatten() {
+            let requested = if host_network {
+                Some(c_port.container_port)
+            } else {
+                c_port.host_port
+            };
+            let port = match requested.map(u16::try_from) {
+                Some(Ok(port)) => port,
+                _ => continue,
+            };
+            if let Some(owner) = lock.get(&port).filter(|owner| **owner != pod_key) {
+                return Err(anyhow::anyhow!(
+                    "Cannot run {}: container {} asks for host port {}, which pod {} in namespace {} already holds on this node",
+                    pod.name(),
+                    container.name(),
+                    port,
+                    owner.name(),
+                    owner.namespace()
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Claims the container port itself for a host network pod. There is no remapping in host
+/// network mode, so a `hostPort`, if given, must match the `containerPort`.
+async fn claim_host_network_port(
+    port_map: &Arc<Mutex<BTreeMap<u16, PodKey>>>,
+    pod: &Pod,
+    container_port: i32,
+    host_port: Option<i32>,
+) -> anyhow::Result<u16> {
+    if let Some(host_port) = host_port {
+        if host_port != container_port {
+            return Err(anyhow::anyhow!(
+                "hostPort {} does not match containerPort {}; host network pods bind the container port directly",
+                host_port,
+                container_port
+            ));
+        }
+    }
+    let port = u16::try_from(container_port)?;
+    let mut lock = port_map.lock().await;
+    if let Some(owner) = lock.get(&port) {
+        error!(
+            "Failed to bind host network port {}, because it's taken by pod {} in namespace {}",
+            port,
+            owner.name(),
+            owner.namespace()
+        );
+        return Err(anyhow::anyhow!(
+            "Port {} is already bound on this node by pod {} in namespace {}",
+            port,
+            owner.name(),
+            owner.namespace()
+        ));
+    }
+    lock.insert(port, PodKey::from(pod));
+    Ok(port)
+}
+
+/// Claims the port the user asked for through the container's `PORT` env var. A host network
+/// pod binds it directly on the node, so it must be one of the container ports it declares, if
+/// it declares any, and follows the same rules as they do.
+async fn claim_env_port(
+    port_map: &Arc<Mutex<BTreeMap<u16, PodKey>>>,
+    pod: &Pod,
+    container: &Container,
+    port: &str,
+) -> anyhow::Result<u16> {
+    let port: u16 = port
+        .parse()
+        .map_err(|e| anyhow::anyhow!("Invalid {} value {:?}: {}", PORT_KEY, port, e))?;
+    if uses_host_network(pod) {
+        let declared: Vec<_> = container.ports().iter().flatten().collect();
+        if let Some(c_port) = declared
+            .iter()
+            .find(|c_port| c_port.container_port == i32::from(port))
+        {
+            return claim_host_network_port(port_map, pod, c_port.container_port, c_port.host_port)
+                .await;
+        }
+        if !declared.is_empty() {
+            return Err(anyhow::anyhow!(
+                "{} {} does not match any containerPort; host network pods bind the container port directly",
+                PORT_KEY,
+                port
+            ));
+        }
+        return claim_host_network_port(port_map, pod, i32::from(port), None).await;
+    }
+    let mut lock = port_map.lock().await;
+    if let Some(owner) = lock.get(&port) {
+        error!(
+            "Failed to assign {} {}, because it's taken by pod {} in namespace {}",
+            PORT_KEY,
+            port,
+            owner.name(),
+            owner.namespace()
+        );
+        return Err(anyhow::anyhow!("Port {} is currently in use", port));
+    }
+    lock.insert(port, PodKey::from(pod));
+    Ok(port)
+}
+
+/// Whether the actor module claims the HTTP capability. A module whose claims can't be read
+/// fails to load when it is started, so it is treated as not linking it.
+fn links_http(module: &[u8]) -> bool {
+    wascap::wasm::extract_claims(module)
+        .ok()
+        .flatten()
+        .and_then(|token| token.claims.metadata)
+        .and_then(|actor| actor.caps)
+        .map(|caps| caps.iter().any(|cap| cap == HTTP_CAPABILITY))
+        .unwrap_or(false)
+}
+
+/// Picks the port the actor's HTTP capability listens on. In order of precedence: a `PORT` set
+/// explicitly in the container's env, the `hostPort`, then a randomly assigned one. The `PORT`
+/// env var is only claimed for actors that link the HTTP capability; for others it is just
+/// another env var.
+///
+/// A randomly assigned port that turns out to be bound by another process is swapped for
+/// another; an explicit one fails with `PortBindFailed`.
 async fn assign_container_port(
     port_map: Arc<Mutex<BTreeMap<u16, PodKey>>>,
     pod: &Pod,
     container: &Container,
+    env: &EnvVars,
+    http_linked: bool,
 ) -> anyhow::Result<u16> {
+    if let Some(port) = env.get(PORT_KEY).filter(|_| http_linked) {
+        let port = claim_env_port(&port_map, pod, container, port).await?;
+        return release_unbindable(&port_map, port).await;
+    }
+    let host_network = uses_host_network(pod);
     let mut port_assigned: u16 = 0;
     if let Some(container_vec) = container.ports().as_ref() {
         for c_port in container_vec.iter() {
             let container_port = c_port.container_port;
-            if let Some(host_port) = c_port.host_port {
+            if host_network {
+                port_assigned =
+                    claim_host_network_port(&port_map, pod, container_port, c_port.host_port)
+                        .await?;
+                release_unbindable(&port_map, port_assigned).await?;
+            } else if let Some(host_port) = c_port.host_port {
                 let host_port: u16 = u16::try_from(host_port)?;
                 let mut lock = port_map.lock().await;
                 if !lock.contains_key(&host_port) {
                     port_assigned = host_port;
                     lock.insert(port_assigned, PodKey::from(pod));
+                    drop(lock);
+                    release_unbindable(&port_map, port_assigned).await?;
                 } else {
                     error!(
                         "Failed to assign hostport {}, because it's taken",
@@ -76,13 +314,67 @@ async fn assign_container_port(
                     return Err(anyhow::anyhow!("Port {} is currently in use", &host_port));
                 }
             } else if (0..=65536).contains(&container_port) {
-                port_assigned = find_available_port(&port_map, pod).await?;
+                port_assigned = find_bindable_port(&port_map, pod).await?;
             }
         }
     }
     Ok(port_assigned)
 }
 
+/// Runs the start unless the pod is deleted first. The start is then dropped, which rolls back
+/// whatever it had done.
+async fn unless_deleted<T>(
+    start: impl std::future::Future<Output = anyhow::Result<T>>,
+    mut deleted: watch::Receiver<bool>,
+) -> anyhow::Result<T> {
+    let wait_for_delete = async {
+        while !*deleted.borrow() {
+            // The pod's state having gone counts as deleted too
+            if deleted.changed().await.is_err() {
+                break;
+            }
+        }
+    };
+    tokio::select! {
+        result = start => result,
+        _ = wait_for_delete => Err(anyhow::anyhow!("the pod was deleted while it was starting")),
+    }
+}
+
+/// Runs the start, giving up on it at the deadline if there is one. Giving up drops the
+/// unfinished start, which rolls back whatever it had done, and returns `None`.
+async fn until_deadline<T>(
+    start: impl std::future::Future<Output = anyhow::Result<T>>,
+    deadline: Option<Instant>,
+) -> Option<anyhow::Result<T>> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, start).await.ok(),
+        None => Some(start.await),
+    }
+}
+
+/// Returns the directory a `subPath` mount exposes, creating it if needed as the kubelet does.
+/// Containers mounting different subPaths of one volume share its directory but each only
+/// sees its own subtree.
+fn sub_path_root(volume: &Path, sub_path: &str) -> anyhow::Result<PathBuf> {
+    let relative = Path::new(sub_path);
+    if relative.is_absolute() || relative.components().any(|c| c == Component::ParentDir) {
+        return Err(anyhow::anyhow!(
+            "subPath {:?} must be a relative path that doesn't contain `..`",
+            sub_path
+        ));
+    }
+    let root = volume.join(relative);
+    std::fs::create_dir_all(&root).map_err(|e| {
+        anyhow::anyhow!(
+            "unable to create subPath {:?} of the volume: {}",
+            sub_path,
+            e
+        )
+    })?;
+    Ok(root)
+}
+
 /// The container is starting.
 #[derive(Default, Debug, TransitionTo)]
 #[transition_to(Running, Terminated)]
@@ -104,9 +396,122 @@ impl State<ContainerState> for Waiting {
             state.pod.name(),
         );
 
+        let (
+            client,
+            log_storage,
+            host,
+            api_rate_limiter,
+            audit,
+            keep_failed,
+            no_capabilities,
+            fs_disabled,
+            link_retries,
+            fs_providers,
+            log_streams,
+            capability_defaults,
+            state_base,
+            sensitive_env,
+        ) = {
+            let state_reader = shared.read().await;
+            (
+                state_reader.client.clone(),
+                state_reader.log_storage.clone(),
+                state_reader.host.clone(),
+                state_reader.api_rate_limiter.clone(),
+                state_reader.audit.clone(),
+                state_reader.keep_failed,
+                state_reader.no_capabilities,
+                state_reader.fs_disabled,
+                state_reader.link_retries,
+                state_reader.fs_providers.clone(),
+                state_reader.log_streams.clone(),
+                state_reader.capability_defaults.clone(),
+                state_reader.state_dir.clone(),
+                state_reader.sensitive_env.clone(),
+            )
+        };
+
+        // A pod with a dedicated host runs its actor and capabilities there instead
+        let (host, fs_providers) = match &state.dedicated_host {
+            Some(dedicated) => (dedicated.host.clone(), dedicated.fs_providers.clone()),
+            None => (host, fs_providers),
+        };
+
+        let log_storage = match log_storage.for_pod(&state.pod) {
+            Ok(log_storage) => log_storage,
+            Err(e) => return Transition::next(self, Terminated::new(e.to_string(), true)),
+        };
+
+        // Each ConfigMap or Secret reference is resolved with a call to the API server
+        let api_calls = container
+            .env()
+            .as_ref()
+            .map(|env| env.iter().filter(|e| e.value_from.is_some()).count())
+            .unwrap_or(0);
+        api_rate_limiter.acquire(api_calls).await;
+        let mut env =
+            <WasmCloudProvider as Provider>::env_vars(&container, &state.pod, &client).await;
+        // What the container's own env resolved to, for spotting ConfigMap changes later
+        let container_env = env.clone();
+        state.redactor = Redactor::new(&container, &env, &sensitive_env);
+        if let Some(rate) = state.pod.annotations().get(LOG_SAMPLE_RATE_ANNOTATION) {
+            env.entry(LOG_SAMPLE_RATE_KEY.to_owned())
+                .or_insert_with(|| rate.clone());
+        }
+        match dns_env(&state.pod) {
+            Ok(dns) => {
+                // Anything the user set explicitly on the container wins
+                for (key, value) in dns {
+                    env.entry(key).or_insert(value);
+                }
+            }
+            Err(e) => {
+                return Transition::next(
+                    self,
+                    Terminated::new(
+                        format!(
+                            "Pod {} container {} has an invalid dnsConfig: {:?}",
+                            state.pod.name(),
+                            container.name(),
+                            e
+                        ),
+                        true,
+                    ),
+                )
+            }
+        }
+
+        if let Some(base) = &state_base {
+            let dir = state_dir(base, &PodKey::from(&state.pod), container.name());
+            if let Err(e) = tokio::fs::create_dir_all(&dir).await {
+                return Transition::next(
+                    self,
+                    Terminated::new(
+                        format!(
+                            "Pod {} container {} failed to create its state directory {}: {:?}",
+                            state.pod.name(),
+                            container.name(),
+                            dir.display(),
+                            e
+                        ),
+                        true,
+                    ),
+                );
+            }
+            env.insert(STATE_DIR_KEY.to_owned(), dir.to_string_lossy().into_owned());
+        }
+
         let port_assigned = {
             let port_map = shared.read().await.port_map.clone();
-            match assign_container_port(Arc::clone(&port_map), &state.pod, &container).await {
+            let http_linked = state
+                .run_context
+                .read()
+                .await
+                .modules
+                .get(container.name())
+                .map(|module| links_http(module))
+                .unwrap_or(false);
+            match assign_container_port(port_map, &state.pod, &container, &env, http_linked).await {
                 Ok(port) => port,
                 Err(e) => {
                     return Transition::next(
@@ -131,16 +536,51 @@ impl State<ContainerState> for Waiting {
             port_assigned
         );
 
-        let (client, log_path, host) = {
-            let state_reader = shared.read().await;
-            (
-                state_reader.client.clone(),
-                state_reader.log_path.clone(),
-                state_reader.host.clone(),
-            )
+        let service_watch = match watched_services(&state.pod) {
+            // A SERVICES value set explicitly on the container is left alone
+            Ok(services) if !services.is_empty() && !env.contains_key(SERVICES_KEY) => {
+                match resolve_services(&client, &api_rate_limiter, &services).await {
+                    Ok(resolved) => {
+                        env.insert(SERVICES_KEY.to_owned(), resolved.clone());
+                        Some(ServiceWatch {
+                            client: client.clone(),
+                            api_rate_limiter: api_rate_limiter.clone(),
+                            services,
+                            current: resolved,
+                        })
+                    }
+                    Err(e) => {
+                        return Transition::next(
+                            self,
+                            Terminated::new(
+                                format!(
+                                    "Pod {} container {} failed to resolve services: {:?}",
+                                    state.pod.name(),
+                                    container.name(),
+                                    e
+                                ),
+                                true,
+                            ),
+                        )
+                    }
+                }
+            }
+            Ok(_) => None,
+            Err(e) => {
+                return Transition::next(
+                    self,
+                    Terminated::new(
+                        format!(
+                            "Pod {} container {} has an invalid services annotation: {:?}",
+                            state.pod.name(),
+                            container.name(),
+                            e
+                        ),
+                        true,
+                    ),
+                )
+            }
         };
-
-        let env = <WasmCloudProvider as Provider>::env_vars(&container, &state.pod, &client).await;
         let volume_bindings: Vec<VolumeBinding> =
             if let Some(volume_mounts) = container.volume_mounts().as_ref() {
                 let run_context = state.run_context.read().await;
@@ -155,11 +595,23 @@ impl State<ContainerState> for Waiting {
                                 container.name()
                             )
                         })?;
+                        let host_path = match vm.sub_path.as_deref() {
+                            Some(sub_path) if !sub_path.is_empty() => {
+                                sub_path_root(vol.deref(), sub_path)?
+                            }
+                            _ => vol.deref().clone(),
+                        };
                         // We can safely assume that this should be valid UTF-8 because it would have
                         // been validated by the k8s API
                         Ok(VolumeBinding {
                             name: vm.name.clone(),
-                            host_path: vol.deref().clone(),
+                            host_path,
+                            mount_path: PathBuf::from(&vm.mount_path),
+                            allowed_ops: state
+                                .pod
+                                .annotations()
+                                .get(&format!("{}{}", BLOBSTORE_OPS_ANNOTATION_PREFIX, vm.name))
+                                .cloned(),
                         })
                     })
                     .collect::<anyhow::Result<_>>()
@@ -184,6 +636,37 @@ impl State<ContainerState> for Waiting {
                 vec![]
             };
 
+        let config_map_watch = {
+            let run_context = state.run_context.read().await;
+            let mounted: Vec<(String, PathBuf)> = container
+                .volume_mounts()
+                .as_ref()
+                .map(|mounts| {
+                    mounts
+                        .iter()
+                        .filter_map(|vm| {
+                            let vol = run_context.volumes.get(&vm.name)?;
+                            Some((vm.name.clone(), vol.deref().clone()))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            let watch = ConfigMapWatch {
+                client: client.clone(),
+                api_rate_limiter: api_rate_limiter.clone(),
+                pod: state.pod.clone(),
+                container: container.clone(),
+                env: container_env,
+                capability_defaults: capability_defaults.clone(),
+                volumes: config_map_volumes(&state.pod, &mounted),
+            };
+            if watch.is_empty() {
+                None
+            } else {
+                Some(watch)
+            }
+        };
+
         debug!("Starting container {} on thread", container.name());
 
         let module_data = {
@@ -206,27 +689,92 @@ impl State<ContainerState> for Waiting {
             }
         };
 
-        match wasmcloud_run(
+        let run = wasmcloud_run(
             host,
+            PodKey::from(&state.pod),
+            audit,
             module_data,
             env,
+            state.redactor.clone(),
             volume_bindings,
-            &log_path,
+            &log_storage,
             port_assigned,
-        )
-        .await
-        {
-            Ok(container_handle) => {
+            service_watch,
+            config_map_watch,
+            keep_failed,
+            no_capabilities,
+            fs_disabled,
+            link_retries,
+            fs_providers,
+            log_streams,
+            capability_defaults,
+        );
+        let run = unless_deleted(run, state.deleted.clone());
+        let result = match until_deadline(run, state.start_deadline).await {
+            Some(result) => result,
+            None => {
+                let message = format!(
+                    "StartTimeout: pod {} container {} did not start before the start deadline",
+                    state.pod.name(),
+                    container.name(),
+                );
+                return Transition::next(self, Terminated::new(message, true));
+            }
+        };
+        match result {
+            Ok((mut container_handle, actor_info)) => {
                 let pod_key = PodKey::from(&state.pod);
+                let actor = actor_info.key.clone();
                 {
                     let provider_state = shared.write().await;
                     let mut handles_writer = provider_state.handles.write().await;
+                    // Checked under the handles lock, which the pod's cleanup takes after
+                    // flagging the deletion, so the handle is either registered before the
+                    // cleanup clears it or never registered at all
+                    if *state.deleted.borrow() {
+                        drop(handles_writer);
+                        drop(provider_state);
+                        if let Err(e) = container_handle.stop().await {
+                            error!(
+                                "Unable to stop actor {} of deleted pod {}: {:?}",
+                                actor,
+                                state.pod.name(),
+                                e
+                            );
+                        }
+                        return Transition::next(
+                            self,
+                            Terminated::new(
+                                format!(
+                                    "Pod {} was deleted while container {} was starting",
+                                    state.pod.name(),
+                                    container.name()
+                                ),
+                                false,
+                            ),
+                        );
+                    }
                     let pod_handle = handles_writer
-                        .entry(pod_key)
+                        .entry(pod_key.clone())
                         .or_insert_with(|| PodHandle::new(HashMap::new(), state.pod.clone(), None));
                     pod_handle
                         .insert_container_handle(state.container_key.clone(), container_handle)
                         .await;
+                    let mut actors = provider_state.actors.write().await;
+                    let pod_actors = actors.entry(pod_key.clone()).or_default();
+                    pod_actors.insert(container.name().to_owned(), actor_info);
+                    if pod_actors.len() == state.pod.containers().len() {
+                        provider_state.set_running(&pod_key).await;
+                    }
+                    provider_state.events.publish(ProviderEvent::ActorStarted {
+                        pod: pod_key.clone(),
+                        container: container.name().to_owned(),
+                        actor,
+                    });
+                }
+                if let Some(started) = state.started.take() {
+                    // The receiver is only waiting when the pod has a start order
+                    started.send(()).ok();
                 }
             }
             Err(e) => {
@@ -250,9 +798,220 @@ impl State<ContainerState> for Waiting {
 
     async fn status(
         &self,
-        _state: &mut ContainerState,
+        state: &mut ContainerState,
         _container: &Container,
     ) -> anyhow::Result<Status> {
+        state.api_rate_limiter.acquire(1).await;
         Ok(Status::waiting("Module is starting."))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use serde_json::json;
+
+    /// Sets the flag when dropped, standing in for a start's rollback.
+    struct RollbackFlag(Arc<std::sync::atomic::AtomicBool>);
+
+    impl Drop for RollbackFlag {
+        fn drop(&mut self) {
+            self.0.store(true, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    /// A start that never finishes, and the flag set once it has been rolled back.
+    fn hanging_start() -> (
+        impl std::future::Future<Output = anyhow::Result<()>>,
+        Arc<std::sync::atomic::AtomicBool>,
+    ) {
+        let rolled_back = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let flag = RollbackFlag(rolled_back.clone());
+        let start = async move {
+            let _flag = flag;
+            std::future::pending::<()>().await;
+            Ok(())
+        };
+        (start, rolled_back)
+    }
+
+    fn port_map() -> Arc<Mutex<BTreeMap<u16, PodKey>>> {
+        Arc::new(Mutex::new(BTreeMap::new()))
+    }
+
+    fn env_port(port: u16) -> EnvVars {
+        vec![(PORT_KEY.to_owned(), port.to_string())]
+            .into_iter()
+            .collect()
+    }
+
+    fn host_port_container(container_port: u16, host_port: u16) -> Container {
+        test_support::container(json!({
+            "name": "actor",
+            "image": "actor:latest",
+            "ports": [{ "containerPort": container_port, "hostPort": host_port }]
+        }))
+    }
+
+    fn host_network_pod(container_port: u16) -> Pod {
+        test_support::pod(json!({
+            "spec": {
+                "hostNetwork": true,
+                "containers": [{
+                    "name": "actor",
+                    "image": "actor:latest",
+                    "ports": [{ "containerPort": container_port }]
+                }]
+            }
+        }))
+    }
+
+    #[tokio::test]
+    async fn env_port_beats_host_port() {
+        let port_map = port_map();
+        let pod = test_support::pod(json!({ "spec": { "containers": [] } }));
+        let container = host_port_container(8080, 38472);
+        let port =
+            assign_container_port(port_map.clone(), &pod, &container, &env_port(38471), true)
+                .await
+                .unwrap();
+        assert_eq!(port, 38471);
+        let claimed: Vec<u16> = port_map.lock().await.keys().copied().collect();
+        assert_eq!(claimed, vec![38471]);
+    }
+
+    #[tokio::test]
+    async fn host_port_beats_a_random_port() {
+        let port_map = port_map();
+        let pod = test_support::pod(json!({ "spec": { "containers": [] } }));
+        let container = host_port_container(8080, 38473);
+        let port = assign_container_port(port_map, &pod, &container, &EnvVars::new(), true)
+            .await
+            .unwrap();
+        assert_eq!(port, 38473);
+    }
+
+    #[tokio::test]
+    async fn container_port_alone_gets_a_random_port() {
+        let port_map = port_map();
+        let pod = test_support::pod(json!({ "spec": { "containers": [] } }));
+        let container = test_support::container(json!({
+            "name": "actor",
+            "image": "actor:latest",
+            "ports": [{ "containerPort": 8080 }]
+        }));
+        let port = assign_container_port(port_map.clone(), &pod, &container, &EnvVars::new(), true)
+            .await
+            .unwrap();
+        assert!((30000..=32768).contains(&port));
+        assert!(port_map.lock().await.contains_key(&port));
+    }
+
+    #[tokio::test]
+    async fn env_port_is_not_claimed_without_http() {
+        let port_map = port_map();
+        let pod = test_support::pod(json!({ "spec": { "containers": [] } }));
+        let container = host_port_container(8080, 38475);
+        let port =
+            assign_container_port(port_map.clone(), &pod, &container, &env_port(38474), false)
+                .await
+                .unwrap();
+        assert_eq!(port, 38475);
+        assert!(!port_map.lock().await.contains_key(&38474));
+    }
+
+    #[tokio::test]
+    async fn env_port_held_by_another_pod_is_refused() {
+        let port_map = port_map();
+        port_map
+            .lock()
+            .await
+            .insert(38478, test_support::pod_key("default", "other"));
+        let pod = test_support::pod(json!({ "spec": { "containers": [] } }));
+        let container = host_port_container(8080, 38479);
+        let err = assign_container_port(port_map, &pod, &container, &env_port(38478), true)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("38478"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn host_network_env_port_must_match_a_container_port() {
+        let port_map = port_map();
+        let pod = host_network_pod(38476);
+        let container = pod.containers().remove(0);
+        let err = assign_container_port(port_map.clone(), &pod, &container, &env_port(38477), true)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("containerPort"), "{}", err);
+        assert!(port_map.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn host_network_env_port_matching_a_container_port_is_claimed() {
+        let port_map = port_map();
+        let pod = host_network_pod(38480);
+        let container = pod.containers().remove(0);
+        let port =
+            assign_container_port(port_map.clone(), &pod, &container, &env_port(38480), true)
+                .await
+                .unwrap();
+        assert_eq!(port, 38480);
+        assert_eq!(port_map.lock().await.get(&38480), Some(&PodKey::from(&pod)));
+    }
+
+    #[tokio::test]
+    async fn hanging_start_is_rolled_back_at_the_deadline() {
+        let (start, rolled_back) = hanging_start();
+        let deadline = Instant::now() + std::time::Duration::from_millis(100);
+        assert!(until_deadline(start, Some(deadline)).await.is_none());
+        assert!(Instant::now() >= deadline);
+        assert!(rolled_back.load(std::sync::atomic::Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn start_finishing_in_time_is_kept() {
+        let deadline = Instant::now() + std::time::Duration::from_secs(60);
+        let result = until_deadline(async { Ok(5) }, Some(deadline)).await;
+        assert_eq!(result.unwrap().unwrap(), 5);
+        let result = until_deadline(async { Ok(5) }, None).await;
+        assert_eq!(result.unwrap().unwrap(), 5);
+    }
+
+    #[tokio::test]
+    async fn deleted_pod_rolls_back_its_start() {
+        let (start, rolled_back) = hanging_start();
+        let (deleted_tx, deleted_rx) = watch::channel(false);
+        let run = tokio::spawn(unless_deleted(start, deleted_rx));
+        deleted_tx.send(true).unwrap();
+        let err = run.await.unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "the pod was deleted while it was starting");
+        assert!(rolled_back.load(std::sync::atomic::Ordering::SeqCst));
+    }
+
+    #[test]
+    fn sub_path_root_is_created_inside_the_volume() {
+        let volume = tempfile::tempdir().unwrap();
+        let root = sub_path_root(volume.path(), "data/logs").unwrap();
+        assert_eq!(root, volume.path().join("data/logs"));
+        assert!(root.is_dir());
+        // Mounting it again finds the directory already there
+        assert_eq!(sub_path_root(volume.path(), "data/logs").unwrap(), root);
+    }
+
+    #[test]
+    fn sub_path_cannot_escape_the_volume() {
+        let volume = tempfile::tempdir().unwrap();
+        for sub_path in &["/etc", "../outside", "data/../../outside"] {
+            let err = sub_path_root(volume.path(), sub_path).unwrap_err();
+            assert!(
+                err.to_string().contains("must be a relative path"),
+                "{}: {}",
+                sub_path,
+                err
+            );
+        }
+        assert!(!volume.path().join("data").exists());
+    }
+}
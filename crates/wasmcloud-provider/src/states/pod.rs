@@ -1,8 +1,14 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
+use std::time::Duration;
 
-use log::debug;
-use tokio::sync::RwLock;
+use chrono::Utc;
+use k8s_openapi::api::core::v1::{Event, EventSource, ObjectReference};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{ObjectMeta, Time};
+use kube::api::{Api, PostParams};
+use log::{debug, error, warn};
+use tokio::sync::{watch, RwLock};
+use tokio::time::Instant;
 
 use krator::{ObjectState, SharedState};
 use kubelet::backoff::BackoffStrategy;
@@ -10,35 +16,150 @@ use kubelet::backoff::ExponentialBackoffStrategy;
 use kubelet::pod::{Pod, PodKey, Status};
 use kubelet::state::common::{BackoffSequence, GenericPodState, ThresholdTrigger};
 
+use crate::rate_limit::RateLimiter;
+use crate::status::{report_status, PodStatusReport, StatusReporter};
+use crate::DedicatedHost;
 use crate::ModuleRunContext;
 use crate::ProviderState;
 
+pub(crate) mod quarantined;
 pub(crate) mod running;
 pub(crate) mod starting;
 
+/// Gives up on a pod that keeps crashing.
+#[derive(Clone, Copy, Debug)]
+pub struct Quarantine {
+    /// How many crash restarts within `window` quarantine the pod.
+    pub(crate) failures: u32,
+    pub(crate) window: Duration,
+}
+
+/// Counts a pod's crash restarts, that is its actors exiting while it was running, within the
+/// quarantine window. Other failures, such as image pulls, are retried with backoff as usual.
+#[derive(Debug)]
+struct CrashCounter {
+    quarantine: Option<Quarantine>,
+    crashes: VecDeque<Instant>,
+}
+
+impl CrashCounter {
+    fn new(quarantine: Option<Quarantine>) -> Self {
+        CrashCounter {
+            quarantine,
+            crashes: VecDeque::new(),
+        }
+    }
+
+    /// Records a crash at `now`, returning true if the pod has now crashed too often and is to
+    /// be quarantined.
+    fn record(&mut self, now: Instant) -> bool {
+        let quarantine = match self.quarantine {
+            Some(quarantine) => quarantine,
+            None => return false,
+        };
+        while let Some(crashed) = self.crashes.front() {
+            if now.duration_since(*crashed) <= quarantine.window {
+                break;
+            }
+            self.crashes.pop_front();
+        }
+        self.crashes.push_back(now);
+        self.crashes.len() >= quarantine.failures as usize
+    }
+}
+
 /// State that is shared between pod state handlers.
 pub struct PodState {
     key: PodKey,
+    uid: Option<String>,
+    /// When the pod was registered with the provider, which the start deadline counts from.
+    registered: Instant,
     run_context: SharedState<ModuleRunContext>,
     errors: usize,
     image_pull_backoff_strategy: ExponentialBackoffStrategy,
     crash_loop_backoff_strategy: ExponentialBackoffStrategy,
+    status_reporters: Arc<Vec<Arc<dyn StatusReporter>>>,
+    client: kube::Client,
+    crashes: CrashCounter,
+    /// Set once the pod is deleted, so container starts still in flight are abandoned.
+    deleted_tx: watch::Sender<bool>,
+    deleted_rx: watch::Receiver<bool>,
+    /// The pod's own wasmCloud host, if it asked for one. Kept across restarts of the pod.
+    dedicated_host: Option<DedicatedHost>,
+    /// Meters the status patches of the states the provider runs the pod through.
+    api_rate_limiter: Arc<RateLimiter>,
 }
 
 impl PodState {
-    pub fn new(pod: &Pod) -> Self {
+    pub fn new(
+        pod: &Pod,
+        status_reporters: Arc<Vec<Arc<dyn StatusReporter>>>,
+        client: kube::Client,
+        quarantine: Option<Quarantine>,
+        api_rate_limiter: Arc<RateLimiter>,
+    ) -> Self {
         let run_context = ModuleRunContext {
             modules: Default::default(),
             volumes: Default::default(),
         };
         let key = PodKey::from(pod);
+        let (deleted_tx, deleted_rx) = watch::channel(false);
         PodState {
             key,
+            uid: pod.as_kube_pod().metadata.uid.clone(),
+            registered: Instant::now(),
             run_context: Arc::new(RwLock::new(run_context)),
             errors: 0,
             image_pull_backoff_strategy: ExponentialBackoffStrategy::default(),
             crash_loop_backoff_strategy: ExponentialBackoffStrategy::default(),
+            status_reporters,
+            client,
+            crashes: CrashCounter::new(quarantine),
+            deleted_tx,
+            deleted_rx,
+            dedicated_host: None,
+            api_rate_limiter,
+        }
+    }
+
+    /// Records that the pod's actors exited while it was running. Once it has crashed too
+    /// often it is quarantined: the cluster is told why and the returned message is what the
+    /// pod is stopped with.
+    pub(crate) async fn record_crash(&mut self) -> Option<String> {
+        if !self.crashes.record(Instant::now()) {
+            return None;
         }
+        let quarantine = self
+            .crashes
+            .quarantine
+            .expect("only quarantined when quarantine is configured");
+        let message = format!(
+            "Pod crashed {} times within {:?} and will not be restarted again until it is deleted",
+            self.crashes.crashes.len(),
+            quarantine.window
+        );
+        warn!(
+            "Quarantining pod {} in namespace {}: {}",
+            self.key.name(),
+            self.key.namespace(),
+            message
+        );
+        self.report(PodStatusReport::Failed(format!("Quarantined: {}", message)))
+            .await;
+        record_warning(
+            self.client.clone(),
+            &self.key,
+            self.uid.clone(),
+            "Quarantined",
+            message.clone(),
+        )
+        .await;
+        Some(message)
+    }
+
+    /// Tells the provider's status reporters about a change in this pod's status.
+    pub(crate) async fn report(&self, status: PodStatusReport) {
+        report_status(&self.status_reporters, &self.key, status).await
     }
 }
 
@@ -83,25 +204,116 @@ impl ObjectState for PodState {
     type Status = Status;
     type SharedState = ProviderState;
     async fn async_drop(self, provider_state: &mut Self::SharedState) {
+        // Container starts run in their own tasks, which outlive this state. Tell them before
+        // the handles are cleared, so one that finishes afterwards stops its actor rather than
+        // registering it
+        self.deleted_tx.send(true).ok();
+        provider_state.release_pod(&self.key).await;
+        {
+            let mut handles = provider_state.handles.write().await;
+            handles.remove(&self.key);
+        }
         {
-            let mut lock = provider_state.port_map.lock().await;
-            let ports_to_remove: Vec<u16> = lock
-                .iter()
-                .filter_map(|(k, v)| if v == &self.key { Some(*k) } else { None })
-                .collect();
+            let mut actors = provider_state.actors.write().await;
+            actors.remove(&self.key);
+        }
+        if let Some(dedicated_host) = &self.dedicated_host {
             debug!(
-                "Pod {} in namespace {} releasing ports {:?}.",
-                &self.key.name(),
-                &self.key.namespace(),
-                &ports_to_remove
+                "Stopping dedicated wasmCloud host of pod {} in namespace {}",
+                self.key.name(),
+                self.key.namespace()
             );
-            for port in ports_to_remove {
-                lock.remove(&port);
-            }
+            dedicated_host.stop().await;
         }
-        {
-            let mut handles = provider_state.handles.write().await;
-            handles.remove(&self.key);
+        self.report(PodStatusReport::Stopped).await;
+    }
+}
+
+/// Records a warning event on the pod, so it shows in `kubectl describe`. Failing to is only
+/// logged.
+pub(crate) async fn record_warning(
+    client: kube::Client,
+    pod_key: &PodKey,
+    uid: Option<String>,
+    reason: &str,
+    message: String,
+) {
+    let event = Event {
+        metadata: ObjectMeta {
+            generate_name: Some(format!("{}.", pod_key.name())),
+            namespace: Some(pod_key.namespace()),
+            ..Default::default()
+        },
+        involved_object: ObjectReference {
+            api_version: Some("v1".to_owned()),
+            kind: Some("Pod".to_owned()),
+            name: Some(pod_key.name()),
+            namespace: Some(pod_key.namespace()),
+            uid,
+            ..Default::default()
+        },
+        reason: Some(reason.to_owned()),
+        message: Some(message),
+        type_: Some("Warning".to_owned()),
+        count: Some(1),
+        first_timestamp: Some(Time(Utc::now())),
+        last_timestamp: Some(Time(Utc::now())),
+        source: Some(EventSource {
+            component: Some("krustlet-wasmcloud".to_owned()),
+            host: None,
+        }),
+        ..Default::default()
+    };
+    let events = Api::<Event>::namespaced(client, &pod_key.namespace());
+    if let Err(e) = events.create(&PostParams::default(), &event).await {
+        error!(
+            "Unable to record {} event for pod {} in namespace {}: {:?}",
+            reason,
+            pod_key.name(),
+            pod_key.namespace(),
+            e
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counter(failures: u32) -> CrashCounter {
+        CrashCounter::new(Some(Quarantine {
+            failures,
+            window: Duration::from_secs(600),
+        }))
+    }
+
+    #[test]
+    fn always_crashing_pod_is_quarantined() {
+        let mut crashes = counter(3);
+        let start = Instant::now();
+        assert!(!crashes.record(start));
+        assert!(!crashes.record(start + Duration::from_secs(10)));
+        assert!(crashes.record(start + Duration::from_secs(20)));
+    }
+
+    #[test]
+    fn crashes_outside_the_window_are_forgotten() {
+        let mut crashes = counter(3);
+        let start = Instant::now();
+        assert!(!crashes.record(start));
+        assert!(!crashes.record(start + Duration::from_secs(300)));
+        // The first crash has left the window by now
+        assert!(!crashes.record(start + Duration::from_secs(601)));
+        assert_eq!(crashes.crashes.len(), 2);
+        assert!(crashes.record(start + Duration::from_secs(700)));
+    }
+
+    #[test]
+    fn pods_are_never_quarantined_without_a_limit() {
+        let mut crashes = CrashCounter::new(None);
+        let start = Instant::now();
+        for secs in 0..100 {
+            assert!(!crashes.record(start + Duration::from_secs(secs)));
         }
     }
 }
@@ -0,0 +1,36 @@
+use kubelet::pod::state::prelude::*;
+
+use crate::{PodState, ProviderState};
+
+/// The Pod crashed too often and is not restarted again until it is deleted. It is left in
+/// `CrashLoopBackOff`, so tooling watching for crash loops sees it, but unlike a crash loop
+/// there is no further restart to wait for.
+#[derive(Debug)]
+pub struct Quarantined {
+    message: String,
+}
+
+impl Quarantined {
+    pub fn new(message: String) -> Self {
+        Quarantined { message }
+    }
+}
+
+#[async_trait::async_trait]
+impl State<PodState> for Quarantined {
+    async fn next(
+        self: Box<Self>,
+        _provider_state: SharedState<ProviderState>,
+        _pod_state: &mut PodState,
+        _pod: Manifest<Pod>,
+    ) -> Transition<PodState> {
+        // The status below is the pod's last, since the state machine ends here
+        Transition::Complete(Err(anyhow::anyhow!(self.message)))
+    }
+
+    async fn status(&self, pod_state: &mut PodState, _pod: &Pod) -> anyhow::Result<PodStatus> {
+        // The status is patched as soon as this returns
+        pod_state.api_rate_limiter.acquire(1).await;
+        Ok(make_status(Phase::Failed, "CrashLoopBackOff"))
+    }
+}
@@ -4,11 +4,14 @@ use kubelet::pod::state::prelude::*;
 use kubelet::state::common::error::Error;
 use kubelet::state::common::GenericProviderState;
 
+use crate::status::PodStatusReport;
 use crate::{fail_fatal, PodState, ProviderState};
 
+use super::quarantined::Quarantined;
+
 /// The Kubelet is running the Pod.
 #[derive(Debug, TransitionTo)]
-#[transition_to(Error<crate::WasmCloudProvider>)]
+#[transition_to(Error<crate::WasmCloudProvider>, Quarantined)]
 pub struct Running {
     rx: Receiver<anyhow::Result<()>>,
 }
@@ -24,7 +27,7 @@ impl State<PodState> for Running {
     async fn next(
         mut self: Box<Self>,
         provider_state: SharedState<ProviderState>,
-        _pod_state: &mut PodState,
+        pod_state: &mut PodState,
         pod: Manifest<Pod>,
     ) -> Transition<PodState> {
         let pod = pod.latest();
@@ -34,12 +37,21 @@ impl State<PodState> for Running {
             match result {
                 Ok(()) => {
                     // This indicates some sort of premature exit.
-                    return Transition::next(
-                        self,
-                        Error::new(format!("Pod {} container exitted.", pod.name())),
-                    );
+                    let message = format!("Pod {} container exitted.", pod.name());
+                    pod_state
+                        .report(PodStatusReport::Failed(message.clone()))
+                        .await;
+                    if let Some(message) = pod_state.record_crash().await {
+                        let provider = provider_state.write().await;
+                        provider.stop(&pod).await.ok();
+                        return Transition::next(self, Quarantined::new(message));
+                    }
+                    return Transition::next(self, Error::new(message));
                 }
                 Err(e) => {
+                    pod_state
+                        .report(PodStatusReport::Failed(e.to_string()))
+                        .await;
                     // Stop remaining containers;
                     {
                         let provider = provider_state.write().await;
@@ -59,7 +71,112 @@ impl State<PodState> for Running {
         )
     }
 
-    async fn status(&self, _pod_state: &mut PodState, _pod: &Pod) -> anyhow::Result<PodStatus> {
+    async fn status(&self, pod_state: &mut PodState, _pod: &Pod) -> anyhow::Result<PodStatus> {
+        // The status is patched as soon as this returns
+        pod_state.api_rate_limiter.acquire(1).await;
+        pod_state.report(PodStatusReport::Running).await;
         Ok(make_status(Phase::Running, "Running"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use kubelet::pod::PodKey;
+    use serde_json::json;
+    use tokio::sync::{mpsc, RwLock};
+
+    use crate::rate_limit::RateLimiter;
+    use crate::states::pod::Quarantine;
+    use crate::status::StatusReporter;
+    use crate::test_support;
+
+    /// Keeps the pod's status reports.
+    #[derive(Default)]
+    struct Reports(std::sync::Mutex<Vec<PodStatusReport>>);
+
+    impl Reports {
+        /// How many times the pod was reported quarantined.
+        fn quarantined(&self) -> usize {
+            self.0
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|report| {
+                    matches!(report, PodStatusReport::Failed(e) if e.starts_with("Quarantined: "))
+                })
+                .count()
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl StatusReporter for Reports {
+        async fn report(&self, _pod_key: &PodKey, status: &PodStatusReport) -> anyhow::Result<()> {
+            self.0.lock().unwrap().push(status.clone());
+            Ok(())
+        }
+    }
+
+    /// Runs the pod until its actor exits on its own, as one that crashes right after starting
+    /// does.
+    async fn exit(
+        provider_state: &SharedState<ProviderState>,
+        pod_state: &mut PodState,
+        pod: &Pod,
+    ) -> Transition<PodState> {
+        let (tx, rx) = mpsc::channel(1);
+        tx.send(Ok(())).await.unwrap();
+        let (_manifest_tx, manifest) = Manifest::new(pod.clone());
+        Box::new(Running::new(rx))
+            .next(provider_state.clone(), pod_state, manifest)
+            .await
+    }
+
+    #[tokio::test]
+    async fn always_crashing_actor_is_quarantined() {
+        let dir = tempfile::tempdir().unwrap();
+        let provider_state = Arc::new(RwLock::new(test_support::provider_state(dir.path())));
+        let pod = test_support::pod(json!({
+            "metadata": { "name": "crashy" },
+            "spec": { "containers": [{ "name": "crashy", "image": "crashy:1" }] }
+        }));
+        let reports = Arc::new(Reports::default());
+        let reporters: Vec<Arc<dyn StatusReporter>> = vec![reports.clone()];
+        let mut pod_state = PodState::new(
+            &pod,
+            Arc::new(reporters),
+            test_support::unreachable_client(),
+            Some(Quarantine {
+                failures: 3,
+                window: Duration::from_secs(600),
+            }),
+            Arc::new(RateLimiter::new(5.0, 10)),
+        );
+
+        // The first exits are restarted as usual
+        for _ in 0..2 {
+            let transition = exit(&provider_state, &mut pod_state, &pod).await;
+            assert!(matches!(transition, Transition::Next(_)));
+            assert_eq!(reports.quarantined(), 0);
+        }
+
+        // The third within the window quarantines the pod
+        let transition = exit(&provider_state, &mut pod_state, &pod).await;
+        assert!(matches!(transition, Transition::Next(_)));
+        assert_eq!(reports.quarantined(), 1);
+
+        // Which ends the pod's state machine for good
+        let (_manifest_tx, manifest) = Manifest::new(pod.clone());
+        let transition = Box::new(Quarantined::new("crashed".to_owned()))
+            .next(provider_state.clone(), &mut pod_state, manifest)
+            .await;
+        match transition {
+            Transition::Complete(Err(e)) => assert_eq!(e.to_string(), "crashed"),
+            _ => panic!("a quarantined pod is never restarted"),
+        }
+    }
+}
@@ -1,18 +1,68 @@
 use std::sync::Arc;
 
 use log::info;
+use tokio::time::Instant;
 
-use kubelet::container::{state::run_to_completion, ContainerKey};
+use kubelet::container::{state::run_to_completion, Container, ContainerKey};
 use kubelet::pod::state::prelude::*;
 use kubelet::state::common::error::Error;
 use kubelet::state::common::GenericProviderState;
 
-use crate::states::container::waiting::Waiting;
+use crate::states::container::waiting::{check_host_ports, Waiting};
 use crate::states::container::ContainerState;
-use crate::{PodState, ProviderState};
+use crate::status::PodStatusReport;
+use crate::{
+    check_fs_disabled, check_resources, check_runtime_class, check_scheduler_name,
+    wants_dedicated_host, DedicatedHost, PodState, ProviderState, START_ORDER_ANNOTATION,
+};
 
 use super::running::Running;
 
+/// Returns the pod's containers in the order they should be started. Containers named in the
+/// start order annotation come first, in the listed order, followed by the rest in spec order.
+fn start_order(pod: &Pod) -> anyhow::Result<Vec<Container>> {
+    let mut containers = pod.containers();
+    let order = match pod.annotations().get(START_ORDER_ANNOTATION) {
+        Some(order) => order,
+        None => return Ok(containers),
+    };
+    let mut ordered = Vec::with_capacity(containers.len());
+    for name in order.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        let index = containers
+            .iter()
+            .position(|c| c.name() == name)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Cannot run {}: {} annotation lists container {} which is not in the pod spec (or is listed twice)",
+                    pod.name(),
+                    START_ORDER_ANNOTATION,
+                    name
+                )
+            })?;
+        ordered.push(containers.remove(index));
+    }
+    ordered.extend(containers);
+    Ok(ordered)
+}
+
+/// The outcome of a container's run.
+///
+/// A panicking container would otherwise never report back, leaving the pod running without
+/// it. It is surfaced as an error so the pod fails and is cleaned up.
+fn run_outcome(
+    container: &str,
+    joined: Result<anyhow::Result<()>, tokio::task::JoinError>,
+) -> anyhow::Result<()> {
+    match joined {
+        Ok(result) => result,
+        Err(e) => Err(anyhow::anyhow!(
+            "Container {} panicked while running: {}",
+            container,
+            e
+        )),
+    }
+}
+
 /// The Kubelet is starting the Pod.
 #[derive(Default, Debug, TransitionTo)]
 #[transition_to(Running, Error<crate::WasmCloudProvider>)]
@@ -31,36 +81,146 @@ impl State<PodState> for Starting {
 
         info!("Starting containers for pod {:?}", pod.name());
 
-        let containers = pod.containers();
+        // The scheduler, runtime class and resource allowlists are provider configuration, and
+        // the ports and image instances in use are provider state, neither of which
+        // `validate_pod_runnable` can see, so they are checked here
+        let (
+            scheduler_names,
+            runtime_class_names,
+            allowed_resources,
+            fs_disabled,
+            port_map,
+            image_instances,
+            start_deadline,
+        ) = {
+            let provider_state = provider_state.read().await;
+            (
+                provider_state.scheduler_names.clone(),
+                provider_state.runtime_class_names.clone(),
+                provider_state.allowed_resources.clone(),
+                provider_state.fs_disabled,
+                provider_state.port_map.clone(),
+                provider_state.image_instances.clone(),
+                provider_state.start_deadline,
+            )
+        };
+        let checked = match check_scheduler_name(&pod, &scheduler_names)
+            .and_then(|_| check_runtime_class(&pod, &runtime_class_names))
+            .and_then(|_| check_resources(&pod, &allowed_resources))
+            .and_then(|_| check_fs_disabled(&pod, fs_disabled))
+        {
+            Ok(()) => check_host_ports(&port_map, &pod).await,
+            Err(e) => Err(e),
+        };
+        // Claimed last, so a pod refused for anything else never holds instances
+        let checked = match checked {
+            Ok(()) => image_instances.claim(&pod).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = checked {
+            pod_state
+                .report(PodStatusReport::Failed(e.to_string()))
+                .await;
+            return Transition::next(self, Error::new(e.to_string()));
+        }
+
+        // The deadline counts from registration so that pulling the images counts against it
+        let start_deadline = start_deadline.map(|timeout| pod_state.registered + timeout);
+        if let Some(deadline) = start_deadline {
+            if Instant::now() >= deadline {
+                let message = format!(
+                    "StartTimeout: pod {} did not start within {:?}, it ran out of time pulling images and mounting volumes",
+                    pod.name(),
+                    deadline - pod_state.registered
+                );
+                pod_state
+                    .report(PodStatusReport::Failed(message.clone()))
+                    .await;
+                return Transition::next(self, Error::new(message));
+            }
+        }
+
+        if wants_dedicated_host(&pod) && pod_state.dedicated_host.is_none() {
+            match DedicatedHost::start().await {
+                Ok(host) => {
+                    info!("Started dedicated wasmCloud host for pod {:?}", pod.name());
+                    pod_state.dedicated_host = Some(host);
+                }
+                Err(e) => {
+                    pod_state
+                        .report(PodStatusReport::Failed(e.to_string()))
+                        .await;
+                    return Transition::next(self, Error::new(e.to_string()));
+                }
+            }
+        }
+
+        let containers = match start_order(&pod) {
+            Ok(containers) => containers,
+            Err(e) => {
+                pod_state
+                    .report(PodStatusReport::Failed(e.to_string()))
+                    .await;
+                return Transition::next(self, Error::new(e.to_string()));
+            }
+        };
+        let sequential = pod.annotations().contains_key(START_ORDER_ANNOTATION);
         let (tx, rx) = tokio::sync::mpsc::channel(containers.len());
         for container in containers {
             let initial_state = Waiting;
             let container_key = ContainerKey::App(container.name().to_string());
+            let (started_tx, started_rx) = tokio::sync::oneshot::channel();
             let container_state = ContainerState::new(
                 pod.clone(),
                 container_key.clone(),
                 Arc::clone(&pod_state.run_context),
+                started_tx,
+                start_deadline,
+                pod_state.deleted_rx.clone(),
+                pod_state.dedicated_host.clone(),
+                pod_state.api_rate_limiter.clone(),
             );
             let task_provider = Arc::clone(&provider_state);
             let task_pod = pod_rx.clone();
             let task_tx = tx.clone();
+            let task_container = container.name().to_owned();
             tokio::task::spawn(async move {
                 let client = {
                     let provider_state = task_provider.read().await;
                     provider_state.client()
                 };
 
-                let result = run_to_completion(
-                    &client,
-                    initial_state,
-                    task_provider,
-                    container_state,
-                    task_pod,
-                    container_key,
-                )
-                .await;
-                task_tx.send(result).await
+                let run = tokio::task::spawn(async move {
+                    run_to_completion(
+                        &client,
+                        initial_state,
+                        task_provider,
+                        container_state,
+                        task_pod,
+                        container_key,
+                    )
+                    .await
+                });
+                task_tx.send(run_outcome(&task_container, run.await)).await
             });
+
+            if sequential {
+                info!(
+                    "Waiting for container {} of pod {:?} to start",
+                    container.name(),
+                    pod.name()
+                );
+                if started_rx.await.is_err() {
+                    // The container failed before starting. Its result is reported through the
+                    // channel, so leave the rest unstarted and let `Running` handle it.
+                    info!(
+                        "Container {} of pod {:?} failed to start, not starting remaining containers",
+                        container.name(),
+                        pod.name()
+                    );
+                    break;
+                }
+            }
         }
 
         info!("All containers started for pod {:?}.", pod.name());
@@ -68,7 +228,85 @@ impl State<PodState> for Starting {
         Transition::next(self, Running::new(rx))
     }
 
-    async fn status(&self, _pod_state: &mut PodState, _pod: &Pod) -> anyhow::Result<PodStatus> {
+    async fn status(&self, pod_state: &mut PodState, _pod: &Pod) -> anyhow::Result<PodStatus> {
+        // The status is patched as soon as this returns
+        pod_state.api_rate_limiter.acquire(1).await;
+        pod_state.report(PodStatusReport::Starting).await;
         Ok(make_status(Phase::Pending, "Starting"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+    use tokio::task::JoinHandle;
+
+    use crate::test_support;
+
+    fn names(containers: Vec<Container>) -> Vec<String> {
+        containers.iter().map(|c| c.name().to_owned()).collect()
+    }
+
+    fn pod(order: Option<&str>) -> Pod {
+        let annotations = order
+            .map(|order| json!({ START_ORDER_ANNOTATION: order }))
+            .unwrap_or_else(|| json!({}));
+        test_support::pod(json!({
+            "metadata": { "annotations": annotations },
+            "spec": {
+                "containers": [
+                    { "name": "a", "image": "a:1" },
+                    { "name": "b", "image": "b:1" },
+                    { "name": "c", "image": "c:1" },
+                ]
+            }
+        }))
+    }
+
+    #[tokio::test]
+    async fn panicking_container_is_an_error() {
+        let run: JoinHandle<anyhow::Result<()>> =
+            tokio::spawn(async { panic!("actor host went away") });
+        let err = run_outcome("greet", run.await).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("Container greet panicked while running"),
+            "{}",
+            err
+        );
+
+        let run: JoinHandle<anyhow::Result<()>> =
+            tokio::spawn(async { Err(anyhow::anyhow!("link failed")) });
+        let err = run_outcome("greet", run.await).unwrap_err();
+        assert_eq!(err.to_string(), "link failed");
+        let run: JoinHandle<anyhow::Result<()>> = tokio::spawn(async { Ok(()) });
+        run_outcome("greet", run.await).unwrap();
+    }
+
+    #[test]
+    fn spec_order_without_annotation() {
+        assert_eq!(names(start_order(&pod(None)).unwrap()), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn listed_containers_first_then_spec_order() {
+        assert_eq!(
+            names(start_order(&pod(Some("c, a"))).unwrap()),
+            ["c", "a", "b"]
+        );
+        assert_eq!(
+            names(start_order(&pod(Some("b,,"))).unwrap()),
+            ["b", "a", "c"]
+        );
+    }
+
+    #[test]
+    fn unknown_or_repeated_container_rejected() {
+        let err = start_order(&pod(Some("a,missing"))).unwrap_err();
+        assert!(err.to_string().contains("missing"));
+        let err = start_order(&pod(Some("a,a"))).unwrap_err();
+        assert!(err.to_string().contains("listed twice"));
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use kubelet::pod::PodKey;
+use log::error;
+use serde_derive::Serialize;
+
+/// How many pod failures [`RecentErrors`] keeps.
+const RECENT_ERRORS: usize = 20;
+
+/// A change in a pod's status, as pushed to [`StatusReporter`]s.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PodStatusReport {
+    /// The pod's actors are being started.
+    Starting,
+    /// All of the pod's actors have been started.
+    Running,
+    /// The pod failed, with the reason.
+    Failed(String),
+    /// The pod was removed from the node and its actors stopped.
+    Stopped,
+}
+
+/// Receives pod status changes, for example to push them to a dashboard or webhook.
+///
+/// Reporters are in addition to the pod status the kubelet keeps updated in Kubernetes, which
+/// always happens. They are called from the pod's state machine, so a reporter that talks to a
+/// slow system should hand the work off rather than hold the pod up.
+#[async_trait::async_trait]
+pub trait StatusReporter: Send + Sync {
+    /// Called every time the pod's status changes. An error is logged and otherwise ignored.
+    async fn report(&self, pod_key: &PodKey, status: &PodStatusReport) -> anyhow::Result<()>;
+}
+
+/// A pod failure kept by [`RecentErrors`].
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct RecentError {
+    timestamp: DateTime<Utc>,
+    pod: String,
+    error: String,
+}
+
+/// Keeps the most recent pod failures for the provider's status document.
+#[derive(Default)]
+pub(crate) struct RecentErrors {
+    errors: Mutex<VecDeque<RecentError>>,
+}
+
+impl RecentErrors {
+    /// The kept failures, oldest first.
+    pub(crate) fn list(&self) -> Vec<RecentError> {
+        let errors = self.errors.lock().unwrap_or_else(|e| e.into_inner());
+        errors.iter().cloned().collect()
+    }
+}
+
+#[async_trait::async_trait]
+impl StatusReporter for RecentErrors {
+    async fn report(&self, pod_key: &PodKey, status: &PodStatusReport) -> anyhow::Result<()> {
+        if let PodStatusReport::Failed(error) = status {
+            let mut errors = self.errors.lock().unwrap_or_else(|e| e.into_inner());
+            if errors.len() == RECENT_ERRORS {
+                errors.pop_front();
+            }
+            errors.push_back(RecentError {
+                timestamp: Utc::now(),
+                pod: format!("{}/{}", pod_key.namespace(), pod_key.name()),
+                error: error.clone(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Passes a status change to every reporter in turn.
+pub(crate) async fn report_status(
+    reporters: &[Arc<dyn StatusReporter>],
+    pod_key: &PodKey,
+    status: PodStatusReport,
+) {
+    for reporter in reporters {
+        if let Err(e) = reporter.report(pod_key, &status).await {
+            error!(
+                "Unable to report status {:?} of pod {} in namespace {}: {:?}",
+                status,
+                pod_key.name(),
+                pod_key.namespace(),
+                e
+            );
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use std::convert::TryFrom;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use k8s_openapi::api::core::v1::Pod as KubePod;
+use kubelet::container::Container;
+use kubelet::plugin_watcher::PluginRegistry;
+use kubelet::pod::{Pod, PodKey};
+use kubelet::store::{PullPolicy, Store};
+use oci_distribution::secrets::RegistryAuth;
+use oci_distribution::Reference;
+use serde_json::json;
+use wasmcloud_host::HostBuilder;
+
+use crate::audit::AuditLog;
+use crate::events::EventBus;
+use crate::image_limits::ImageInstances;
+use crate::logs::{LogStorage, LogStreams};
+use crate::rate_limit::RateLimiter;
+use crate::{ProviderState, WasmCloudConfig};
+
+/// Builds a pod from its JSON manifest, named `test-pod` in `default` unless the manifest says
+/// otherwise.
+pub(crate) fn pod(mut manifest: serde_json::Value) -> Pod {
+    let metadata = manifest
+        .as_object_mut()
+        .expect("pod manifest is not an object")
+        .entry("metadata")
+        .or_insert_with(|| json!({}));
+    metadata
+        .as_object_mut()
+        .expect("pod metadata is not an object")
+        .entry("name")
+        .or_insert_with(|| json!("test-pod"));
+    metadata
+        .as_object_mut()
+        .unwrap()
+        .entry("namespace")
+        .or_insert_with(|| json!("default"));
+    let pod: KubePod = serde_json::from_value(manifest).expect("invalid pod manifest");
+    Pod::from(pod)
+}
+
+/// The key of a pod with the given name and namespace.
+pub(crate) fn pod_key(namespace: &str, name: &str) -> PodKey {
+    PodKey::from(&pod(json!({
+        "metadata": { "name": name, "namespace": namespace }
+    })))
+}
+
+/// Builds a container from its JSON manifest, as the only container of a pod.
+pub(crate) fn container(manifest: serde_json::Value) -> Container {
+    pod(json!({ "spec": { "containers": [manifest] } }))
+        .containers()
+        .remove(0)
+}
+
+/// A [`Store`] that records the references it is asked for and returns an empty module.
+#[derive(Default)]
+pub(crate) struct RecordingStore {
+    pub(crate) fetched: Mutex<Vec<String>>,
+}
+
+#[async_trait::async_trait]
+impl Store for RecordingStore {
+    async fn get(
+        &self,
+        image_ref: &Reference,
+        _pull_policy: PullPolicy,
+        _auth: &RegistryAuth,
+    ) -> anyhow::Result<Vec<u8>> {
+        self.fetched.lock().unwrap().push(image_ref.whole());
+        Ok(vec![])
+    }
+}
+
+/// Fetches the image from the store without credentials.
+pub(crate) async fn fetch(store: &dyn Store, image: &str) -> anyhow::Result<Vec<u8>> {
+    let image_ref = Reference::try_from(image.to_owned()).expect("invalid image reference");
+    store
+        .get(
+            &image_ref,
+            PullPolicy::IfNotPresent,
+            &RegistryAuth::Anonymous,
+        )
+        .await
+}
+
+/// A client for an API server that isn't there, for code that only records events on the side
+/// and carries on when it can't.
+pub(crate) fn unreachable_client() -> kube::Client {
+    kube::Client::new(kube::Config::new(
+        "http://127.0.0.1:9".parse().expect("invalid URL"),
+    ))
+}
+
+/// The provider's state with the default configuration and no pods. Its host isn't started, so
+/// it can't run actors.
+pub(crate) fn provider_state(data_dir: &Path) -> ProviderState {
+    let config = WasmCloudConfig::default();
+    ProviderState {
+        client: unreachable_client(),
+        handles: Default::default(),
+        store: Arc::new(RecordingStore::default()),
+        volume_path: data_dir.join("volumes"),
+        log_storage: LogStorage::Disk(data_dir.join("logs")),
+        host: Arc::new(tokio::sync::Mutex::new(HostBuilder::new().build())),
+        port_map: Default::default(),
+        actors: Default::default(),
+        plugin_registry: Arc::new(PluginRegistry::new(&data_dir.join("plugins"))),
+        api_rate_limiter: Arc::new(RateLimiter::new(config.kube_api_qps, config.kube_api_burst)),
+        audit: Arc::new(AuditLog::new(None).expect("unable to create audit log")),
+        running: Default::default(),
+        keep_failed: config.keep_failed,
+        no_capabilities: config.no_capabilities,
+        scheduler_names: Arc::new(config.scheduler_names),
+        fs_providers: Default::default(),
+        log_streams: Arc::new(LogStreams::new(None, None)),
+        start_deadline: config.start_deadline,
+        status_reporters: Arc::new(vec![]),
+        recent_errors: Default::default(),
+        capability_defaults: Arc::new(config.capability_defaults),
+        quarantine: None,
+        events: Arc::new(EventBus::new()),
+        state_dir: None,
+        allowed_resources: Arc::new(config.allowed_resources),
+        fs_disabled: config.disable_fs,
+        sensitive_env: Arc::new(config.sensitive_env),
+        link_retries: config.link_retries,
+        runtime_class_names: Arc::new(config.runtime_class_names),
+        image_instances: Arc::new(ImageInstances::new(config.image_instance_limits)),
+        tolerate_direct_assignment: config.tolerate_direct_assignment,
+    }
+}
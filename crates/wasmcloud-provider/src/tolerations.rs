@@ -0,0 +1,102 @@
+use k8s_openapi::api::core::v1::{Pod as KubePod, Toleration};
+use kube::api::{Api, Patch, PatchParams};
+use kubelet::pod::{Pod, PodKey};
+use log::warn;
+use serde_json::json;
+
+use crate::states::pod::record_warning;
+use crate::TARGET_WASM32_WASMCLOUD;
+
+/// The key of the taints the node is registered with.
+const ARCH_TAINT_KEY: &str = "kubernetes.io/arch";
+
+/// The effects of the node's architecture taints.
+const ARCH_TAINT_EFFECTS: &[&str] = &["NoSchedule", "NoExecute"];
+
+/// Gives a pod that was assigned straight to this node with `spec.nodeName`, and so never went
+/// past the scheduler's taint check, the architecture tolerations it is missing.
+///
+/// The scheduler never places a pod that doesn't tolerate the node's taints here, so such a
+/// pod was assigned directly. It is run anyway, but without the tolerations the cluster's
+/// taint manager would evict it for not tolerating the `NoExecute` taint, so they are added to
+/// the pod and a warning is logged and recorded as an event on it. Pods that tolerate both
+/// taints are left alone.
+pub(crate) async fn tolerate_direct_assignment(client: kube::Client, pod: &Pod) {
+    let existing = pod
+        .as_kube_pod()
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.tolerations.clone())
+        .unwrap_or_default();
+    let missing: Vec<Toleration> = ARCH_TAINT_EFFECTS
+        .iter()
+        .filter(|effect| !existing.iter().any(|t| tolerates_arch_taint(t, effect)))
+        .map(|effect| Toleration {
+            effect: Some((*effect).to_owned()),
+            key: Some(ARCH_TAINT_KEY.to_owned()),
+            operator: Some("Equal".to_owned()),
+            value: Some(TARGET_WASM32_WASMCLOUD.to_owned()),
+            toleration_seconds: None,
+        })
+        .collect();
+    if missing.is_empty() {
+        return;
+    }
+
+    let pod_key = PodKey::from(pod);
+    // Tolerations have no merge key, so the whole list is sent. Adding to it is the one change
+    // Kubernetes allows to a running pod's tolerations
+    let tolerations: Vec<Toleration> = existing.into_iter().chain(missing).collect();
+    let pods: Api<KubePod> = Api::namespaced(client.clone(), &pod_key.namespace());
+    let patch = json!({ "spec": { "tolerations": tolerations } });
+    let outcome = match pods
+        .patch(
+            &pod_key.name(),
+            &PatchParams::default(),
+            &Patch::Merge(&patch),
+        )
+        .await
+    {
+        Ok(_) => "the tolerations have been added".to_owned(),
+        Err(e) => format!(
+            "adding the tolerations failed, so the taint manager will evict it: {}",
+            e
+        ),
+    };
+    let message = format!(
+        "Pod was assigned directly to this node without tolerating its {}={} taints, which pods placed by the scheduler must. It is run anyway, and {}",
+        ARCH_TAINT_KEY, TARGET_WASM32_WASMCLOUD, outcome
+    );
+    warn!(
+        "Pod {} in namespace {}: {}",
+        pod_key.name(),
+        pod_key.namespace(),
+        message
+    );
+    record_warning(
+        client,
+        &pod_key,
+        pod.as_kube_pod().metadata.uid.clone(),
+        "MissingTolerations",
+        message,
+    )
+    .await;
+}
+
+/// Whether the toleration tolerates the node's architecture taint with the given effect.
+fn tolerates_arch_taint(toleration: &Toleration, effect: &str) -> bool {
+    let effect_matches = match toleration.effect.as_deref() {
+        None | Some("") => true,
+        Some(e) => e == effect,
+    };
+    let key_matches = match toleration.key.as_deref() {
+        // An empty key with `Exists` tolerates every taint
+        None | Some("") => toleration.operator.as_deref() == Some("Exists"),
+        Some(key) => key == ARCH_TAINT_KEY,
+    };
+    let value_matches = match toleration.operator.as_deref() {
+        Some("Exists") => true,
+        _ => toleration.value.as_deref().unwrap_or_default() == TARGET_WASM32_WASMCLOUD,
+    };
+    effect_matches && key_matches && value_matches
+}
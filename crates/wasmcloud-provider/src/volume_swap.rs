@@ -0,0 +1,129 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[cfg(unix)]
+use chrono::Utc;
+
+/// The link to the current version of a swapped volume's contents. Every file in the volume is
+/// a link through it, so renaming a new link over it swaps them all at once.
+#[cfg(unix)]
+const DATA_LINK: &str = "..data";
+
+/// Where the new data link is made before it is renamed over the old one.
+#[cfg(unix)]
+const DATA_LINK_TMP: &str = "..data_tmp";
+
+/// Names starting with this are the swap's own bookkeeping rather than the volume's files.
+const HIDDEN_PREFIX: &str = "..";
+
+/// Replaces the files in the volume directory `dir` with `files`, keyed by file name, so that
+/// readers see either all of the old files or all of the new ones, never a mix.
+///
+/// This uses the layout the Kubernetes kubelet uses for ConfigMap volumes. Each version is
+/// written in full to a hidden directory, `..data` links to the current one and every file is a
+/// link through `..data`. The swap itself is a single rename of `..data`, and the volume stays
+/// at the same path, so the FS capability serving it needs no change. Plain files left by the
+/// first mount are replaced by links the first time, see [`adopt`].
+#[cfg(unix)]
+pub(crate) async fn swap_contents(
+    dir: &Path,
+    files: &BTreeMap<String, Vec<u8>>,
+) -> anyhow::Result<()> {
+    let version = format!(
+        "{}{}",
+        HIDDEN_PREFIX,
+        Utc::now().format("%Y_%m_%d_%H_%M_%S%.f")
+    );
+    let version_dir = dir.join(&version);
+    tokio::fs::create_dir(&version_dir).await?;
+    for (name, contents) in files {
+        tokio::fs::write(version_dir.join(name), contents).await?;
+    }
+    let old_version = tokio::fs::read_link(dir.join(DATA_LINK)).await.ok();
+
+    let data_link_tmp = dir.join(DATA_LINK_TMP);
+    // Left behind by a swap that failed part way
+    tokio::fs::remove_file(&data_link_tmp).await.ok();
+    tokio::fs::symlink(&version, &data_link_tmp).await?;
+    tokio::fs::rename(&data_link_tmp, dir.join(DATA_LINK)).await?;
+
+    // A file that is already a link follows the swap by itself
+    for name in files.keys() {
+        let path = dir.join(name);
+        if let Ok(metadata) = tokio::fs::symlink_metadata(&path).await {
+            if metadata.file_type().is_symlink() {
+                continue;
+            }
+        }
+        let link_tmp = dir.join(format!("{}{}.tmp", HIDDEN_PREFIX, name));
+        tokio::fs::remove_file(&link_tmp).await.ok();
+        tokio::fs::symlink(Path::new(DATA_LINK).join(name), &link_tmp).await?;
+        tokio::fs::rename(&link_tmp, &path).await?;
+    }
+
+    remove_missing(dir, files).await?;
+    if let Some(old_version) = old_version {
+        tokio::fs::remove_dir_all(dir.join(old_version)).await?;
+    }
+    Ok(())
+}
+
+/// Replaces the files in the volume directory `dir` with `files`, keyed by file name.
+///
+/// Without links to swap, each file is written and renamed into place on its own. Readers never
+/// see a half written file, but may see some files from before the swap and some from after.
+#[cfg(not(unix))]
+pub(crate) async fn swap_contents(
+    dir: &Path,
+    files: &BTreeMap<String, Vec<u8>>,
+) -> anyhow::Result<()> {
+    remove_missing(dir, files).await?;
+    for (name, contents) in files {
+        let tmp = dir.join(format!("{}{}.tmp", HIDDEN_PREFIX, name));
+        tokio::fs::write(&tmp, contents).await?;
+        tokio::fs::rename(&tmp, dir.join(name)).await?;
+    }
+    Ok(())
+}
+
+/// Moves a volume's plain files into the swapped layout, keeping their contents, so later
+/// swaps replace every file at once. Does nothing to a volume that already uses it.
+#[cfg(unix)]
+pub(crate) async fn adopt(dir: &Path) -> anyhow::Result<()> {
+    if tokio::fs::symlink_metadata(dir.join(DATA_LINK))
+        .await
+        .is_ok()
+    {
+        return Ok(());
+    }
+    let mut files = BTreeMap::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !name.starts_with(HIDDEN_PREFIX) && entry.file_type().await?.is_file() {
+            files.insert(name, tokio::fs::read(entry.path()).await?);
+        }
+    }
+    swap_contents(dir, &files).await
+}
+
+/// Plain files are swapped in place, so there is nothing to move.
+#[cfg(not(unix))]
+pub(crate) async fn adopt(_dir: &Path) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// Removes the volume's files that aren't in `files`.
+async fn remove_missing(dir: &Path, files: &BTreeMap<String, Vec<u8>>) -> anyhow::Result<()> {
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with(HIDDEN_PREFIX) || files.contains_key(&name) {
+            continue;
+        }
+        if !entry.file_type().await?.is_dir() {
+            tokio::fs::remove_file(entry.path()).await?;
+        }
+    }
+    Ok(())
+}
@@ -1,6 +1,9 @@
 use futures::{StreamExt, TryStreamExt};
-use k8s_openapi::api::core::v1::{Node, Pod, Taint};
-use kube::api::{Api, DeleteParams, ListParams, LogParams, PostParams};
+use k8s_openapi::api::core::v1::{
+    ConfigMap, ContainerState, Event as KubeEvent, Namespace, Node, Pod, Secret, Taint,
+};
+use k8s_openapi::api::node::v1beta1::RuntimeClass;
+use kube::api::{Api, DeleteParams, ListParams, LogParams, Patch, PatchParams, PostParams};
 use kube_runtime::watcher::{watcher, Event};
 use serde_json::json;
 
@@ -16,7 +19,9 @@ async fn test_wasmcloud_provider() -> Result<(), Box<dyn std::error::Error>> {
 
     let client: kube::Client = nodes.into();
 
-    let _cleaner = WasmCloudTestResourceCleaner {};
+    let _cleaner = WasmCloudTestResourceCleaner {
+        pods: vec!["greet-wasmcloud"],
+    };
 
     let pods: Api<Pod> = Api::namespaced(client.clone(), "default");
 
@@ -151,14 +156,1640 @@ async fn create_wasmcloud_pod(client: kube::Client, pods: &Api<Pod>) -> anyhow::
     Ok(())
 }
 
-struct WasmCloudTestResourceCleaner {}
+#[tokio::test]
+async fn test_host_network_pods() -> Result<(), Box<dyn std::error::Error>> {
+    let client = kube::Client::try_default().await?;
+    let pods: Api<Pod> = Api::namespaced(client.clone(), "default");
+
+    let _cleaner = WasmCloudTestResourceCleaner {
+        pods: vec!["greet-hostnet-one", "greet-hostnet-two"],
+    };
+
+    for (name, port) in &[("greet-hostnet-one", 30101), ("greet-hostnet-two", 30102)] {
+        let p = serde_json::from_value(json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {
+                "name": name,
+                "annotations": {
+                    "wasmcloud.dev/host-network": "true"
+                }
+            },
+            "spec": {
+                "containers": [
+                    {
+                        "name": name,
+                        "image": "webassembly.azurecr.io/greet-wasmcloud:v0.6.0",
+                        "ports": [
+                            {
+                                "containerPort": port
+                            }
+                        ],
+                    },
+                ],
+                "tolerations": wasmcloud_tolerations()
+            }
+        }))?;
+        pods.create(&PostParams::default(), &p).await?;
+        wait_for_pod_ready(client.clone(), name, "default").await?;
+    }
+
+    // Both actors should be reachable on their container ports with no remapping
+    for port in &[30101, 30102] {
+        reqwest::get(&format!("http://127.0.0.1:{}", port)).await?;
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_start_order_annotation() -> Result<(), Box<dyn std::error::Error>> {
+    let client = kube::Client::try_default().await?;
+    let pods: Api<Pod> = Api::namespaced(client.clone(), "default");
+
+    let _cleaner = WasmCloudTestResourceCleaner {
+        pods: vec!["greet-start-order", "greet-start-order-reversed"],
+    };
+
+    // The container named "blocked" can never start, as something that isn't krustlet holds its
+    // port. Started in order, each container is only started once the one before it has, so
+    // whether "other" is ever run shows which of the two was started first
+    let _listener = std::net::TcpListener::bind("0.0.0.0:30231")?;
+    let start_order_pod = |name: &str, order: &str, other_port: u16| {
+        json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {
+                "name": name,
+                "annotations": {
+                    "wasmcloud.dev/start-order": order
+                }
+            },
+            "spec": {
+                "containers": [
+                    {
+                        "name": "other",
+                        "image": "webassembly.azurecr.io/greet-wasmcloud:v0.6.0",
+                        "ports": [{ "containerPort": 8080, "hostPort": other_port }],
+                    },
+                    {
+                        "name": "blocked",
+                        "image": "webassembly.azurecr.io/greet-wasmcloud:v0.6.0",
+                        "ports": [{ "containerPort": 8080, "hostPort": 30231 }],
+                    },
+                ],
+                "tolerations": wasmcloud_tolerations()
+            }
+        })
+    };
+
+    // Listed first, the blocked container holds back the other one
+    let p = serde_json::from_value(start_order_pod("greet-start-order", "blocked,other", 30232))?;
+    pods.create(&PostParams::default(), &p).await?;
+    let pod = wait_for_pod(client.clone(), "greet-start-order", "default", |pod| {
+        container_state(pod, "blocked").map_or(false, |state| state.terminated.is_some())
+    })
+    .await?;
+    assert!(
+        !container_was_run(&pod, "other"),
+        "expected container other not to be started after blocked failed to start"
+    );
+
+    // Listed second, it fails only after the other one has started
+    let p = serde_json::from_value(start_order_pod(
+        "greet-start-order-reversed",
+        "other,blocked",
+        30233,
+    ))?;
+    pods.create(&PostParams::default(), &p).await?;
+    wait_for_pod(
+        client.clone(),
+        "greet-start-order-reversed",
+        "default",
+        |pod| {
+            container_state(pod, "blocked").map_or(false, |state| state.terminated.is_some())
+                && container_was_run(pod, "other")
+        },
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// The current state of the named container of the pod, if it has a status yet.
+fn container_state(pod: &Pod, container: &str) -> Option<ContainerState> {
+    pod.status
+        .as_ref()?
+        .container_statuses
+        .as_ref()?
+        .iter()
+        .find(|status| status.name == container)?
+        .state
+        .clone()
+}
+
+/// Whether the provider has run the named container: it reports a container as running once
+/// it has started, and as terminated once it stops.
+fn container_was_run(pod: &Pod, container: &str) -> bool {
+    container_state(pod, container).map_or(false, |state| {
+        state.running.is_some() || state.terminated.is_some()
+    })
+}
+
+#[tokio::test]
+async fn test_host_port_taken_by_another_process() -> Result<(), Box<dyn std::error::Error>> {
+    let client = kube::Client::try_default().await?;
+    let pods: Api<Pod> = Api::namespaced(client.clone(), "default");
+
+    let _cleaner = WasmCloudTestResourceCleaner {
+        pods: vec!["greet-port-taken", "greet-port-auto"],
+    };
+
+    // Something that isn't krustlet holds the port the first pod asks for
+    let _listener = std::net::TcpListener::bind("0.0.0.0:30201")?;
+
+    let p = serde_json::from_value(json!({
+        "apiVersion": "v1",
+        "kind": "Pod",
+        "metadata": {
+            "name": "greet-port-taken"
+        },
+        "spec": {
+            "containers": [
+                {
+                    "name": "greet-port-taken",
+                    "image": "webassembly.azurecr.io/greet-wasmcloud:v0.6.0",
+                    "ports": [{ "containerPort": 8080, "hostPort": 30201 }],
+                },
+            ],
+            "tolerations": wasmcloud_tolerations()
+        }
+    }))?;
+    pods.create(&PostParams::default(), &p).await?;
+    let message =
+        wait_for_container_terminated(client.clone(), "greet-port-taken", "default").await?;
+    assert!(
+        message.contains("PortBindFailed"),
+        "expected a PortBindFailed termination, got: {}",
+        message
+    );
+
+    // A pod without a hostPort still starts, on whichever port is free
+    let p = serde_json::from_value(json!({
+        "apiVersion": "v1",
+        "kind": "Pod",
+        "metadata": {
+            "name": "greet-port-auto"
+        },
+        "spec": {
+            "containers": [
+                {
+                    "name": "greet-port-auto",
+                    "image": "webassembly.azurecr.io/greet-wasmcloud:v0.6.0",
+                    "ports": [{ "containerPort": 8080 }],
+                },
+            ],
+            "tolerations": wasmcloud_tolerations()
+        }
+    }))?;
+    pods.create(&PostParams::default(), &p).await?;
+    wait_for_pod_ready(client.clone(), "greet-port-auto", "default").await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_config_map_reload() -> Result<(), Box<dyn std::error::Error>> {
+    let client = kube::Client::try_default().await?;
+    let pods: Api<Pod> = Api::namespaced(client.clone(), "default");
+    let config_maps: Api<ConfigMap> = Api::namespaced(client.clone(), "default");
+
+    let _cleaner = WasmCloudTestResourceCleaner {
+        pods: vec!["greet-config-reload"],
+    };
+
+    let cm = serde_json::from_value(json!({
+        "apiVersion": "v1",
+        "kind": "ConfigMap",
+        "metadata": { "name": "greet-config-reload" },
+        "data": { "GREETING": "hello" }
+    }))?;
+    config_maps.create(&PostParams::default(), &cm).await?;
+
+    let p = serde_json::from_value(json!({
+        "apiVersion": "v1",
+        "kind": "Pod",
+        "metadata": {
+            "name": "greet-config-reload"
+        },
+        "spec": {
+            "containers": [
+                {
+                    "name": "greet-config-reload",
+                    "image": "webassembly.azurecr.io/greet-wasmcloud:v0.6.0",
+                    "ports": [{ "containerPort": 8080 }],
+                    "env": [
+                        {
+                            "name": "GREETING",
+                            "valueFrom": {
+                                "configMapKeyRef": { "name": "greet-config-reload", "key": "GREETING" }
+                            }
+                        }
+                    ],
+                    "volumeMounts": [{ "name": "config", "mountPath": "/config" }]
+                },
+            ],
+            "volumes": [
+                { "name": "config", "configMap": { "name": "greet-config-reload" } }
+            ],
+            "tolerations": wasmcloud_tolerations()
+        }
+    }))?;
+    pods.create(&PostParams::default(), &p).await?;
+    wait_for_pod_ready(client.clone(), "greet-config-reload", "default").await?;
+    let started_at = |pod: Pod| {
+        pod.status
+            .and_then(|s| s.container_statuses)
+            .and_then(|statuses| statuses.into_iter().next())
+            .and_then(|status| status.state)
+            .and_then(|state| state.running)
+            .and_then(|running| running.started_at)
+            .map(|t| t.0)
+    };
+    let before = started_at(pods.get("greet-config-reload").await?);
+    assert!(before.is_some(), "container was not running");
+
+    config_maps
+        .patch(
+            "greet-config-reload",
+            &PatchParams::default(),
+            &Patch::Merge(json!({ "data": { "GREETING": "bonjour" } })),
+        )
+        .await?;
+    // Long enough for the change to be spotted, settle and be applied
+    tokio::time::sleep(std::time::Duration::from_secs(15)).await;
+
+    let after = started_at(pods.get("greet-config-reload").await?);
+    assert_eq!(
+        before, after,
+        "the container was restarted to apply the ConfigMap change"
+    );
+    // The actor's links can't be read back from outside, but when the krustlet under test
+    // shares its data directory the mounted copy of the value can
+    if let Ok(data_dir) = std::env::var("KRUSTLET_DATA_DIR") {
+        let greeting = std::path::PathBuf::from(data_dir)
+            .join("volumes")
+            .join("greet-config-reload-default")
+            .join("config")
+            .join("GREETING");
+        assert_eq!(
+            std::fs::read_to_string(&greeting)?,
+            "bonjour",
+            "the actor still sees the old value"
+        );
+    }
+
+    config_maps
+        .delete("greet-config-reload", &DeleteParams::default())
+        .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_config_map_volume_swapped_atomically() -> Result<(), Box<dyn std::error::Error>> {
+    // Only meaningful when the krustlet under test shares its data directory with the test
+    let data_dir = match std::env::var("KRUSTLET_DATA_DIR") {
+        Ok(dir) => std::path::PathBuf::from(dir),
+        Err(_) => return Ok(()),
+    };
+    let client = kube::Client::try_default().await?;
+    let pods: Api<Pod> = Api::namespaced(client.clone(), "default");
+    let config_maps: Api<ConfigMap> = Api::namespaced(client.clone(), "default");
+
+    let _cleaner = WasmCloudTestResourceCleaner {
+        pods: vec!["greet-config-swap"],
+    };
+
+    // Large enough that a torn read would show
+    let value = |version: &str| version.repeat(64 * 1024);
+    let cm = serde_json::from_value(json!({
+        "apiVersion": "v1",
+        "kind": "ConfigMap",
+        "metadata": { "name": "greet-config-swap" },
+        "data": { "first": value("0"), "second": value("0") }
+    }))?;
+    config_maps.create(&PostParams::default(), &cm).await?;
+
+    let p = serde_json::from_value(json!({
+        "apiVersion": "v1",
+        "kind": "Pod",
+        "metadata": {
+            "name": "greet-config-swap"
+        },
+        "spec": {
+            "containers": [
+                {
+                    "name": "greet-config-swap",
+                    "image": "webassembly.azurecr.io/greet-wasmcloud:v0.6.0",
+                    "ports": [{ "containerPort": 8080 }],
+                    "volumeMounts": [{ "name": "config", "mountPath": "/config" }]
+                },
+            ],
+            "volumes": [
+                { "name": "config", "configMap": { "name": "greet-config-swap" } }
+            ],
+            "tolerations": wasmcloud_tolerations()
+        }
+    }))?;
+    pods.create(&PostParams::default(), &p).await?;
+    wait_for_pod_ready(client.clone(), "greet-config-swap", "default").await?;
+
+    let volume = data_dir
+        .join("volumes")
+        .join("greet-config-swap-default")
+        .join("config");
+    // Give the reload task time to move the mounted files into the swapped layout
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+    assert!(
+        volume.join("..data").exists(),
+        "volume {} was not set up for swapping",
+        volume.display()
+    );
+
+    let done = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let reader = {
+        let volume = volume.clone();
+        let done = done.clone();
+        std::thread::spawn(move || -> Result<usize, String> {
+            let uniform = |contents: &str| {
+                contents.len() == 64 * 1024
+                    && contents.chars().all(|c| Some(c) == contents.chars().next())
+            };
+            let mut reads = 0;
+            while !done.load(std::sync::atomic::Ordering::SeqCst) {
+                if let Ok(first) = std::fs::read_to_string(volume.join("first")) {
+                    if !uniform(&first) {
+                        return Err("read a torn file".to_owned());
+                    }
+                }
+                // Files read through one version must come from the same update
+                let version = match std::fs::read_link(volume.join("..data")) {
+                    Ok(version) => volume.join(version),
+                    Err(_) => continue,
+                };
+                let first = std::fs::read_to_string(version.join("first"));
+                let second = std::fs::read_to_string(version.join("second"));
+                // The version may have been replaced and removed since it was looked up
+                if let (Ok(first), Ok(second)) = (first, second) {
+                    if first != second {
+                        return Err("read files from different updates".to_owned());
+                    }
+                    reads += 1;
+                }
+            }
+            Ok(reads)
+        })
+    };
+
+    for version in &["1", "2", "3"] {
+        config_maps
+            .patch(
+                "greet-config-swap",
+                &PatchParams::default(),
+                &Patch::Merge(
+                    json!({ "data": { "first": value(version), "second": value(version) } }),
+                ),
+            )
+            .await?;
+        // Long enough for the change to be spotted, settle and be applied
+        tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+    }
+    done.store(true, std::sync::atomic::Ordering::SeqCst);
+    let reads = reader.join().expect("reader panicked")?;
+    assert!(reads > 0, "the volume was never read");
+    assert_eq!(
+        std::fs::read_to_string(volume.join("second"))?,
+        value("3"),
+        "the last update was not applied"
+    );
+
+    config_maps
+        .delete("greet-config-swap", &DeleteParams::default())
+        .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_secret_env_redacted_from_status() -> Result<(), Box<dyn std::error::Error>> {
+    let client = kube::Client::try_default().await?;
+    let pods: Api<Pod> = Api::namespaced(client.clone(), "default");
+    let secrets: Api<Secret> = Api::namespaced(client.clone(), "default");
+
+    let _cleaner = WasmCloudTestResourceCleaner {
+        pods: vec!["greet-secret-redaction"],
+    };
+
+    let secret = serde_json::from_value(json!({
+        "apiVersion": "v1",
+        "kind": "Secret",
+        "metadata": { "name": "greet-secret-redaction" },
+        "stringData": { "port": "hunter2-not-a-port" }
+    }))?;
+    secrets.create(&PostParams::default(), &secret).await?;
+
+    // An invalid PORT fails the start with an error quoting the value
+    let p = serde_json::from_value(json!({
+        "apiVersion": "v1",
+        "kind": "Pod",
+        "metadata": {
+            "name": "greet-secret-redaction"
+        },
+        "spec": {
+            "containers": [
+                {
+                    "name": "greet-secret-redaction",
+                    "image": "webassembly.azurecr.io/greet-wasmcloud:v0.6.0",
+                    "env": [
+                        {
+                            "name": "PORT",
+                            "valueFrom": {
+                                "secretKeyRef": { "name": "greet-secret-redaction", "key": "port" }
+                            }
+                        }
+                    ]
+                },
+            ],
+            "tolerations": wasmcloud_tolerations()
+        }
+    }))?;
+    pods.create(&PostParams::default(), &p).await?;
+
+    let message =
+        wait_for_container_terminated(client.clone(), "greet-secret-redaction", "default").await?;
+    assert!(
+        !message.contains("hunter2"),
+        "the secret value appeared in the container status: {}",
+        message
+    );
+    assert!(
+        message.contains("[REDACTED]"),
+        "expected the secret value to be masked, got {}",
+        message
+    );
+
+    secrets
+        .delete("greet-secret-redaction", &DeleteParams::default())
+        .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_state_dir_survives_pod_recreation() -> Result<(), Box<dyn std::error::Error>> {
+    // Only meaningful when the krustlet under test was given a state directory
+    let base = match std::env::var("KRUSTLET_WASMCLOUD_STATE_DIR") {
+        Ok(base) => std::path::PathBuf::from(base),
+        Err(_) => return Ok(()),
+    };
+    let client = kube::Client::try_default().await?;
+    let pods: Api<Pod> = Api::namespaced(client.clone(), "default");
+
+    let _cleaner = WasmCloudTestResourceCleaner {
+        pods: vec!["greet-stateful"],
+    };
+
+    let p: Pod = serde_json::from_value(json!({
+        "apiVersion": "v1",
+        "kind": "Pod",
+        "metadata": {
+            "name": "greet-stateful"
+        },
+        "spec": {
+            "containers": [
+                {
+                    "name": "greet-stateful",
+                    "image": "webassembly.azurecr.io/greet-wasmcloud:v0.6.0",
+                    "ports": [{ "containerPort": 8080 }],
+                },
+            ],
+            "tolerations": wasmcloud_tolerations()
+        }
+    }))?;
+    let state_dir = base
+        .join("default")
+        .join("greet-stateful")
+        .join("greet-stateful");
+
+    pods.create(&PostParams::default(), &p).await?;
+    wait_for_pod_ready(client.clone(), "greet-stateful", "default").await?;
+    assert!(state_dir.is_dir(), "state directory was not created");
+    std::fs::write(state_dir.join("marker"), "kept")?;
+
+    pods.delete("greet-stateful", &DeleteParams::default())
+        .await?;
+    wait_for_pod_deleted(client.clone(), "greet-stateful", "default").await?;
+    pods.create(&PostParams::default(), &p).await?;
+    wait_for_pod_ready(client.clone(), "greet-stateful", "default").await?;
+
+    assert_eq!(
+        std::fs::read_to_string(state_dir.join("marker"))?,
+        "kept",
+        "the recreated pod did not get its previous state directory"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_unsupported_resource_requests() -> Result<(), Box<dyn std::error::Error>> {
+    let client = kube::Client::try_default().await?;
+    let pods: Api<Pod> = Api::namespaced(client.clone(), "default");
+
+    let _cleaner = WasmCloudTestResourceCleaner {
+        pods: vec!["greet-gpu", "greet-cpu"],
+    };
+
+    for (name, resources) in &[
+        ("greet-gpu", json!({ "nvidia.com/gpu": "1" })),
+        ("greet-cpu", json!({ "cpu": "100m" })),
+    ] {
+        let p = serde_json::from_value(json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {
+                "name": name
+            },
+            "spec": {
+                "containers": [
+                    {
+                        "name": name,
+                        "image": "webassembly.azurecr.io/greet-wasmcloud:v0.6.0",
+                        "ports": [{ "containerPort": 8080 }],
+                        "resources": { "requests": resources, "limits": resources }
+                    },
+                ],
+                "tolerations": wasmcloud_tolerations()
+            }
+        }))?;
+        pods.create(&PostParams::default(), &p).await?;
+    }
+
+    let message =
+        wait_for_pod_message(client.clone(), "greet-gpu", "default", "nvidia.com/gpu").await?;
+    assert!(
+        message.contains("does not provide"),
+        "expected the GPU request to be refused, got: {}",
+        message
+    );
+    wait_for_pod_ready(client.clone(), "greet-cpu", "default").await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dangling_volume_mount() -> Result<(), Box<dyn std::error::Error>> {
+    let client = kube::Client::try_default().await?;
+    let pods: Api<Pod> = Api::namespaced(client.clone(), "default");
+
+    let p = serde_json::from_value(json!({
+        "apiVersion": "v1",
+        "kind": "Pod",
+        "metadata": {
+            "name": "greet-dangling-mount"
+        },
+        "spec": {
+            "containers": [
+                {
+                    "name": "greet-dangling-mount",
+                    "image": "webassembly.azurecr.io/greet-wasmcloud:v0.6.0",
+                    "ports": [{ "containerPort": 8080 }],
+                    "volumeMounts": [{ "name": "confg", "mountPath": "/config" }]
+                },
+            ],
+            "volumes": [
+                { "name": "config", "emptyDir": {} }
+            ],
+            "tolerations": wasmcloud_tolerations()
+        }
+    }))?;
+    // The API server refuses it before it reaches the node. The provider checks again for pods
+    // that didn't go through its validation
+    match pods.create(&PostParams::default(), &p).await {
+        Err(kube::Error::Api(e)) => assert!(
+            e.message.contains("confg"),
+            "expected the missing volume to be named, got: {}",
+            e.message
+        ),
+        Err(e) => return Err(e.into()),
+        Ok(_) => {
+            let _cleaner = WasmCloudTestResourceCleaner {
+                pods: vec!["greet-dangling-mount"],
+            };
+            let message = wait_for_pod_message(
+                client.clone(),
+                "greet-dangling-mount",
+                "default",
+                "not one of the pod's volumes",
+            )
+            .await?;
+            assert!(
+                message.contains("confg"),
+                "expected the missing volume to be named, got: {}",
+                message
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_runtime_class() -> Result<(), Box<dyn std::error::Error>> {
+    let client = kube::Client::try_default().await?;
+    let pods: Api<Pod> = Api::namespaced(client.clone(), "default");
+    let runtime_classes: Api<RuntimeClass> = Api::all(client.clone());
+
+    let _cleaner = WasmCloudTestResourceCleaner {
+        pods: vec![
+            "greet-runtime-class-match",
+            "greet-runtime-class-mismatch",
+            "greet-runtime-class-unset",
+        ],
+    };
+
+    // Pods may only name runtime classes that exist
+    for name in &["wasmcloud", "krustlet-test-other"] {
+        let runtime_class = serde_json::from_value(json!({
+            "apiVersion": "node.k8s.io/v1beta1",
+            "kind": "RuntimeClass",
+            "metadata": { "name": name },
+            "handler": name
+        }))?;
+        match runtime_classes
+            .create(&PostParams::default(), &runtime_class)
+            .await
+        {
+            Err(kube::Error::Api(e)) if e.code == 409 => {}
+            result => {
+                result?;
+            }
+        }
+    }
+
+    for (name, runtime_class) in &[
+        ("greet-runtime-class-match", json!("wasmcloud")),
+        ("greet-runtime-class-mismatch", json!("krustlet-test-other")),
+        ("greet-runtime-class-unset", json!(null)),
+    ] {
+        let p = serde_json::from_value(json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {
+                "name": name
+            },
+            "spec": {
+                "runtimeClassName": runtime_class,
+                "containers": [
+                    {
+                        "name": name,
+                        "image": "webassembly.azurecr.io/greet-wasmcloud:v0.6.0",
+                        "ports": [{ "containerPort": 8080 }],
+                    },
+                ],
+                "tolerations": wasmcloud_tolerations()
+            }
+        }))?;
+        pods.create(&PostParams::default(), &p).await?;
+    }
+
+    wait_for_pod_ready(client.clone(), "greet-runtime-class-match", "default").await?;
+    wait_for_pod_ready(client.clone(), "greet-runtime-class-unset", "default").await?;
+    let message = wait_for_pod_message(
+        client.clone(),
+        "greet-runtime-class-mismatch",
+        "default",
+        "krustlet-test-other",
+    )
+    .await?;
+    assert!(
+        message.contains("runtime class"),
+        "expected the mismatching runtime class to be refused, got: {}",
+        message
+    );
+
+    runtime_classes
+        .delete("krustlet-test-other", &DeleteParams::default())
+        .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_capability_versions() -> Result<(), Box<dyn std::error::Error>> {
+    let client = kube::Client::try_default().await?;
+    let pods: Api<Pod> = Api::namespaced(client.clone(), "default");
+
+    let _cleaner = WasmCloudTestResourceCleaner {
+        pods: vec!["greet-capability-match", "greet-capability-mismatch"],
+    };
+
+    for (name, versions) in &[
+        (
+            "greet-capability-match",
+            "wasmcloud:httpserver=0.1.0,wasmcloud:logging=0.1.0",
+        ),
+        ("greet-capability-mismatch", "wasmcloud:httpserver=0.2.0"),
+    ] {
+        let p = serde_json::from_value(json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {
+                "name": name,
+                "annotations": {
+                    "wasmcloud.dev/capability-versions": versions
+                }
+            },
+            "spec": {
+                "containers": [
+                    {
+                        "name": name,
+                        "image": "webassembly.azurecr.io/greet-wasmcloud:v0.6.0",
+                        "ports": [{ "containerPort": 8080 }],
+                    },
+                ],
+                "tolerations": wasmcloud_tolerations()
+            }
+        }))?;
+        pods.create(&PostParams::default(), &p).await?;
+    }
+
+    wait_for_pod_ready(client.clone(), "greet-capability-match", "default").await?;
+    let message = wait_for_pod_message(
+        client.clone(),
+        "greet-capability-mismatch",
+        "default",
+        "CapabilityVersionMismatch",
+    )
+    .await?;
+    assert!(
+        message.contains("wasmcloud:httpserver"),
+        "expected the mismatching capability to be named, got: {}",
+        message
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_logs_before_any_output() -> Result<(), Box<dyn std::error::Error>> {
+    let client = kube::Client::try_default().await?;
+    let pods: Api<Pod> = Api::namespaced(client.clone(), "default");
+
+    let _cleaner = WasmCloudTestResourceCleaner {
+        pods: vec!["greet-no-output"],
+    };
+
+    let p = serde_json::from_value(json!({
+        "apiVersion": "v1",
+        "kind": "Pod",
+        "metadata": {
+            "name": "greet-no-output"
+        },
+        "spec": {
+            "containers": [
+                {
+                    "name": "greet-no-output",
+                    "image": "webassembly.azurecr.io/greet-wasmcloud:v0.6.0",
+                    "ports": [{ "containerPort": 8080 }],
+                },
+            ],
+            "tolerations": wasmcloud_tolerations()
+        }
+    }))?;
+    pods.create(&PostParams::default(), &p).await?;
+    wait_for_pod_ready(client.clone(), "greet-no-output", "default").await?;
+
+    // The actor only logs when it handles a request, so nothing has been written yet
+    let logs = pods.logs("greet-no-output", &LogParams::default()).await?;
+    assert_eq!(logs, "");
+    let tail = LogParams {
+        tail_lines: Some(10),
+        ..LogParams::default()
+    };
+    let logs = pods.logs("greet-no-output", &tail).await?;
+    assert_eq!(logs, "");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_log_sampling() -> Result<(), Box<dyn std::error::Error>> {
+    let client = kube::Client::try_default().await?;
+    let pods: Api<Pod> = Api::namespaced(client.clone(), "default");
+
+    let _cleaner = WasmCloudTestResourceCleaner {
+        pods: vec!["greet-log-sampling"],
+    };
+
+    let p = serde_json::from_value(json!({
+        "apiVersion": "v1",
+        "kind": "Pod",
+        "metadata": {
+            "name": "greet-log-sampling",
+            "annotations": {
+                "wasmcloud.dev/log-sample-rate": "4"
+            }
+        },
+        "spec": {
+            "containers": [
+                {
+                    "name": "greet-log-sampling",
+                    "image": "webassembly.azurecr.io/greet-wasmcloud:v0.6.0",
+                    "ports": [{ "containerPort": 8080, "hostPort": 30301 }],
+                },
+            ],
+            "tolerations": wasmcloud_tolerations()
+        }
+    }))?;
+    pods.create(&PostParams::default(), &p).await?;
+    wait_for_pod_ready(client.clone(), "greet-log-sampling", "default").await?;
+
+    // Each request logs one record at every level
+    let requests = 20;
+    for _ in 0..requests {
+        reqwest::get("http://127.0.0.1:30301").await?;
+    }
+
+    let logs = pods
+        .logs("greet-log-sampling", &LogParams::default())
+        .await?;
+    let info = logs.matches("info something").count();
+    assert_eq!(logs.matches("error something").count(), requests);
+    assert_eq!(logs.matches("warn something").count(), requests);
+    assert!(
+        info > 0 && info < requests,
+        "expected some but not all info records to be kept, got {} of {}",
+        info,
+        requests
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_log_storage_annotation() -> Result<(), Box<dyn std::error::Error>> {
+    let client = kube::Client::try_default().await?;
+    // A namespace of its own, so any log file written for the pod would be easy to find
+    let namespace = "wasmcloud-memory-logs";
+    let namespaces: Api<Namespace> = Api::all(client.clone());
+    let ns = serde_json::from_value(json!({
+        "apiVersion": "v1",
+        "kind": "Namespace",
+        "metadata": { "name": namespace }
+    }))?;
+    match namespaces.create(&PostParams::default(), &ns).await {
+        Err(kube::Error::Api(e)) if e.code == 409 => {}
+        result => {
+            result?;
+        }
+    }
+    let pods: Api<Pod> = Api::namespaced(client.clone(), namespace);
+
+    let p = serde_json::from_value(json!({
+        "apiVersion": "v1",
+        "kind": "Pod",
+        "metadata": {
+            "name": "greet-memory-logs",
+            "annotations": {
+                "wasmcloud.dev/log-storage": "memory"
+            }
+        },
+        "spec": {
+            "containers": [
+                {
+                    "name": "greet-memory-logs",
+                    "image": "webassembly.azurecr.io/greet-wasmcloud:v0.6.0",
+                    "ports": [{ "containerPort": 8080, "hostPort": 30305 }],
+                },
+            ],
+            "tolerations": wasmcloud_tolerations()
+        }
+    }))?;
+    pods.create(&PostParams::default(), &p).await?;
+    wait_for_pod_ready(client.clone(), "greet-memory-logs", namespace).await?;
+    reqwest::get("http://127.0.0.1:30305").await?;
+
+    let logs = pods
+        .logs("greet-memory-logs", &LogParams::default())
+        .await?;
+    assert!(
+        logs.contains("error something"),
+        "expected the request to be logged, got {}",
+        logs
+    );
+    // Logs on disk are kept per namespace, so the namespace has none
+    if let Ok(data_dir) = std::env::var("KRUSTLET_DATA_DIR") {
+        let log_dir = std::path::Path::new(&data_dir)
+            .join("wasmcloud-logs")
+            .join(namespace);
+        let files = std::fs::read_dir(&log_dir)
+            .map(|entries| entries.count())
+            .unwrap_or(0);
+        assert_eq!(files, 0, "expected no log files in {}", log_dir.display());
+    }
+
+    namespaces
+        .delete(namespace, &DeleteParams::default())
+        .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_log_trimmed_under_disk_pressure() -> Result<(), Box<dyn std::error::Error>> {
+    // Only meaningful when the krustlet under test was given a free space threshold above what
+    // its disk has, so it is always under pressure
+    if std::env::var("KRUSTLET_WASMCLOUD_LOG_MIN_FREE_BYTES").is_err() {
+        return Ok(());
+    }
+    let client = kube::Client::try_default().await?;
+    let pods: Api<Pod> = Api::namespaced(client.clone(), "default");
+
+    let _cleaner = WasmCloudTestResourceCleaner {
+        pods: vec!["greet-log-trim"],
+    };
+
+    let p = serde_json::from_value(json!({
+        "apiVersion": "v1",
+        "kind": "Pod",
+        "metadata": {
+            "name": "greet-log-trim"
+        },
+        "spec": {
+            "containers": [
+                {
+                    "name": "greet-log-trim",
+                    "image": "webassembly.azurecr.io/greet-wasmcloud:v0.6.0",
+                    "ports": [{ "containerPort": 8080, "hostPort": 30302 }],
+                },
+            ],
+            "tolerations": wasmcloud_tolerations()
+        }
+    }))?;
+    pods.create(&PostParams::default(), &p).await?;
+    wait_for_pod_ready(client.clone(), "greet-log-trim", "default").await?;
+
+    for _ in 0..20 {
+        reqwest::get("http://127.0.0.1:30302").await?;
+    }
+    // Give the monitor time to check the disk at least once
+    tokio::time::sleep(std::time::Duration::from_secs(15)).await;
+
+    let logs = pods.logs("greet-log-trim", &LogParams::default()).await?;
+    assert!(
+        logs.contains("log trimmed by the kubelet"),
+        "expected the log to have been trimmed, got {}",
+        logs
+    );
+    assert!(
+        logs.matches("error something").count() < 20,
+        "expected records from before the trim to be gone"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dedicated_host() -> Result<(), Box<dyn std::error::Error>> {
+    // Which host each actor runs in is only visible in the audit trail, so this needs the
+    // krustlet under test to write it to a file the test can read
+    let audit_log = match std::env::var("KRUSTLET_WASMCLOUD_AUDIT_LOG") {
+        Ok(path) if path != "stdout" => path,
+        _ => return Ok(()),
+    };
+    let client = kube::Client::try_default().await?;
+    let pods: Api<Pod> = Api::namespaced(client.clone(), "default");
+
+    let _cleaner = WasmCloudTestResourceCleaner {
+        pods: vec!["greet-dedicated", "greet-shared"],
+    };
+
+    for (name, port, dedicated) in &[
+        ("greet-dedicated", 30303, "true"),
+        ("greet-shared", 30304, "false"),
+    ] {
+        let p = serde_json::from_value(json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {
+                "name": name,
+                "annotations": {
+                    "wasmcloud.dev/dedicated-host": dedicated
+                }
+            },
+            "spec": {
+                "containers": [
+                    {
+                        "name": name,
+                        "image": "webassembly.azurecr.io/greet-wasmcloud:v0.6.0",
+                        "ports": [{ "containerPort": 8080, "hostPort": port }],
+                    },
+                ],
+                "tolerations": wasmcloud_tolerations()
+            }
+        }))?;
+        pods.create(&PostParams::default(), &p).await?;
+        wait_for_pod_ready(client.clone(), name, "default").await?;
+    }
+    for port in &[30303, 30304] {
+        reqwest::get(&format!("http://127.0.0.1:{}", port)).await?;
+    }
+
+    // The host each pod's actor was started in
+    let started_in = |pod: &str| -> Result<String, Box<dyn std::error::Error>> {
+        let hosts: Vec<String> = std::fs::read_to_string(&audit_log)?
+            .lines()
+            .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+            .filter(|record| {
+                record["namespace"] == "default"
+                    && record["pod"] == pod
+                    && record["action"] == "start"
+                    && record["outcome"] == "success"
+            })
+            .filter_map(|record| record["host"].as_str().map(str::to_owned))
+            .collect();
+        hosts
+            .last()
+            .cloned()
+            .ok_or_else(|| format!("no start of pod {} was audited", pod).into())
+    };
+    let dedicated = started_in("greet-dedicated")?;
+    let shared = started_in("greet-shared")?;
+    assert!(!dedicated.is_empty() && !shared.is_empty());
+    assert_ne!(
+        dedicated, shared,
+        "the dedicated pod's actor was started in the shared host"
+    );
+
+    // Tearing down the dedicated host leaves the shared one serving
+    pods.delete("greet-dedicated", &DeleteParams::default())
+        .await?;
+    wait_for_pod_deleted(client.clone(), "greet-dedicated", "default").await?;
+    reqwest::get("http://127.0.0.1:30304").await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_image_fetch_timeout() -> Result<(), Box<dyn std::error::Error>> {
+    // Only meaningful when the krustlet under test was given an image fetch timeout, which must
+    // be shorter than the 30 seconds the pod is watched for
+    if std::env::var("KRUSTLET_WASMCLOUD_IMAGE_FETCH_TIMEOUT_SECS").is_err() {
+        return Ok(());
+    }
+    let client = kube::Client::try_default().await?;
+    let pods: Api<Pod> = Api::namespaced(client.clone(), "default");
+
+    let _cleaner = WasmCloudTestResourceCleaner {
+        pods: vec!["greet-fetch-timeout"],
+    };
+
+    // Connections to this non-routable address hang rather than being refused
+    let p = serde_json::from_value(json!({
+        "apiVersion": "v1",
+        "kind": "Pod",
+        "metadata": {
+            "name": "greet-fetch-timeout"
+        },
+        "spec": {
+            "containers": [
+                {
+                    "name": "greet-fetch-timeout",
+                    "image": "10.255.255.1/greet-wasmcloud:v0.6.0",
+                },
+            ],
+            "tolerations": wasmcloud_tolerations()
+        }
+    }))?;
+    pods.create(&PostParams::default(), &p).await?;
+
+    wait_for_pod_message(
+        client.clone(),
+        "greet-fetch-timeout",
+        "default",
+        "ImagePullTimeout",
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_audit_records_start_and_stop() -> Result<(), Box<dyn std::error::Error>> {
+    // Needs the krustlet under test to write its audit trail to a file the test can read
+    let audit_log = match std::env::var("KRUSTLET_WASMCLOUD_AUDIT_LOG") {
+        Ok(path) if path != "stdout" => path,
+        _ => return Ok(()),
+    };
+    let client = kube::Client::try_default().await?;
+    let pods: Api<Pod> = Api::namespaced(client.clone(), "default");
+
+    let _cleaner = WasmCloudTestResourceCleaner {
+        pods: vec!["greet-audit"],
+    };
+
+    let p = serde_json::from_value(json!({
+        "apiVersion": "v1",
+        "kind": "Pod",
+        "metadata": {
+            "name": "greet-audit"
+        },
+        "spec": {
+            "containers": [
+                {
+                    "name": "greet-audit",
+                    "image": "webassembly.azurecr.io/greet-wasmcloud:v0.6.0",
+                    "ports": [{ "containerPort": 8080 }],
+                },
+            ],
+            "tolerations": wasmcloud_tolerations()
+        }
+    }))?;
+    pods.create(&PostParams::default(), &p).await?;
+    wait_for_pod_ready(client.clone(), "greet-audit", "default").await?;
+    pods.delete("greet-audit", &DeleteParams::default()).await?;
+    wait_for_pod_deleted(client.clone(), "greet-audit", "default").await?;
+
+    let records: Vec<serde_json::Value> = std::fs::read_to_string(&audit_log)?
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .filter(|record: &serde_json::Value| {
+            record["namespace"] == "default" && record["pod"] == "greet-audit"
+        })
+        .collect();
+    let actions: Vec<&str> = records
+        .iter()
+        .filter(|record| record["action"] == "start" || record["action"] == "stop")
+        .map(|record| record["action"].as_str().unwrap_or_default())
+        .collect();
+    assert_eq!(actions, ["start", "stop"]);
+    for record in &records {
+        assert_eq!(record["outcome"], "success", "unexpected record {}", record);
+        assert!(record["timestamp"].is_string());
+        assert!(record["actor"]
+            .as_str()
+            .map_or(false, |actor| !actor.is_empty()));
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_links_removed_on_stop() -> Result<(), Box<dyn std::error::Error>> {
+    // Links are only visible in the audit trail, so this needs the krustlet under test to write
+    // it to a file the test can read
+    let audit_log = match std::env::var("KRUSTLET_WASMCLOUD_AUDIT_LOG") {
+        Ok(path) if path != "stdout" => path,
+        _ => return Ok(()),
+    };
+    let client = kube::Client::try_default().await?;
+    let pods: Api<Pod> = Api::namespaced(client.clone(), "default");
+
+    let _cleaner = WasmCloudTestResourceCleaner {
+        pods: vec!["greet-unlink"],
+    };
+
+    let p = serde_json::from_value(json!({
+        "apiVersion": "v1",
+        "kind": "Pod",
+        "metadata": {
+            "name": "greet-unlink"
+        },
+        "spec": {
+            "containers": [
+                {
+                    "name": "greet-unlink",
+                    "image": "webassembly.azurecr.io/greet-wasmcloud:v0.6.0",
+                    "ports": [{ "containerPort": 8080 }],
+                },
+            ],
+            "tolerations": wasmcloud_tolerations()
+        }
+    }))?;
+    pods.create(&PostParams::default(), &p).await?;
+    wait_for_pod_ready(client.clone(), "greet-unlink", "default").await?;
+    pods.delete("greet-unlink", &DeleteParams::default())
+        .await?;
+    wait_for_pod_deleted(client.clone(), "greet-unlink", "default").await?;
+
+    let records: Vec<serde_json::Value> = std::fs::read_to_string(&audit_log)?
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .filter(|record: &serde_json::Value| record["pod"] == "greet-unlink")
+        .collect();
+    let capabilities = |action: &str| {
+        let mut capabilities: Vec<String> = records
+            .iter()
+            .filter(|record| record["action"] == action && record["outcome"] == "success")
+            .map(|record| format!("{}:{}", record["capability"], record["binding"]))
+            .collect();
+        capabilities.sort();
+        capabilities
+    };
+    let linked = capabilities("link");
+    assert!(!linked.is_empty(), "expected the actor to have been linked");
+    assert_eq!(linked, capabilities("unlink"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dev_dir() -> Result<(), Box<dyn std::error::Error>> {
+    // Only meaningful when the krustlet under test watches a dev directory this test can write
+    // to
+    let dev_dir = match std::env::var("KRUSTLET_WASMCLOUD_DEV_DIR") {
+        Ok(dir) => std::path::PathBuf::from(dir),
+        Err(_) => return Ok(()),
+    };
+    let client = kube::Client::try_default().await?;
+
+    let _cleaner = WasmCloudTestResourceCleaner {
+        pods: vec!["greet-dev"],
+    };
+
+    let reference: oci_distribution::Reference =
+        "webassembly.azurecr.io/greet-wasmcloud:v0.6.0".parse()?;
+    let image = oci_distribution::Client::default()
+        .pull(
+            &reference,
+            &oci_distribution::secrets::RegistryAuth::Anonymous,
+            vec![oci_distribution::manifest::WASM_LAYER_MEDIA_TYPE],
+        )
+        .await?;
+    let module = dev_dir.join("greet-dev.wasm");
+    std::fs::write(&module, &image.layers[0].data)?;
+
+    wait_for_pod_ready(client.clone(), "greet-dev", "default").await?;
+
+    std::fs::remove_file(&module)?;
+    wait_for_pod_deleted(client.clone(), "greet-dev", "default").await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_component_module_refused() -> Result<(), Box<dyn std::error::Error>> {
+    // The dev directory is the only way to hand the krustlet under test a module without
+    // pushing it to a registry
+    let dev_dir = match std::env::var("KRUSTLET_WASMCLOUD_DEV_DIR") {
+        Ok(dir) => std::path::PathBuf::from(dir),
+        Err(_) => return Ok(()),
+    };
+    let client = kube::Client::try_default().await?;
+
+    let _cleaner = WasmCloudTestResourceCleaner {
+        pods: vec!["component-dev"],
+    };
+
+    // The header of an empty component: the wasm magic number, version 0x0d and layer 1
+    let module = dev_dir.join("component-dev.wasm");
+    std::fs::write(&module, b"\0asm\x0d\x00\x01\x00")?;
+
+    let message = wait_for_container_terminated(client.clone(), "component-dev", "default").await?;
+    assert!(
+        message.contains("UnsupportedModuleFormat") && message.contains("component"),
+        "expected an UnsupportedModuleFormat termination, got: {}",
+        message
+    );
+
+    std::fs::remove_file(&module)?;
+    wait_for_pod_deleted(client.clone(), "component-dev", "default").await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_conflicting_host_ports() -> Result<(), Box<dyn std::error::Error>> {
+    let client = kube::Client::try_default().await?;
+    let pods: Api<Pod> = Api::namespaced(client.clone(), "default");
+
+    let _cleaner = WasmCloudTestResourceCleaner {
+        pods: vec!["greet-port-first", "greet-port-second"],
+    };
+
+    for name in &["greet-port-first", "greet-port-second"] {
+        let p = serde_json::from_value(json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {
+                "name": name
+            },
+            "spec": {
+                "containers": [
+                    {
+                        "name": name,
+                        "image": "webassembly.azurecr.io/greet-wasmcloud:v0.6.0",
+                        "ports": [{ "containerPort": 8080, "hostPort": 30401 }],
+                    },
+                ],
+                "tolerations": wasmcloud_tolerations()
+            }
+        }))?;
+        pods.create(&PostParams::default(), &p).await?;
+        if *name == "greet-port-first" {
+            wait_for_pod_ready(client.clone(), name, "default").await?;
+        }
+    }
+
+    let message = wait_for_pod_message(
+        client.clone(),
+        "greet-port-second",
+        "default",
+        "already holds",
+    )
+    .await?;
+    assert!(
+        message.contains("greet-port-first"),
+        "expected the conflicting pod to be named, got: {}",
+        message
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_image_instance_limit() -> Result<(), Box<dyn std::error::Error>> {
+    // Needs the krustlet under test to limit an image, which must not be one the other tests
+    // run. The first listed image is used, and its limit must be at most 3
+    const NAMES: [&str; 4] = [
+        "greet-image-limit-0",
+        "greet-image-limit-1",
+        "greet-image-limit-2",
+        "greet-image-limit-3",
+    ];
+    let (image, max) = match std::env::var("KRUSTLET_WASMCLOUD_IMAGE_INSTANCE_LIMITS") {
+        Ok(limits) => {
+            let first = limits
+                .split(',')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_owned();
+            let mut parts = first.rsplitn(2, '=');
+            let max: usize = parts.next().unwrap_or_default().parse()?;
+            (parts.next().unwrap_or_default().to_owned(), max)
+        }
+        Err(_) => return Ok(()),
+    };
+    assert!(max < NAMES.len(), "the limit must be at most 3");
+    let client = kube::Client::try_default().await?;
+    let pods: Api<Pod> = Api::namespaced(client.clone(), "default");
+
+    // One more pod than the limit allows, each running a single instance
+    let names = &NAMES[..=max];
+    let _cleaner = WasmCloudTestResourceCleaner {
+        pods: names.to_vec(),
+    };
+
+    for (i, name) in names.iter().enumerate() {
+        let p = serde_json::from_value(json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {
+                "name": name
+            },
+            "spec": {
+                "containers": [
+                    {
+                        "name": name,
+                        "image": image,
+                        "ports": [{ "containerPort": 8080, "hostPort": 30601 + i }],
+                    },
+                ],
+                "tolerations": wasmcloud_tolerations()
+            }
+        }))?;
+        pods.create(&PostParams::default(), &p).await?;
+        if i < max {
+            wait_for_pod_ready(client.clone(), name, "default").await?;
+        }
+    }
+
+    wait_for_pod_message(
+        client.clone(),
+        names[max],
+        "default",
+        "ImageInstanceLimitExceeded",
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_node_log_stream_limit() -> Result<(), Box<dyn std::error::Error>> {
+    // Needs the krustlet under test to limit log streams across the node. Other tests reading
+    // logs at the same time count against the limit too, so run this one on its own
+    let max: usize = match std::env::var("KRUSTLET_WASMCLOUD_MAX_NODE_LOG_STREAMS") {
+        Ok(max) => max.parse()?,
+        Err(_) => return Ok(()),
+    };
+    if max == 0 {
+        return Ok(());
+    }
+    let client = kube::Client::try_default().await?;
+    let pods: Api<Pod> = Api::namespaced(client.clone(), "default");
+
+    let _cleaner = WasmCloudTestResourceCleaner {
+        pods: vec!["greet-log-streams-one", "greet-log-streams-two"],
+    };
+
+    for (name, port) in &[
+        ("greet-log-streams-one", 30701),
+        ("greet-log-streams-two", 30702),
+    ] {
+        let p = serde_json::from_value(json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {
+                "name": name
+            },
+            "spec": {
+                "containers": [
+                    {
+                        "name": name,
+                        "image": "webassembly.azurecr.io/greet-wasmcloud:v0.6.0",
+                        "ports": [{ "containerPort": 8080, "hostPort": port }],
+                    },
+                ],
+                "tolerations": wasmcloud_tolerations()
+            }
+        }))?;
+        pods.create(&PostParams::default(), &p).await?;
+        wait_for_pod_ready(client.clone(), name, "default").await?;
+    }
+
+    // Use up the limit following the first pod's logs, then ask for the second pod's
+    let follow = LogParams {
+        follow: true,
+        ..Default::default()
+    };
+    let mut streams = Vec::with_capacity(max);
+    for _ in 0..max {
+        streams.push(pods.log_stream("greet-log-streams-one", &follow).await?);
+    }
+    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    assert!(
+        pods.logs("greet-log-streams-two", &LogParams::default())
+            .await
+            .is_err(),
+        "a log stream beyond the node's limit was opened"
+    );
+
+    // Closing one makes room again
+    streams.pop();
+    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    pods.logs("greet-log-streams-two", &LogParams::default())
+        .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_direct_assignment_without_tolerations() -> Result<(), Box<dyn std::error::Error>> {
+    // Only meaningful when the krustlet under test tolerates direct assignment and may patch
+    // pods
+    if std::env::var("KRUSTLET_WASMCLOUD_TOLERATE_DIRECT_ASSIGNMENT").as_deref() != Ok("true") {
+        return Ok(());
+    }
+    let client = kube::Client::try_default().await?;
+    let pods: Api<Pod> = Api::namespaced(client.clone(), "default");
+
+    let _cleaner = WasmCloudTestResourceCleaner {
+        pods: vec!["greet-direct-assignment"],
+    };
+
+    // Bypasses the scheduler, which would never place a pod without the tolerations here
+    let p = serde_json::from_value(json!({
+        "apiVersion": "v1",
+        "kind": "Pod",
+        "metadata": {
+            "name": "greet-direct-assignment"
+        },
+        "spec": {
+            "nodeName": "krustlet-wasmcloud",
+            "containers": [
+                {
+                    "name": "greet-direct-assignment",
+                    "image": "webassembly.azurecr.io/greet-wasmcloud:v0.6.0",
+                    "ports": [{ "containerPort": 8080, "hostPort": 30801 }],
+                },
+            ]
+        }
+    }))?;
+    pods.create(&PostParams::default(), &p).await?;
+    wait_for_pod_ready(client.clone(), "greet-direct-assignment", "default").await?;
+
+    let events: Api<KubeEvent> = Api::namespaced(client.clone(), "default");
+    let warnings = events
+        .list(
+            &ListParams::default()
+                .fields("involvedObject.name=greet-direct-assignment,reason=MissingTolerations"),
+        )
+        .await?;
+    assert!(
+        !warnings.items.is_empty(),
+        "no MissingTolerations warning was recorded"
+    );
+
+    // Without the added tolerations the taint manager would have evicted it by now
+    tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+    let pod = pods.get("greet-direct-assignment").await?;
+    let tolerations = pod.spec.and_then(|s| s.tolerations).unwrap_or_default();
+    assert!(
+        tolerations
+            .iter()
+            .any(|t| t.key.as_deref() == Some("kubernetes.io/arch")
+                && t.effect.as_deref() == Some("NoExecute")),
+        "the NoExecute toleration was not added, got: {:?}",
+        tolerations
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_delete_while_starting() -> Result<(), Box<dyn std::error::Error>> {
+    let client = kube::Client::try_default().await?;
+    let pods: Api<Pod> = Api::namespaced(client.clone(), "default");
+
+    let _cleaner = WasmCloudTestResourceCleaner {
+        pods: vec!["greet-delete-starting"],
+    };
+
+    let p = serde_json::from_value(json!({
+        "apiVersion": "v1",
+        "kind": "Pod",
+        "metadata": {
+            "name": "greet-delete-starting"
+        },
+        "spec": {
+            "containers": [
+                {
+                    "name": "greet-delete-starting",
+                    "image": "webassembly.azurecr.io/greet-wasmcloud:v0.6.0",
+                    "ports": [{ "containerPort": 8080, "hostPort": 30501 }],
+                },
+            ],
+            "tolerations": wasmcloud_tolerations()
+        }
+    }))?;
+    pods.create(&PostParams::default(), &p).await?;
+    // Delete before the actor has had a chance to finish starting
+    pods.delete("greet-delete-starting", &DeleteParams::default())
+        .await?;
+    wait_for_pod_deleted(client.clone(), "greet-delete-starting", "default").await?;
+
+    // An orphaned actor would still be serving on its port
+    tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+    assert!(
+        reqwest::get("http://127.0.0.1:30501").await.is_err(),
+        "an actor of the deleted pod is still running"
+    );
+
+    Ok(())
+}
+
+fn wasmcloud_tolerations() -> serde_json::Value {
+    json!([
+        {
+            "effect": "NoExecute",
+            "key": "kubernetes.io/arch",
+            "operator": "Equal",
+            "value": "wasm32-wasmcloud"
+        },
+        {
+            "effect": "NoSchedule",
+            "key": "kubernetes.io/arch",
+            "operator": "Equal",
+            "value": "wasm32-wasmcloud"
+        },
+    ])
+}
+
+struct WasmCloudTestResourceCleaner {
+    pods: Vec<&'static str>,
+}
 
 impl Drop for WasmCloudTestResourceCleaner {
     fn drop(&mut self) {
+        let pods = self.pods.clone();
         let t = std::thread::spawn(move || {
             let rt =
                 tokio::runtime::Runtime::new().expect("Failed to create Tokio runtime for cleanup");
-            rt.block_on(clean_up_wasmcloud_test_resources());
+            rt.block_on(clean_up_wasmcloud_test_resources(pods));
         });
 
         t.join()
@@ -166,15 +1797,20 @@ impl Drop for WasmCloudTestResourceCleaner {
     }
 }
 
-async fn clean_up_wasmcloud_test_resources() {
+async fn clean_up_wasmcloud_test_resources(pod_names: Vec<&'static str>) {
     let client = kube::Client::try_default()
         .await
         .expect("Failed to create client");
 
     let pods: Api<Pod> = Api::namespaced(client.clone(), "default");
-    pods.delete("greet-wasmcloud", &DeleteParams::default())
-        .await
-        .expect("Failed to delete pod");
+    for pod_name in pod_names {
+        match pods.delete(pod_name, &DeleteParams::default()).await {
+            Ok(_) => {}
+            // Tests that delete their own pods have already cleaned up after themselves
+            Err(kube::Error::Api(e)) if e.code == 404 => {}
+            Err(e) => panic!("Failed to delete pod {}: {}", pod_name, e),
+        }
+    }
 }
 
 pub async fn wait_for_pod_ready(
@@ -215,3 +1851,125 @@ pub async fn wait_for_pod_ready(
 
     Ok(())
 }
+
+/// Waits for the pod to satisfy `done`, returning it as it was then.
+pub async fn wait_for_pod(
+    client: kube::Client,
+    pod_name: &str,
+    namespace: &str,
+    done: impl Fn(&Pod) -> bool,
+) -> anyhow::Result<Pod> {
+    let api: Api<Pod> = Api::namespaced(client, namespace);
+    let inf = watcher(
+        api,
+        ListParams::default()
+            .fields(&format!("metadata.name={}", pod_name))
+            .timeout(30),
+    );
+
+    let mut watcher = inf.boxed();
+    while let Some(event) = watcher.try_next().await? {
+        if let Event::Applied(o) = event {
+            if done(&o) {
+                return Ok(o);
+            }
+        }
+    }
+
+    Err(anyhow::anyhow!(
+        "pod {} never reached the expected state",
+        pod_name
+    ))
+}
+
+/// Waits for the pod's first container to terminate, returning its termination message.
+pub async fn wait_for_container_terminated(
+    client: kube::Client,
+    pod_name: &str,
+    namespace: &str,
+) -> anyhow::Result<String> {
+    let api: Api<Pod> = Api::namespaced(client, namespace);
+    let inf = watcher(
+        api,
+        ListParams::default()
+            .fields(&format!("metadata.name={}", pod_name))
+            .timeout(30),
+    );
+
+    let mut watcher = inf.boxed();
+    while let Some(event) = watcher.try_next().await? {
+        if let Event::Applied(o) = event {
+            let terminated = o
+                .status
+                .and_then(|s| s.container_statuses)
+                .and_then(|statuses| statuses.into_iter().next())
+                .and_then(|status| status.state)
+                .and_then(|state| state.terminated);
+            if let Some(terminated) = terminated {
+                return Ok(terminated.message.unwrap_or_default());
+            }
+        }
+    }
+
+    Err(anyhow::anyhow!(
+        "container of pod {} never terminated",
+        pod_name
+    ))
+}
+
+/// Waits for the pod to be gone from the API server.
+pub async fn wait_for_pod_deleted(
+    client: kube::Client,
+    pod_name: &str,
+    namespace: &str,
+) -> anyhow::Result<()> {
+    let api: Api<Pod> = Api::namespaced(client, namespace);
+    let inf = watcher(
+        api,
+        ListParams::default()
+            .fields(&format!("metadata.name={}", pod_name))
+            .timeout(30),
+    );
+
+    let mut watcher = inf.boxed();
+    while let Some(event) = watcher.try_next().await? {
+        if let Event::Deleted(_) = event {
+            return Ok(());
+        }
+    }
+
+    Err(anyhow::anyhow!("pod {} was never deleted", pod_name))
+}
+
+/// Waits for the pod's status message to mention `needle`, returning the message.
+pub async fn wait_for_pod_message(
+    client: kube::Client,
+    pod_name: &str,
+    namespace: &str,
+    needle: &str,
+) -> anyhow::Result<String> {
+    let api: Api<Pod> = Api::namespaced(client, namespace);
+    let inf = watcher(
+        api,
+        ListParams::default()
+            .fields(&format!("metadata.name={}", pod_name))
+            .timeout(30),
+    );
+
+    let mut watcher = inf.boxed();
+    while let Some(event) = watcher.try_next().await? {
+        if let Event::Applied(o) = event {
+            if let Some(message) = o.status.and_then(|s| s.message) {
+                if message.contains(needle) {
+                    return Ok(message);
+                }
+            }
+        }
+    }
+
+    Err(anyhow::anyhow!(
+        "pod {} status never mentioned {}",
+        pod_name,
+        needle
+    ))
+}